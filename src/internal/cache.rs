@@ -31,6 +31,8 @@ pub struct Cache {
     #[serde(skip_serializing_if = "entry_expired_option")]
     pub omni_path_updates: Option<OmniPathUpdates>,
     #[serde(skip_serializing_if = "entry_empty_option")]
+    pub system_package_operation: Option<SystemPackageOperation>,
+    #[serde(skip_serializing_if = "entry_empty_option")]
     pub trusted_repositories: Option<TrustedRepositories>,
     #[serde(skip_serializing_if = "entry_empty_option")]
     pub up_environments: Option<UpEnvironments>,
@@ -50,6 +52,7 @@ impl Cache {
             asdf_operation: None,
             homebrew_operation: None,
             omni_path_updates: None,
+            system_package_operation: None,
             trusted_repositories: None,
             up_environments: None,
         }
@@ -163,6 +166,32 @@ pub struct HomebrewTapped {
     pub required_by: Vec<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SystemPackageOperation {
+    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    pub installed: Vec<SystemPackageInstalled>,
+    #[serde(default = "set_origin_of_time", with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+impl Empty for SystemPackageOperation {
+    fn is_empty(&self) -> bool {
+        self.installed.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SystemPackageInstalled {
+    pub manager: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default = "set_false", skip_serializing_if = "is_false")]
+    pub installed: bool,
+    #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+    pub required_by: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AsdfOperation {
     #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
@@ -276,6 +305,10 @@ pub struct UpEnvironment {
     pub versions: Vec<UpVersion>,
     #[serde(default = "HashMap::new", skip_serializing_if = "HashMap::is_empty")]
     pub env_vars: HashMap<String, String>,
+    #[serde(default = "HashMap::new", skip_serializing_if = "HashMap::is_empty")]
+    pub hashes: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
 }
 
 impl UpEnvironment {
@@ -283,6 +316,8 @@ impl UpEnvironment {
         Self {
             versions: Vec::new(),
             env_vars: HashMap::new(),
+            hashes: HashMap::new(),
+            path: String::new(),
         }
     }
 
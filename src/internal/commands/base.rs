@@ -1,5 +1,8 @@
 use crate::internal::commands::builtin::CdCommand;
 use crate::internal::commands::builtin::CloneCommand;
+use crate::internal::commands::builtin::EnvCommand;
+use crate::internal::commands::builtin::EnvExportCommand;
+use crate::internal::commands::builtin::ExecCommand;
 use crate::internal::commands::builtin::HelpCommand;
 use crate::internal::commands::builtin::HookCommand;
 use crate::internal::commands::builtin::ScopeCommand;
@@ -18,6 +21,9 @@ use crate::internal::user_interface::StringColor;
 pub enum Command {
     BuiltinCd(CdCommand),
     BuiltinClone(CloneCommand),
+    BuiltinEnv(EnvCommand),
+    BuiltinEnvExport(EnvExportCommand),
+    BuiltinExec(ExecCommand),
     BuiltinHelp(HelpCommand),
     BuiltinHook(HookCommand),
     BuiltinScope(ScopeCommand),
@@ -34,6 +40,9 @@ impl Command {
         match self {
             Command::BuiltinCd(command) => command.name(),
             Command::BuiltinClone(command) => command.name(),
+            Command::BuiltinEnv(command) => command.name(),
+            Command::BuiltinEnvExport(command) => command.name(),
+            Command::BuiltinExec(command) => command.name(),
             Command::BuiltinHelp(command) => command.name(),
             Command::BuiltinHook(command) => command.name(),
             Command::BuiltinScope(command) => command.name(),
@@ -54,6 +63,9 @@ impl Command {
         match self {
             Command::BuiltinCd(command) => command.aliases(),
             Command::BuiltinClone(command) => command.aliases(),
+            Command::BuiltinEnv(command) => command.aliases(),
+            Command::BuiltinEnvExport(command) => command.aliases(),
+            Command::BuiltinExec(command) => command.aliases(),
             Command::BuiltinHelp(command) => command.aliases(),
             Command::BuiltinHook(command) => command.aliases(),
             Command::BuiltinScope(command) => command.aliases(),
@@ -76,6 +88,9 @@ impl Command {
         match self {
             Command::BuiltinCd(_) => "builtin".to_string(),
             Command::BuiltinClone(_) => "builtin".to_string(),
+            Command::BuiltinEnv(_) => "builtin".to_string(),
+            Command::BuiltinEnvExport(_) => "builtin".to_string(),
+            Command::BuiltinExec(_) => "builtin".to_string(),
             Command::BuiltinHelp(_) => "builtin".to_string(),
             Command::BuiltinHook(_) => "builtin".to_string(),
             Command::BuiltinScope(_) => "builtin".to_string(),
@@ -125,6 +140,9 @@ impl Command {
         match self {
             Command::BuiltinCd(command) => command.syntax(),
             Command::BuiltinClone(command) => command.syntax(),
+            Command::BuiltinEnv(command) => command.syntax(),
+            Command::BuiltinEnvExport(command) => command.syntax(),
+            Command::BuiltinExec(command) => command.syntax(),
             Command::BuiltinHelp(command) => command.syntax(),
             Command::BuiltinHook(command) => command.syntax(),
             Command::BuiltinScope(command) => command.syntax(),
@@ -141,6 +159,9 @@ impl Command {
         match self {
             Command::BuiltinCd(command) => command.category(),
             Command::BuiltinClone(command) => command.category(),
+            Command::BuiltinEnv(command) => command.category(),
+            Command::BuiltinEnvExport(command) => command.category(),
+            Command::BuiltinExec(command) => command.category(),
             Command::BuiltinHelp(command) => command.category(),
             Command::BuiltinHook(command) => command.category(),
             Command::BuiltinScope(command) => command.category(),
@@ -157,6 +178,9 @@ impl Command {
         let help: Option<String> = match self {
             Command::BuiltinCd(command) => command.help(),
             Command::BuiltinClone(command) => command.help(),
+            Command::BuiltinEnv(command) => command.help(),
+            Command::BuiltinEnvExport(command) => command.help(),
+            Command::BuiltinExec(command) => command.help(),
             Command::BuiltinHelp(command) => command.help(),
             Command::BuiltinHook(command) => command.help(),
             Command::BuiltinScope(command) => command.help(),
@@ -235,8 +259,12 @@ impl Command {
     }
 
     pub fn exec(&self, argv: Vec<String>, called_as: Option<Vec<String>>) {
-        // Load the dynamic environment for that command
-        update_dynamic_env_for_command(&self.source_dir());
+        // Load the dynamic environment for that command, unless the command
+        // needs to inspect the dynamic environment loaded by the shell
+        match self {
+            Command::BuiltinEnv(_) => {}
+            _ => update_dynamic_env_for_command(&self.source_dir()),
+        }
 
         // Set the general execution environment
         let name = if let Some(called_as) = called_as {
@@ -249,6 +277,9 @@ impl Command {
         match self {
             Command::BuiltinCd(command) => command.exec(argv),
             Command::BuiltinClone(command) => command.exec(argv),
+            Command::BuiltinEnv(command) => command.exec(argv),
+            Command::BuiltinEnvExport(command) => command.exec(argv),
+            Command::BuiltinExec(command) => command.exec(argv),
             Command::BuiltinHelp(command) => command.exec(argv),
             Command::BuiltinHook(_command) => {}
             Command::BuiltinScope(command) => command.exec(argv),
@@ -266,6 +297,9 @@ impl Command {
         match self {
             Command::BuiltinCd(command) => command.autocompletion(),
             Command::BuiltinClone(command) => command.autocompletion(),
+            Command::BuiltinEnv(command) => command.autocompletion(),
+            Command::BuiltinEnvExport(command) => command.autocompletion(),
+            Command::BuiltinExec(command) => command.autocompletion(),
             Command::BuiltinHelp(command) => command.autocompletion(),
             Command::BuiltinHook(command) => command.autocompletion(),
             Command::BuiltinScope(command) => command.autocompletion(),
@@ -282,6 +316,9 @@ impl Command {
         match self {
             Command::BuiltinCd(command) => command.autocomplete(comp_cword, argv),
             Command::BuiltinClone(command) => command.autocomplete(comp_cword, argv),
+            Command::BuiltinEnv(command) => command.autocomplete(comp_cword, argv),
+            Command::BuiltinEnvExport(command) => command.autocomplete(comp_cword, argv),
+            Command::BuiltinExec(command) => command.autocomplete(comp_cword, argv),
             Command::BuiltinHelp(command) => command.autocomplete(comp_cword, argv),
             Command::BuiltinHook(command) => command.autocomplete(comp_cword, argv),
             Command::BuiltinScope(command) => command.autocomplete(comp_cword, argv),
@@ -2,10 +2,10 @@ use std::process::exit;
 
 use clap;
 use once_cell::sync::OnceCell;
-use shell_escape::escape;
 
 use crate::internal::commands::builtin::HelpCommand;
 use crate::internal::commands::utils::omni_cmd;
+use crate::internal::commands::utils::omni_cmd_quote;
 use crate::internal::config::config;
 use crate::internal::config::CommandSyntax;
 use crate::internal::config::SyntaxOptArg;
@@ -240,7 +240,7 @@ impl CdCommand {
             exit(0);
         }
 
-        let path_escaped = escape(std::borrow::Cow::Borrowed(path_str.as_str()));
+        let path_escaped = omni_cmd_quote(&path_str);
         match omni_cmd(format!("cd {}", path_escaped).as_str()) {
             Ok(_) => {}
             Err(e) => {
@@ -258,7 +258,7 @@ impl CdCommand {
                 exit(0);
             }
 
-            let path_escaped = escape(std::borrow::Cow::Borrowed(path_str.as_str()));
+            let path_escaped = omni_cmd_quote(&path_str);
             match omni_cmd(format!("cd {}", path_escaped).as_str()) {
                 Ok(_) => {}
                 Err(e) => {
@@ -7,13 +7,13 @@ use git_url_parse::GitUrl;
 use indicatif::ProgressBar;
 use indicatif::ProgressStyle;
 use once_cell::sync::OnceCell;
-use shell_escape::escape;
 use shell_words::join as shell_join;
 use tokio::process::Command as TokioCommand;
 
 use crate::internal::commands::builtin::HelpCommand;
 use crate::internal::commands::builtin::UpCommand;
 use crate::internal::commands::utils::omni_cmd;
+use crate::internal::commands::utils::omni_cmd_quote;
 use crate::internal::config;
 use crate::internal::config::up::utils::run_command_with_handler;
 use crate::internal::config::up::utils::RunConfig;
@@ -350,7 +350,7 @@ impl CloneCommand {
         // directly cd into it
         if ENV.omni_cmd_file.is_some() {
             let path_str = clone_path.to_string_lossy();
-            let path_escaped = escape(path_str);
+            let path_escaped = omni_cmd_quote(&path_str);
             match omni_cmd(format!("cd {}", path_escaped).as_str()) {
                 Ok(_) => {}
                 Err(e) => {
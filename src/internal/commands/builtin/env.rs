@@ -0,0 +1,209 @@
+use std::process::exit;
+
+use clap;
+use once_cell::sync::OnceCell;
+
+use crate::internal::commands::builtin::HelpCommand;
+use crate::internal::config::CommandSyntax;
+use crate::internal::config::SyntaxOptArg;
+use crate::internal::dynenv::DynamicEnv;
+use crate::internal::dynenv::DynamicEnvChange;
+use crate::internal::user_interface::StringColor;
+use crate::internal::Cache;
+use crate::omni_error;
+use crate::omni_info;
+
+#[derive(Debug, Clone)]
+struct EnvCommandArgs {
+    json: bool,
+}
+
+impl EnvCommandArgs {
+    fn parse(argv: Vec<String>) -> Self {
+        let mut parse_argv = vec!["".to_string()];
+        parse_argv.extend(argv);
+
+        let matches = clap::Command::new("")
+            .disable_help_subcommand(true)
+            .disable_version_flag(true)
+            .arg(
+                clap::Arg::new("json")
+                    .long("json")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .try_get_matches_from(&parse_argv);
+
+        if let Err(err) = matches {
+            match err.kind() {
+                clap::error::ErrorKind::DisplayHelp
+                | clap::error::ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+                    HelpCommand::new().exec(vec!["env".to_string()]);
+                }
+                clap::error::ErrorKind::DisplayVersion => {
+                    unreachable!("version flag is disabled");
+                }
+                _ => {
+                    let err_str = format!("{}", err);
+                    let err_str = err_str
+                        .split('\n')
+                        .take_while(|line| !line.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let err_str = err_str.trim_start_matches("error: ");
+                    omni_error!(err_str);
+                }
+            }
+            exit(1);
+        }
+
+        let matches = matches.unwrap();
+
+        Self {
+            json: *matches.get_one::<bool>("json").unwrap_or(&false),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EnvCommand {
+    cli_args: OnceCell<EnvCommandArgs>,
+}
+
+impl EnvCommand {
+    pub fn new() -> Self {
+        Self {
+            cli_args: OnceCell::new(),
+        }
+    }
+
+    fn cli_args(&self) -> &EnvCommandArgs {
+        self.cli_args.get_or_init(|| {
+            omni_error!("command arguments not initialized");
+            exit(1);
+        })
+    }
+
+    pub fn name(&self) -> Vec<String> {
+        vec!["env".to_string()]
+    }
+
+    pub fn aliases(&self) -> Vec<Vec<String>> {
+        vec![]
+    }
+
+    pub fn help(&self) -> Option<String> {
+        Some(
+            concat!(
+                "Show the changes operated by the dynamic environment\n",
+                "\n",
+                "This decodes the dynamic environment currently loaded in the shell, and ",
+                "lists each variable that omni set, unset, or for which values were ",
+                "prepended, appended or removed, along with the value it had before and ",
+                "the feature responsible for the change (a tool version, the \x1B[3menv\x1B[0m ",
+                "configuration, a python virtual environment, etc.).",
+            )
+            .to_string(),
+        )
+    }
+
+    pub fn syntax(&self) -> Option<CommandSyntax> {
+        Some(CommandSyntax {
+            usage: None,
+            arguments: vec![],
+            options: vec![SyntaxOptArg {
+                name: "--json".to_string(),
+                desc: Some(
+                    "Output the changes in JSON format \x1B[90m(default: no)\x1B[0m".to_string(),
+                ),
+            }],
+        })
+    }
+
+    pub fn category(&self) -> Option<Vec<String>> {
+        Some(vec!["General".to_string()])
+    }
+
+    pub fn exec(&self, argv: Vec<String>) {
+        if self.cli_args.set(EnvCommandArgs::parse(argv)).is_err() {
+            unreachable!();
+        }
+
+        let mut dynenv = DynamicEnv::from_env(Cache::new());
+        let id = if dynenv.id() == 0 {
+            None
+        } else {
+            Some(dynenv.id_str())
+        };
+        let changes = dynenv.changes();
+
+        if self.cli_args().json {
+            let mut object = serde_json::Map::new();
+            object.insert(
+                "id".to_string(),
+                match &id {
+                    Some(id) => serde_json::Value::String(id.to_string()),
+                    None => serde_json::Value::Null,
+                },
+            );
+            object.insert(
+                "variables".to_string(),
+                serde_json::to_value(&changes).unwrap(),
+            );
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&serde_json::Value::Object(object)).unwrap()
+            );
+            exit(0);
+        }
+
+        if id.is_none() {
+            omni_info!("no dynamic environment loaded");
+            exit(0);
+        }
+
+        println!(
+            "{} {}",
+            "Dynamic environment".to_string().bold(),
+            format!("({})", id.unwrap()).light_black()
+        );
+        for change in changes.iter() {
+            self.print_change(change);
+        }
+
+        exit(0);
+    }
+
+    fn print_change(&self, change: &DynamicEnvChange) {
+        println!();
+        println!("  {}", change.name.bold());
+
+        for operation in change.operations.iter() {
+            let name = format!("{:<8}", operation.operation);
+            let name = match operation.operation.as_str() {
+                "unset" | "remove" => name.red(),
+                _ => name.green(),
+            };
+            let value = operation.value.clone().unwrap_or("".to_string());
+            let feature = match &operation.feature {
+                Some(feature) => format!(" ({})", feature).light_black(),
+                None => "".to_string(),
+            };
+            println!("    {} {}{}", name, value, feature);
+        }
+
+        let previous = match &change.previous {
+            Some(previous) => previous.to_string(),
+            None => "unset".to_string().italic(),
+        };
+        println!("    {} {}", "previous".to_string().light_black(), previous);
+    }
+
+    pub fn autocompletion(&self) -> bool {
+        true
+    }
+
+    pub fn autocomplete(&self, _comp_cword: usize, _argv: Vec<String>) {
+        println!("--json");
+        exit(0);
+    }
+}
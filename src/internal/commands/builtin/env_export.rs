@@ -0,0 +1,175 @@
+use std::process::exit;
+
+use clap;
+use once_cell::sync::OnceCell;
+
+use crate::internal::commands::builtin::HelpCommand;
+use crate::internal::config::CommandSyntax;
+use crate::internal::config::SyntaxOptArg;
+use crate::internal::dynenv::export_dynamic_env;
+use crate::internal::dynenv::DynamicEnvExportFormat;
+use crate::internal::user_interface::StringColor;
+use crate::omni_error;
+
+#[derive(Debug, Clone)]
+struct EnvExportCommandArgs {
+    format: DynamicEnvExportFormat,
+}
+
+impl EnvExportCommandArgs {
+    fn parse(argv: Vec<String>) -> Self {
+        let mut parse_argv = vec!["".to_string()];
+        parse_argv.extend(argv);
+
+        let matches = clap::Command::new("")
+            .disable_help_subcommand(true)
+            .disable_version_flag(true)
+            .arg(
+                clap::Arg::new("format")
+                    .short('f')
+                    .long("format")
+                    .action(clap::ArgAction::Set)
+                    .default_value("dotenv")
+                    .value_parser(clap::builder::PossibleValuesParser::new([
+                        "dotenv", "direnv", "json", "vscode",
+                    ])),
+            )
+            .try_get_matches_from(&parse_argv);
+
+        if let Err(err) = matches {
+            match err.kind() {
+                clap::error::ErrorKind::DisplayHelp
+                | clap::error::ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+                    HelpCommand::new().exec(vec!["env".to_string(), "export".to_string()]);
+                }
+                clap::error::ErrorKind::DisplayVersion => {
+                    unreachable!("version flag is disabled");
+                }
+                _ => {
+                    let err_str = format!("{}", err);
+                    let err_str = err_str
+                        .split('\n')
+                        .take_while(|line| !line.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let err_str = err_str.trim_start_matches("error: ");
+                    omni_error!(err_str);
+                }
+            }
+            exit(1);
+        }
+
+        let matches = matches.unwrap();
+
+        let format = matches
+            .get_one::<String>("format")
+            .and_then(|format| DynamicEnvExportFormat::from_str(format))
+            .unwrap_or(DynamicEnvExportFormat::Dotenv);
+
+        Self { format }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EnvExportCommand {
+    cli_args: OnceCell<EnvExportCommandArgs>,
+}
+
+impl EnvExportCommand {
+    pub fn new() -> Self {
+        Self {
+            cli_args: OnceCell::new(),
+        }
+    }
+
+    fn cli_args(&self) -> &EnvExportCommandArgs {
+        self.cli_args.get_or_init(|| {
+            omni_error!("command arguments not initialized");
+            exit(1);
+        })
+    }
+
+    pub fn name(&self) -> Vec<String> {
+        vec!["env".to_string(), "export".to_string()]
+    }
+
+    pub fn aliases(&self) -> Vec<Vec<String>> {
+        vec![]
+    }
+
+    pub fn help(&self) -> Option<String> {
+        Some(
+            concat!(
+                "Export the dynamic environment of the current directory\n",
+                "\n",
+                "This renders the changes that omni's dynamic environment operates for the ",
+                "current directory in a format that can be used by other tools, so that ",
+                "they can use the same tools versions and environment variables as the ",
+                "shell integration. Changes to lists, such as \x1B[3mPATH\x1B[0m, are expressed ",
+                "natively when the format supports it.",
+            )
+            .to_string(),
+        )
+    }
+
+    pub fn syntax(&self) -> Option<CommandSyntax> {
+        Some(CommandSyntax {
+            usage: None,
+            arguments: vec![],
+            options: vec![SyntaxOptArg {
+                name: "--format, -f".to_string(),
+                desc: Some(
+                    concat!(
+                        "The format to export the dynamic environment in; one of ",
+                        "\x1B[1mdotenv\x1B[0m (the final values of the variables), ",
+                        "\x1B[1mdirenv\x1B[0m (an .envrc using direnv's standard library), ",
+                        "\x1B[1mjson\x1B[0m (the final values and the operations) or ",
+                        "\x1B[1mvscode\x1B[0m (a map of variables that can be used in VS Code ",
+                        "settings) \x1B[90m(default: dotenv)\x1B[0m",
+                    )
+                    .to_string(),
+                ),
+            }],
+        })
+    }
+
+    pub fn category(&self) -> Option<Vec<String>> {
+        Some(vec!["General".to_string()])
+    }
+
+    pub fn exec(&self, argv: Vec<String>) {
+        if self
+            .cli_args
+            .set(EnvExportCommandArgs::parse(argv))
+            .is_err()
+        {
+            unreachable!();
+        }
+
+        print!(
+            "{}",
+            export_dynamic_env(self.cli_args().format.clone(), ".")
+        );
+        exit(0);
+    }
+
+    pub fn autocompletion(&self) -> bool {
+        true
+    }
+
+    pub fn autocomplete(&self, comp_cword: usize, argv: Vec<String>) {
+        if comp_cword > 0 {
+            let prev = argv.get(comp_cword - 1).map(|arg| arg.as_str());
+            if prev == Some("--format") || prev == Some("-f") {
+                println!("dotenv");
+                println!("direnv");
+                println!("json");
+                println!("vscode");
+                exit(0);
+            }
+        }
+
+        println!("--format");
+        exit(0);
+    }
+}
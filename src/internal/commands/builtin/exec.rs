@@ -0,0 +1,184 @@
+use std::os::unix::process::CommandExt;
+use std::process::exit;
+use std::process::Command as ProcessCommand;
+
+use clap;
+use once_cell::sync::OnceCell;
+
+use crate::internal::commands::builtin::HelpCommand;
+use crate::internal::commands::builtin::ScopeCommand;
+use crate::internal::config::CommandSyntax;
+use crate::internal::config::SyntaxOptArg;
+use crate::internal::dynenv::update_dynamic_env_for_command;
+use crate::internal::user_interface::StringColor;
+use crate::omni_error;
+
+#[derive(Debug, Clone)]
+struct ExecCommandArgs {
+    repo: Option<String>,
+    command: Vec<String>,
+}
+
+impl ExecCommandArgs {
+    fn parse(argv: Vec<String>) -> Self {
+        let mut parse_argv = vec!["".to_string()];
+        parse_argv.extend(argv);
+
+        let matches = clap::Command::new("")
+            .disable_help_subcommand(true)
+            .disable_version_flag(true)
+            .arg(
+                clap::Arg::new("repo")
+                    .short('r')
+                    .long("repo")
+                    .action(clap::ArgAction::Set),
+            )
+            .arg(
+                clap::Arg::new("command")
+                    .action(clap::ArgAction::Append)
+                    .num_args(1..)
+                    .allow_hyphen_values(true)
+                    .trailing_var_arg(true),
+            )
+            .try_get_matches_from(&parse_argv);
+
+        if let Err(err) = matches {
+            match err.kind() {
+                clap::error::ErrorKind::DisplayHelp
+                | clap::error::ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+                    HelpCommand::new().exec(vec!["exec".to_string()]);
+                }
+                clap::error::ErrorKind::DisplayVersion => {
+                    unreachable!("version flag is disabled");
+                }
+                _ => {
+                    let err_str = format!("{}", err);
+                    let err_str = err_str
+                        .split('\n')
+                        .take_while(|line| !line.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let err_str = err_str.trim_start_matches("error: ");
+                    omni_error!(err_str);
+                }
+            }
+            exit(1);
+        }
+
+        let matches = matches.unwrap();
+
+        let repo = matches
+            .get_one::<String>("repo")
+            .map(|repo| repo.to_string());
+
+        let command: Vec<String> = matches
+            .get_many::<String>("command")
+            .map(|args| args.map(|arg| arg.to_string()).collect())
+            .unwrap_or(vec![]);
+        if command.is_empty() {
+            omni_error!("no command specified");
+            exit(1);
+        }
+
+        Self { repo, command }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecCommand {
+    cli_args: OnceCell<ExecCommandArgs>,
+}
+
+impl ExecCommand {
+    pub fn new() -> Self {
+        Self {
+            cli_args: OnceCell::new(),
+        }
+    }
+
+    fn cli_args(&self) -> &ExecCommandArgs {
+        self.cli_args.get_or_init(|| {
+            omni_error!("command arguments not initialized");
+            exit(1);
+        })
+    }
+
+    pub fn name(&self) -> Vec<String> {
+        vec!["exec".to_string()]
+    }
+
+    pub fn aliases(&self) -> Vec<Vec<String>> {
+        vec![]
+    }
+
+    pub fn help(&self) -> Option<String> {
+        Some(
+            concat!(
+                "Runs a command in the dynamic environment of a repository\n",
+                "\n",
+                "The command is executed with the same environment that omni's shell ",
+                "integration would provide when in the repository directory (tools ",
+                "versions, environment variables, etc.), without requiring the shell ",
+                "hook to be loaded. This is useful for scripts, editors or cron jobs.",
+            )
+            .to_string(),
+        )
+    }
+
+    pub fn syntax(&self) -> Option<CommandSyntax> {
+        Some(CommandSyntax {
+            usage: None,
+            arguments: vec![SyntaxOptArg {
+                name: "command".to_string(),
+                desc: Some(
+                    "The command to run, followed by its arguments; use \x1B[3m--\x1B[0m to separate the command from omni's options if needed."
+                        .to_string(),
+                ),
+            }],
+            options: vec![SyntaxOptArg {
+                name: "--repo, -r".to_string(),
+                desc: Some("The repository to run the command in the context of; this can be a path, or a repository name in the format <org>/<repo> or just <repo>, in which case the repository will be searched for in all the organizations. Defaults to the current directory.".to_string()),
+            }],
+        })
+    }
+
+    pub fn category(&self) -> Option<Vec<String>> {
+        Some(vec!["General".to_string()])
+    }
+
+    pub fn exec(&self, argv: Vec<String>) {
+        if self.cli_args.set(ExecCommandArgs::parse(argv)).is_err() {
+            unreachable!();
+        }
+
+        if let Some(repo) = &self.cli_args().repo {
+            ScopeCommand::new().switch_scope(repo, false);
+
+            // Load the dynamic environment of the repository we switched to
+            update_dynamic_env_for_command(".");
+        }
+
+        // Those are only relevant for omni's shell integration and
+        // commands, they should not leak to the executed command
+        std::env::remove_var("OMNI_CMD_FILE");
+        std::env::remove_var("OMNI_SUBCOMMAND");
+
+        let argv = self.cli_args().command.clone();
+        let mut command = ProcessCommand::new(&argv[0]);
+        command.args(&argv[1..]);
+        let err = command.exec();
+
+        omni_error!(format!(
+            "{}: {}",
+            argv[0].clone().yellow(),
+            format!("{}", err).red()
+        ));
+        exit(1);
+    }
+
+    pub fn autocompletion(&self) -> bool {
+        false
+    }
+
+    pub fn autocomplete(&self, _comp_cword: usize, _argv: Vec<String>) {}
+}
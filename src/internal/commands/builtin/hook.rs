@@ -25,15 +25,23 @@ impl HookCommand {
             "\n",
             "The \x1B[1m\x1B[4minit\x1B[0m hook will provide you with the command to run to ",
             "initialize omni in your shell. You can specify which shell you wish to load it ",
-            "for by specifying either one of \x1B[1mzsh\x1B[0m, \x1B[1mbash\x1B[0m, or ",
-            "\x1B[1mfish\x1B[0m as optional parameter. If no argument is specified, the login ",
-            "shell, as provided by the \x1B[3mSHELL\x1B[0m environment variable, will be used. ",
-            "You can load omni in your shell by using \x1B[1meval \"$(omni hook init YOURSHELL)",
-            "\"\x1B[0m for bash or zsh, or \x1B[1momni hook init fish | source\x1B[0m for fish.\n",
+            "for by specifying either one of \x1B[1mzsh\x1B[0m, \x1B[1mbash\x1B[0m, ",
+            "\x1B[1mfish\x1B[0m, \x1B[1mnu\x1B[0m or \x1B[1mpwsh\x1B[0m as optional parameter. ",
+            "If no argument is specified, the login shell, as provided by the \x1B[3mSHELL\x1B[0m ",
+            "environment variable, will be used. You can load omni in your shell by using ",
+            "\x1B[1meval \"$(omni hook init YOURSHELL)\"\x1B[0m for bash or zsh, ",
+            "\x1B[1momni hook init fish | source\x1B[0m for fish, or ",
+            "\x1B[1momni hook init pwsh | Out-String | Invoke-Expression\x1B[0m for pwsh. ",
+            "For nu, save the output to a file and \x1B[1msource\x1B[0m it from your ",
+            "configuration.\n",
             "\n",
             "The \x1B[1m\x1B[4menv\x1B[0m hook is called during your shell prompt to set the ",
             "dynamic environment required for \x1B[3momni up\x1B[0m-ed repositories.\n",
             "\n",
+            "The \x1B[1m\x1B[4mshim\x1B[0m hook is called by the shims generated during ",
+            "\x1B[3momni up\x1B[0m, to run the requested binary in the dynamic environment ",
+            "of the current directory.\n",
+            "\n",
             "The \x1B[1m\x1B[4muuid\x1B[0m hook provides and alternative to \x1B[3muuidgen\x1B[0m, ",
             "in case it is not installed, so that omni can work without extra dependencies..",
         ).to_string())
@@ -65,6 +73,7 @@ impl HookCommand {
         if comp_cword == 0 {
             println!("env");
             println!("init");
+            println!("shim");
             println!("uuid");
         }
         exit(0);
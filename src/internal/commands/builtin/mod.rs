@@ -4,6 +4,15 @@ pub use cd::CdCommand;
 pub mod clone;
 pub use clone::CloneCommand;
 
+pub mod env;
+pub use env::EnvCommand;
+
+pub mod env_export;
+pub use env_export::EnvExportCommand;
+
+pub mod exec;
+pub use exec::ExecCommand;
+
 pub mod help;
 pub use help::HelpCommand;
 
@@ -253,7 +253,7 @@ impl ScopeCommand {
         }
     }
 
-    fn switch_scope(&self, repo: &str, silent_failure: bool) {
+    pub fn switch_scope(&self, repo: &str, silent_failure: bool) {
         if let Ok(repo_path) = std::fs::canonicalize(repo) {
             if let Err(err) = std::env::set_current_dir(&repo_path) {
                 if !silent_failure {
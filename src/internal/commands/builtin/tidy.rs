@@ -14,18 +14,22 @@ use walkdir::WalkDir;
 use crate::internal::commands::builtin::HelpCommand;
 use crate::internal::config::config;
 use crate::internal::config::global_config_loader;
+use crate::internal::config::up::gc::format_size;
+use crate::internal::config::up::UpGc;
 use crate::internal::config::CommandSyntax;
 use crate::internal::config::ConfigSource;
 use crate::internal::config::SyntaxOptArg;
 use crate::internal::git::format_path;
 use crate::internal::git::safe_git_url_parse;
 use crate::internal::git_env;
+use crate::internal::shims::update_shims;
 use crate::internal::user_interface::StringColor;
 use crate::internal::ConfigLoader;
 use crate::internal::ENV;
 use crate::internal::ORG_LOADER;
 use crate::omni_error;
 use crate::omni_info;
+use crate::omni_warning;
 
 #[derive(Debug, Clone)]
 struct TidyCommandArgs {
@@ -33,6 +37,7 @@ struct TidyCommandArgs {
     search_paths: HashSet<String>,
     up_all: bool,
     up_args: Vec<String>,
+    tools: bool,
 }
 
 impl TidyCommandArgs {
@@ -60,6 +65,11 @@ impl TidyCommandArgs {
                     .long("up-all")
                     .action(clap::ArgAction::SetTrue),
             )
+            .arg(
+                clap::Arg::new("tools")
+                    .long("tools")
+                    .action(clap::ArgAction::SetTrue),
+            )
             .arg(
                 clap::Arg::new("up-args")
                     .action(clap::ArgAction::Append)
@@ -116,6 +126,7 @@ impl TidyCommandArgs {
             search_paths: search_paths,
             up_all: *matches.get_one::<bool>("up-all").unwrap_or(&false),
             up_args: up_args,
+            tools: *matches.get_one::<bool>("tools").unwrap_or(&false),
         }
     }
 }
@@ -156,7 +167,12 @@ impl TidyCommand {
                 "path to the path they should be at if they had been cloned using \x1B[3momni ",
                 "clone\x1B[0m. This is useful if you have a bunch of repositories that you have ",
                 "cloned manually, and you want to start using \x1B[3momni\x1B[0m, or if you changed ",
-                "your mind on the repo path format you wish to use.",
+                "your mind on the repo path format you wish to use.\n",
+                "\n",
+                "With \x1B[3m--tools\x1B[0m, this will instead offer to remove the tool versions, ",
+                "python venvs and homebrew packages installed by \x1B[3momni up\x1B[0m that are ",
+                "not used by any repository anymore, forgetting about the repositories that do ",
+                "not exist anymore.",
             )
             .to_string(),
         )
@@ -196,6 +212,17 @@ impl TidyCommand {
                         .to_string(),
                     ),
                 },
+                SyntaxOptArg {
+                    name: "--tools".to_string(),
+                    desc: Some(
+                        concat!(
+                            "Remove the tools installed by \x1B[3momni up\x1B[0m that are ",
+                            "not used by any repository anymore, instead of organizing ",
+                            "repositories",
+                        )
+                        .to_string(),
+                    ),
+                },
             ],
         })
     }
@@ -209,6 +236,10 @@ impl TidyCommand {
             unreachable!();
         }
 
+        if self.cli_args().tools {
+            self.tidy_tools();
+        }
+
         let all_repositories = self.list_repositories();
 
         // Filter the repositories that are already organized
@@ -362,11 +393,103 @@ impl TidyCommand {
         println!("-y");
         println!("--yes");
         println!("--up-all");
+        println!("--tools");
         println!("-h");
         println!("--help");
         exit(0);
     }
 
+    fn tidy_tools(&self) {
+        let spinner = if ENV.interactive_shell {
+            let spinner = ProgressBar::new_spinner();
+            spinner.set_style(
+                ProgressStyle::default_spinner()
+                    .template("{spinner:.green} {msg:.green}")
+                    .unwrap(),
+            );
+            spinner.set_message("Searching unused tools...");
+            spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+            Some(spinner)
+        } else {
+            None
+        };
+
+        let gc = UpGc::new();
+
+        spinner.map(|s| s.finish_and_clear());
+
+        if gc.is_empty() {
+            omni_info!("No unused tools to clean up! \u{1F389}"); // party popper emoji code
+            exit(0);
+        }
+
+        omni_info!(format!(
+            "Found {} unused resource{} ({}) and {} stale environment{}:",
+            format!("{}", gc.items.len()).underline(),
+            if gc.items.len() == 1 { "" } else { "s" },
+            format_size(gc.size()),
+            format!("{}", gc.stale_environments.len()).underline(),
+            if gc.stale_environments.len() == 1 {
+                ""
+            } else {
+                "s"
+            },
+        ));
+        gc.print();
+
+        if !self.cli_args().yes {
+            if !ENV.interactive_shell {
+                omni_info!(format!(
+                    "use {} to clean them up",
+                    "--yes".to_string().light_blue()
+                ));
+                exit(0);
+            }
+
+            let question = requestty::Question::confirm("confirm_tidy_tools")
+                .ask_if_answered(true)
+                .on_esc(requestty::OnEsc::Terminate)
+                .message(format!(
+                    "{} {}",
+                    "omni:".to_string().light_cyan(),
+                    "Do you want to clean them up?",
+                ))
+                .default(false)
+                .build();
+
+            match requestty::prompt_one(question) {
+                Ok(requestty::Answer::Bool(true)) => {}
+                Ok(_) => {
+                    omni_info!("Nothing to do! \u{1F971}"); // yawning face emoji
+                    exit(0);
+                }
+                Err(err) => {
+                    println!("{}", format!("[✘] {:?}", err).red());
+                    exit(0);
+                }
+            }
+        }
+
+        let result = gc.apply();
+
+        // The removed tools might have provided binaries for which
+        // shims were generated, which are not needed anymore
+        if let Err(err) = update_shims() {
+            omni_warning!(format!("failed to update shims: {}", err));
+        }
+
+        match result {
+            Ok(freed) => {
+                omni_info!(format!("done! {} freed", format_size(freed)).light_green());
+                exit(0);
+            }
+            Err(err) => {
+                omni_error!(format!("issue while cleaning up: {}", err));
+                exit(1);
+            }
+        }
+    }
+
     fn list_repositories(&self) -> Vec<TidyGitRepo> {
         // Prepare a spinner for the research
         let spinner = if ENV.interactive_shell {
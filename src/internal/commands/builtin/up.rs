@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::hash::Hash;
 use std::hash::Hasher;
+use std::path::Path;
 use std::path::PathBuf;
 use std::process::exit;
 use std::str::FromStr;
@@ -23,21 +24,34 @@ use crate::internal::commands::Command;
 use crate::internal::config::config;
 use crate::internal::config::config_loader;
 use crate::internal::config::flush_config;
+use crate::internal::config::up::detect::detect_up_config;
+use crate::internal::config::up::lock::load_lock;
+use crate::internal::config::up::lock::write_lock;
 use crate::internal::config::up::run_progress;
+use crate::internal::config::up::transaction::begin_transaction;
+use crate::internal::config::up::transaction::commit_transaction;
+use crate::internal::config::up::transaction::rollback_transaction;
+use crate::internal::config::up::utils::set_up_env_path;
 use crate::internal::config::up::utils::PrintProgressHandler;
 use crate::internal::config::up::utils::RunConfig;
 use crate::internal::config::up::ProgressHandler;
 use crate::internal::config::up::SpinnerProgressHandler;
+use crate::internal::config::up::UpCheck;
+use crate::internal::config::up::UpCheckStatus;
 use crate::internal::config::up::UpConfig;
+use crate::internal::config::up::UpConfigTool;
+use crate::internal::config::up::UpStepSelection;
 use crate::internal::config::CommandSyntax;
 use crate::internal::config::ConfigExtendStrategy;
 use crate::internal::config::ConfigLoader;
+use crate::internal::config::ConfigSource;
 use crate::internal::config::ConfigValue;
 use crate::internal::config::SyntaxOptArg;
 use crate::internal::git::format_path;
 use crate::internal::git::safe_git_url_parse;
 use crate::internal::git::ORG_LOADER;
 use crate::internal::git_env;
+use crate::internal::shims::update_shims;
 use crate::internal::user_interface::StringColor;
 use crate::internal::workdir;
 use crate::internal::workdir_or_init;
@@ -49,8 +63,17 @@ use crate::omni_warning;
 
 #[derive(Debug, Clone)]
 struct UpCommandArgs {
+    check: bool,
     clone_suggested: UpCommandArgsCloneSuggestedOptions,
+    dry_run: bool,
+    dump_brewfile: bool,
+    force: bool,
+    json: bool,
+    only: Vec<String>,
+    skip: Vec<String>,
+    transactional: bool,
     trust: UpCommandArgsTrustOptions,
+    update_lock: bool,
     update_repository: bool,
     update_user_config: UpCommandArgsUpdateUserConfigOptions,
 }
@@ -68,6 +91,11 @@ impl UpCommandArgs {
                     .long("bootstrap")
                     .action(clap::ArgAction::SetTrue),
             )
+            .arg(
+                clap::Arg::new("check")
+                    .long("check")
+                    .action(clap::ArgAction::SetTrue),
+            )
             .arg(
                 clap::Arg::new("clone-suggested")
                     .long("clone-suggested")
@@ -78,6 +106,45 @@ impl UpCommandArgs {
                         "yes", "ask", "no",
                     ])),
             )
+            .arg(
+                clap::Arg::new("dry-run")
+                    .long("dry-run")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(
+                clap::Arg::new("dump-brewfile")
+                    .long("dump-brewfile")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(
+                clap::Arg::new("force")
+                    .long("force")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(
+                clap::Arg::new("json")
+                    .long("json")
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .arg(
+                clap::Arg::new("only")
+                    .long("only")
+                    .num_args(1)
+                    .value_delimiter(',')
+                    .action(clap::ArgAction::Append),
+            )
+            .arg(
+                clap::Arg::new("skip")
+                    .long("skip")
+                    .num_args(1)
+                    .value_delimiter(',')
+                    .action(clap::ArgAction::Append),
+            )
+            .arg(
+                clap::Arg::new("transactional")
+                    .long("transactional")
+                    .action(clap::ArgAction::SetTrue),
+            )
             .arg(
                 clap::Arg::new("trust")
                     .long("trust")
@@ -88,6 +155,11 @@ impl UpCommandArgs {
                         "always", "yes", "no",
                     ])),
             )
+            .arg(
+                clap::Arg::new("update-lock")
+                    .long("update-lock")
+                    .action(clap::ArgAction::SetTrue),
+            )
             .arg(
                 clap::Arg::new("update-repository")
                     .long("update-repository")
@@ -166,8 +238,23 @@ impl UpCommandArgs {
             };
 
         Self {
+            check: *matches.get_one::<bool>("check").unwrap_or(&false),
             clone_suggested: clone_suggested,
+            dry_run: *matches.get_one::<bool>("dry-run").unwrap_or(&false),
+            dump_brewfile: *matches.get_one::<bool>("dump-brewfile").unwrap_or(&false),
+            force: *matches.get_one::<bool>("force").unwrap_or(&false),
+            json: *matches.get_one::<bool>("json").unwrap_or(&false),
+            only: matches
+                .get_many::<String>("only")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default(),
+            skip: matches
+                .get_many::<String>("skip")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default(),
+            transactional: *matches.get_one::<bool>("transactional").unwrap_or(&false),
             trust: trust,
+            update_lock: *matches.get_one::<bool>("update-lock").unwrap_or(&false),
             update_repository: *matches
                 .get_one::<bool>("update-repository")
                 .unwrap_or(&false),
@@ -295,6 +382,17 @@ impl UpCommand {
                         .to_string(),
                     ),
                 },
+                SyntaxOptArg {
+                    name: "--check".to_string(),
+                    desc: Some(
+                        concat!(
+                            "Check whether each step is already met on the system, without ",
+                            "making any change; exits with a non-zero status if any step is not ",
+                            "met",
+                        )
+                        .to_string(),
+                    ),
+                },
                 SyntaxOptArg {
                     name: "--clone-suggested".to_string(),
                     desc: Some(
@@ -306,6 +404,82 @@ impl UpCommand {
                         .to_string(),
                     ),
                 },
+                SyntaxOptArg {
+                    name: "--dry-run".to_string(),
+                    desc: Some(
+                        concat!(
+                            "Show the changes that would be made to the system, without making ",
+                            "them; versions are resolved and installed resources are checked, ",
+                            "and the \x1B[3mmet?\x1B[0m commands of custom steps are only ",
+                            "evaluated if the repository is trusted",
+                        )
+                        .to_string(),
+                    ),
+                },
+                SyntaxOptArg {
+                    name: "--dump-brewfile".to_string(),
+                    desc: Some(
+                        concat!(
+                            "Print the taps and formulae of the \x1B[3mhomebrew\x1B[0m steps in ",
+                            "the Brewfile format, without running any step",
+                        )
+                        .to_string(),
+                    ),
+                },
+                SyntaxOptArg {
+                    name: "--force".to_string(),
+                    desc: Some(
+                        concat!(
+                            "Run all the steps, even the ones for which the inputs have not ",
+                            "changed since their last successful run \x1B[90m(default: no)\x1B[0m",
+                        )
+                        .to_string(),
+                    ),
+                },
+                SyntaxOptArg {
+                    name: "--json".to_string(),
+                    desc: Some(
+                        concat!(
+                            "With \x1B[1m--check\x1B[0m, print the report as JSON on the ",
+                            "standard output",
+                        )
+                        .to_string(),
+                    ),
+                },
+                SyntaxOptArg {
+                    name: "--only".to_string(),
+                    desc: Some(
+                        concat!(
+                            "Only run the given steps, selected by type (e.g. ",
+                            "\x1B[3mbundler\x1B[0m), by \x1B[3mname\x1B[0m for custom steps, or ",
+                            "by position in the configuration; can be repeated or given a ",
+                            "comma-separated list",
+                        )
+                        .to_string(),
+                    ),
+                },
+                SyntaxOptArg {
+                    name: "--skip".to_string(),
+                    desc: Some(
+                        concat!(
+                            "Do not run the given steps, selected the same way as for ",
+                            "\x1B[1m--only\x1B[0m; the steps that are not run keep what they ",
+                            "had set up",
+                        )
+                        .to_string(),
+                    ),
+                },
+                SyntaxOptArg {
+                    name: "--transactional".to_string(),
+                    desc: Some(
+                        concat!(
+                            "Record the changes made by the steps and, if a step fails, undo ",
+                            "them in reverse order and restore the repository environment as it ",
+                            "was before the run \x1B[90m(default: no)\x1B[0m",
+                        )
+                        .to_string(),
+                    ),
+                },
                 SyntaxOptArg {
                     name: "--trust".to_string(),
                     desc: Some(
@@ -313,6 +487,17 @@ impl UpCommand {
                             .to_string(),
                     ),
                 },
+                SyntaxOptArg {
+                    name: "--update-lock".to_string(),
+                    desc: Some(
+                        concat!(
+                            "Resolve all the versions again instead of using the ones recorded ",
+                            "in the \x1B[3momni.lock\x1B[0m file of the repository, and rewrite ",
+                            "the lock file with the new versions \x1B[90m(default: no)\x1B[0m",
+                        )
+                        .to_string(),
+                    ),
+                },
                 SyntaxOptArg {
                     name: "--update-repository".to_string(),
                     desc: Some(
@@ -365,14 +550,18 @@ impl UpCommand {
             }
         }
 
-        if !self.update_repository() {
+        if !self.cli_args().dry_run
+            && !self.cli_args().check
+            && !self.cli_args().dump_brewfile
+            && !self.update_repository()
+        {
             // Nothing more to do if we tried updating and the
             // repo was already up to date
             exit(0);
         }
 
         let config = config(".");
-        let up_config = config.up.clone();
+        let mut up_config = config.up.clone();
         if let Some(up_config) = up_config.clone() {
             if up_config.has_errors() {
                 for error in up_config.errors() {
@@ -381,6 +570,55 @@ impl UpCommand {
             }
         }
 
+        // Without any up configuration, try to infer one from the
+        // files found in the work directory; checking and dumping the
+        // Brewfile only consider the configuration of the repository
+        if self.is_up()
+            && up_config.is_none()
+            && wd.in_workdir()
+            && !self.cli_args().check
+            && !self.cli_args().dump_brewfile
+        {
+            up_config = self.detect_up_config();
+        }
+
+        // Make sure that all the selected steps exist, to avoid silently
+        // running nothing because of a typo
+        let selection = self.selection();
+        if let Some(up_config) = &up_config {
+            let unmatched = selection.unmatched(&up_config.steps);
+            if !unmatched.is_empty() {
+                omni_error!(format!(
+                    "no step matching {} in the {} configuration",
+                    unmatched.join(", ").light_yellow(),
+                    "up".to_string().italic(),
+                ));
+                exit(1);
+            }
+        }
+
+        // Load the lock file so that the versions it contains are
+        // used when resolving the versions of the tools
+        if self.is_up() {
+            if let Err(err) = load_lock(self.cli_args().update_lock) {
+                omni_error!(format!("{}", err));
+                exit(1);
+            }
+        }
+
+        if self.cli_args().check {
+            exit(self.print_check(up_config));
+        }
+
+        if self.cli_args().dump_brewfile {
+            exit(self.print_brewfile(up_config));
+        }
+
+        if self.cli_args().dry_run {
+            self.print_plan(up_config);
+            exit(0);
+        }
+
         let mut suggest_config = None;
         let config_loader = config_loader(".");
         if self.is_up() && self.should_suggest_config() {
@@ -415,7 +653,7 @@ impl UpCommand {
                 "No {} configuration found, nothing to do.",
                 "up".to_string().italic(),
             ));
-            UpConfig::clear_cache();
+            UpConfig::clear_cache(false);
             exit(0);
         }
 
@@ -435,9 +673,23 @@ impl UpCommand {
             exit(1);
         }
 
+        // When running as a transaction, start recording before touching
+        // the cache so that the up environment can be restored as it was
+        let transactional = self.is_up() && self.cli_args().transactional;
+        if transactional {
+            if let Err(err) = begin_transaction() {
+                omni_error!(format!("failed to start transaction: {}", err));
+                exit(1);
+            }
+        }
+
         // No matter what's happening after, we want a clean cache for that
-        // repository, as we're rebuilding the up environment from scratch
-        UpConfig::clear_cache();
+        // repository, as we're rebuilding the up environment from scratch;
+        // when tearing down all the steps, we also forget about the hashes
+        // of the steps. The previous environment is kept so that the steps
+        // that do not need to run again, or that are not selected, can
+        // restore what they had set up
+        let previous_env = UpConfig::clear_cache(self.is_up() || !selection.is_empty());
 
         // If there are environment variables to set, do it
         if env_vars.is_some() {
@@ -479,14 +731,39 @@ impl UpCommand {
         if has_up_config {
             let up_config = up_config.unwrap();
             if self.is_up() {
-                if let Err(err) = up_config.up() {
+                let result = up_config.up(previous_env.as_ref(), self.cli_args().force, &selection);
+                if result.is_ok() {
+                    if let Err(err) = write_lock(&up_config) {
+                        omni_warning!(format!("failed to update lock file: {}", err));
+                    }
+                }
+                if let Err(err) = result {
                     omni_error!(format!("issue while setting repo up: {}", err));
+                    if transactional {
+                        omni_info!(format!("Rolling back the changes made during this run"));
+                        if let Err(err) = rollback_transaction() {
+                            omni_error!(format!("issue while rolling back: {}", err));
+                        }
+                        exit(1);
+                    }
                 }
             } else {
-                if let Err(err) = up_config.down() {
+                if let Err(err) = up_config.down(previous_env.as_ref(), &selection) {
                     omni_error!(format!("issue while tearing repo down: {}", err));
                 }
             }
+
+            if let Err(err) = set_up_env_path() {
+                omni_warning!(format!("failed to update cache: {}", err));
+            }
+
+            if let Err(err) = update_shims() {
+                omni_warning!(format!("failed to update shims: {}", err));
+            }
+        }
+
+        if transactional {
+            commit_transaction();
         }
 
         if suggest_config.is_some() {
@@ -506,8 +783,17 @@ impl UpCommand {
 
     pub fn autocomplete(&self, _comp_cword: usize, _argv: Vec<String>) {
         println!("--bootstrap");
+        println!("--check");
         println!("--clone-suggested");
+        println!("--dry-run");
+        println!("--dump-brewfile");
+        println!("--force");
+        println!("--json");
+        println!("--only");
+        println!("--skip");
+        println!("--transactional");
         println!("--trust");
+        println!("--update-lock");
         println!("--update-repository");
         println!("--update-user-config");
         exit(0);
@@ -525,14 +811,268 @@ impl UpCommand {
         self.subcommand() == "down"
     }
 
-    fn trust(&self) -> bool {
+    fn selection(&self) -> UpStepSelection {
+        UpStepSelection::new(self.cli_args().only.clone(), self.cli_args().skip.clone())
+    }
+
+    /// Prints the changes that the command would make to the system,
+    /// without making any of them.
+    fn print_plan(&self, up_config: Option<UpConfig>) {
+        let wd = workdir(".");
+        if self.is_down() && (!wd.in_workdir() || !wd.has_id()) {
+            omni_info!(format!("Outside of a work directory, nothing to do."));
+            return;
+        }
+
+        let up_config = match up_config {
+            Some(up_config) if up_config.has_steps() => up_config,
+            _ => {
+                omni_info!(format!(
+                    "No {} configuration found, nothing to do.",
+                    "up".to_string().italic(),
+                ));
+                return;
+            }
+        };
+
+        let trusted = self.is_trusted_without_prompt();
+        let plan = if self.is_up() {
+            up_config.plan_up(trusted, self.cli_args().force, &self.selection())
+        } else {
+            up_config.plan_down(trusted, &self.selection())
+        };
+        plan.print();
+
+        let num_changes = plan.num_changes();
+        omni_info!(format!(
+            "{} would make {} change{}",
+            format!("omni {}", self.subcommand()).bold(),
+            num_changes,
+            if num_changes == 1 { "" } else { "s" },
+        ));
+    }
+
+    /// Prints whether each step of the up configuration is met on the
+    /// system, without making any change, and returns the exit code:
+    /// non-zero if any step is not met.
+    fn print_check(&self, up_config: Option<UpConfig>) -> i32 {
+        if self.is_down() {
+            omni_error!(format!(
+                "{} can only be used with {}",
+                "--check".to_string().light_yellow(),
+                "omni up".to_string().bold(),
+            ));
+            return 1;
+        }
+
+        let check = match up_config {
+            Some(up_config) if up_config.has_steps() => {
+                up_config.check(self.is_trusted_without_prompt(), &self.selection())
+            }
+            _ => UpCheck::new(),
+        };
+
+        if self.cli_args().json {
+            println!("{}", serde_json::to_string_pretty(&check).unwrap());
+        } else if check.steps.is_empty() {
+            omni_info!(format!(
+                "No {} configuration found, nothing to check.",
+                "up".to_string().italic(),
+            ));
+        } else {
+            check.print();
+
+            let num_unmet = check.num_unmet();
+            let all_checked = check
+                .steps
+                .iter()
+                .all(|step| step.status != UpCheckStatus::Unchecked);
+            if num_unmet == 0 && all_checked {
+                omni_info!(format!("All the steps are met"));
+            } else if num_unmet == 0 {
+                omni_info!(format!("All the steps that could be checked are met"));
+            } else {
+                omni_info!(format!(
+                    "{} step{} not met",
+                    num_unmet,
+                    if num_unmet == 1 { " is" } else { "s are" },
+                ));
+            }
+        }
+
+        if check.met {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Prints the taps and formulae of the selected homebrew steps in the
+    /// Brewfile format, and returns the exit code.
+    fn print_brewfile(&self, up_config: Option<UpConfig>) -> i32 {
+        let selection = self.selection();
+        let brewfiles = up_config
+            .map(|up_config| up_config.steps)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|step| selection.includes(step))
+            .filter_map(|step| match step.tool {
+                UpConfigTool::Homebrew(config) => Some(config.to_brewfile()),
+                _ => None,
+            })
+            .filter(|brewfile| !brewfile.is_empty())
+            .collect::<Vec<String>>();
+
+        if brewfiles.is_empty() {
+            omni_error!(format!(
+                "no {} step found in the {} configuration",
+                "homebrew".to_string().italic(),
+                "up".to_string().italic(),
+            ));
+            return 1;
+        }
+
+        println!("{}", brewfiles.join("\n"));
+        0
+    }
+
+    /// Returns whether the repository is trusted, without prompting;
+    /// used when nothing will be run anyway, as the trust only matters
+    /// for evaluating the `met?` commands.
+    fn is_trusted_without_prompt(&self) -> bool {
         match self.cli_args().trust {
-            UpCommandArgsTrustOptions::Always => return self.add_trust(),
-            UpCommandArgsTrustOptions::Yes => return true,
-            UpCommandArgsTrustOptions::No => return false,
-            UpCommandArgsTrustOptions::Check => {}
+            UpCommandArgsTrustOptions::Always | UpCommandArgsTrustOptions::Yes => true,
+            UpCommandArgsTrustOptions::No => false,
+            UpCommandArgsTrustOptions::Check => self.is_trusted(),
         }
+    }
 
+    /// Infers an up configuration from the files found in the work
+    /// directory, shows it, and offers to write it to the configuration
+    /// file of the repository; returns the configuration to use for
+    /// this run, if any.
+    fn detect_up_config(&self) -> Option<UpConfig> {
+        let detected = detect_up_config()?;
+        let up_config = UpConfig::from_config_value(Some(detected.clone()))?;
+
+        let yaml = detected
+            .as_yaml()
+            .lines()
+            .map(|line| format!("  {}", line))
+            .collect::<Vec<String>>()
+            .join("\n");
+        omni_info!(format!(
+            "No {} configuration found, but the following was inferred from the repository files:",
+            "up".to_string().italic(),
+        ));
+        eprintln!(
+            "  {}\n  {}",
+            "up:".to_string().light_blue(),
+            yaml.replace("\n", "\n  ")
+        );
+
+        if self.cli_args().dry_run {
+            return Some(up_config);
+        }
+
+        if !ENV.interactive_shell {
+            omni_info!(format!(
+                "Add it to {} to use it.",
+                self.repo_config_file().display().to_string().light_blue(),
+            ));
+            return None;
+        }
+
+        let config_file = self.repo_config_file();
+        let write_choice = format!("Yes, write it to {} and use it", config_file.display());
+        let choices = vec![
+            ('y', write_choice.as_str()),
+            ('o', "Only use it for this run"),
+            ('n', "No"),
+        ];
+        let question = requestty::Question::expand("use_detected_config")
+            .ask_if_answered(true)
+            .on_esc(requestty::OnEsc::Terminate)
+            .message("Do you want to use this configuration?")
+            .choices(choices)
+            .default('y')
+            .build();
+
+        match requestty::prompt_one(question) {
+            Ok(answer) => match answer {
+                requestty::Answer::ExpandItem(expanditem) => match expanditem.key {
+                    'y' => {
+                        self.write_up_config(&config_file, &detected);
+                        Some(up_config)
+                    }
+                    'o' => Some(up_config),
+                    'n' => None,
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            },
+            Err(err) => {
+                println!("{}", format!("[✘] {:?}", err).red());
+                None
+            }
+        }
+    }
+
+    /// Writes the given up configuration to the configuration file of
+    /// the repository.
+    fn write_up_config(&self, config_file: &Path, up: &ConfigValue) {
+        let result = ConfigLoader::edit_user_config_file(
+            config_file.display().to_string(),
+            |config_value| {
+                let mut mapping = serde_yaml::Mapping::new();
+                mapping.insert(
+                    serde_yaml::Value::String("up".to_string()),
+                    up.as_serde_yaml(),
+                );
+                config_value.extend(
+                    ConfigValue::from_value(
+                        ConfigSource::Null,
+                        vec![],
+                        serde_yaml::Value::Mapping(mapping),
+                    ),
+                    ConfigExtendStrategy::Default,
+                    vec![],
+                );
+                true
+            },
+        );
+
+        if let Err(err) = result {
+            omni_error!(format!(
+                "Unable to update {}: {}",
+                config_file.display(),
+                err
+            ));
+        } else {
+            omni_info!(format!(
+                "Updated {}",
+                config_file.display().to_string().light_blue()
+            ));
+        }
+    }
+
+    /// Returns the path of the configuration file of the repository,
+    /// which is `.omni.yaml` unless only `.omni/config.yaml` exists.
+    fn repo_config_file(&self) -> PathBuf {
+        let wd = workdir(".");
+        let root = PathBuf::from(wd.root().unwrap_or("."));
+
+        if !root.join(".omni.yaml").exists() && root.join(".omni").join("config.yaml").exists() {
+            return root.join(".omni").join("config.yaml");
+        }
+
+        root.join(".omni.yaml")
+    }
+
+    /// Returns whether the repository is trusted, either because it is
+    /// part of a trusted organization or because it was added to the
+    /// trusted repositories.
+    fn is_trusted(&self) -> bool {
         let git = git_env(".");
         if git.in_repo() && git.has_origin() {
             for org in ORG_LOADER.orgs() {
@@ -543,18 +1083,31 @@ impl UpCommand {
         }
 
         let workdir = workdir(".");
-        let repo_id = workdir.id();
-        if repo_id.is_some() {
+        if let Some(repo_id) = workdir.id() {
             if let Some(trusted_repos) = &CACHE.trusted_repositories {
-                if trusted_repos
-                    .repositories
-                    .contains(&repo_id.clone().unwrap())
-                {
+                if trusted_repos.repositories.contains(&repo_id) {
                     return true;
                 }
             }
         }
 
+        false
+    }
+
+    fn trust(&self) -> bool {
+        match self.cli_args().trust {
+            UpCommandArgsTrustOptions::Always => return self.add_trust(),
+            UpCommandArgsTrustOptions::Yes => return true,
+            UpCommandArgsTrustOptions::No => return false,
+            UpCommandArgsTrustOptions::Check => {}
+        }
+
+        if self.is_trusted() {
+            return true;
+        }
+
+        let repo_id = workdir(".").id();
+
         if !ENV.interactive_shell {
             return false;
         }
@@ -9,6 +9,9 @@ use strsim::normalized_damerau_levenshtein;
 use crate::internal::commands::base::Command;
 use crate::internal::commands::builtin::CdCommand;
 use crate::internal::commands::builtin::CloneCommand;
+use crate::internal::commands::builtin::EnvCommand;
+use crate::internal::commands::builtin::EnvExportCommand;
+use crate::internal::commands::builtin::ExecCommand;
 use crate::internal::commands::builtin::HelpCommand;
 use crate::internal::commands::builtin::HookCommand;
 use crate::internal::commands::builtin::ScopeCommand;
@@ -76,6 +79,9 @@ impl CommandLoader {
         // Load all builtins first
         commands.push(Command::BuiltinCd(CdCommand::new()));
         commands.push(Command::BuiltinClone(CloneCommand::new()));
+        commands.push(Command::BuiltinEnv(EnvCommand::new()));
+        commands.push(Command::BuiltinEnvExport(EnvExportCommand::new()));
+        commands.push(Command::BuiltinExec(ExecCommand::new()));
         commands.push(Command::BuiltinHelp(HelpCommand::new()));
         commands.push(Command::BuiltinHook(HookCommand::new()));
         commands.push(Command::BuiltinScope(ScopeCommand::new()));
@@ -6,6 +6,8 @@ use std::path::{Path, PathBuf};
 use path_clean::PathClean;
 use pathdiff;
 
+use crate::internal::dynenv::DynamicEnvExportMode;
+use crate::internal::env::determine_shell;
 use crate::internal::ENV;
 
 pub fn split_name(string: &str, split_on: &str) -> Vec<String> {
@@ -59,6 +61,14 @@ pub fn abs_path(path: impl AsRef<Path>) -> PathBuf {
     absolute_path
 }
 
+/// Quotes the value so that it can be used as a single argument in a
+/// command of the omni command file, for the shell omni was called from.
+pub fn omni_cmd_quote(value: &str) -> String {
+    DynamicEnvExportMode::from_shell(&determine_shell())
+        .unwrap_or(DynamicEnvExportMode::Posix)
+        .quote(value)
+}
+
 pub fn omni_cmd(cmd: &str) -> Result<(), io::Error> {
     let cmd_file = ENV
         .omni_cmd_file
@@ -5,11 +5,13 @@ use std::collections::HashSet;
 use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use lazy_static::lazy_static;
 use node_semver::Range as semverRange;
 use node_semver::Version as semverVersion;
 use once_cell::sync::OnceCell;
+use regex::Regex;
 use serde::Deserialize;
 use serde::Serialize;
 use time::OffsetDateTime;
@@ -22,7 +24,15 @@ use crate::internal::cache::Cache;
 use crate::internal::cache::UpEnvironment;
 use crate::internal::cache::UpEnvironments;
 use crate::internal::cache::UpVersion;
+use crate::internal::config::up::jvm::split_java_version;
+use crate::internal::config::up::lock::lock_asdf_plugin_commit;
+use crate::internal::config::up::lock::lock_asdf_version;
+use crate::internal::config::up::lock::locked_asdf_plugin_commit;
+use crate::internal::config::up::lock::locked_asdf_version;
+use crate::internal::config::up::plan::UpPlanItem;
 use crate::internal::config::up::tool::UpConfigTool;
+use crate::internal::config::up::transaction::record_change;
+use crate::internal::config::up::transaction::UpChange;
 use crate::internal::config::up::utils::run_progress;
 use crate::internal::config::up::utils::PrintProgressHandler;
 use crate::internal::config::up::utils::ProgressHandler;
@@ -44,6 +54,48 @@ lazy_static! {
     pub static ref ASDF_BIN: String = format!("{}/bin/asdf", *ASDF_PATH);
 }
 
+static ASDF_INSTALLED: OnceCell<()> = OnceCell::new();
+
+const NODEJS_RELEASES_URL: &str = "https://nodejs.org/dist/index.json";
+const NODEJS_RELEASES_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The tools for which `lts` can be used as version specification.
+const LTS_TOOLS: [&str; 2] = ["nodejs", "java"];
+
+lazy_static! {
+    /// The major versions of node that have long-term support releases,
+    /// according to the list of releases of node, or the reason why that
+    /// list could not be fetched.
+    static ref NODEJS_LTS_MAJORS: Result<HashSet<u64>, String> = {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(NODEJS_RELEASES_TIMEOUT)
+            .build()
+            .map_err(|err| err.to_string())?;
+        let contents = client
+            .get(NODEJS_RELEASES_URL)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|err| err.to_string())?;
+        let releases: serde_json::Value =
+            serde_json::from_str(&contents).map_err(|err| err.to_string())?;
+
+        Ok(releases
+            .as_array()
+            .ok_or("unexpected format".to_string())?
+            .iter()
+            .filter(|release| matches!(release.get("lts"), Some(lts) if lts.is_string()))
+            .filter_map(|release| release.get("version")?.as_str())
+            .filter_map(coerce_version)
+            .map(|version| version.major)
+            .collect())
+    };
+
+    static ref VERSION_REGEX: Regex =
+        Regex::new(r"^v?(?P<major>\d+)(\.(?P<minor>\d+))?(\.(?P<patch>\d+))?(?P<rest>[-+].*)?$")
+            .unwrap();
+}
+
 fn is_asdf_installed() -> bool {
     let bin_path = std::path::Path::new(&*ASDF_BIN);
     bin_path.is_file() && bin_path.metadata().unwrap().permissions().mode() & 0o111 != 0
@@ -61,6 +113,16 @@ fn install_asdf(progress_handler: Option<Box<&dyn ProgressHandler>>) -> Result<(
     let new_path_env = std::env::join_paths(new_paths).expect("Failed to join paths");
     std::env::set_var("PATH", new_path_env);
 
+    // Steps can run concurrently, so we make sure that asdf is only
+    // installed and updated once, with the other steps waiting for it
+    ASDF_INSTALLED
+        .get_or_try_init(|| install_and_update_asdf(progress_handler))
+        .map(|_| ())
+}
+
+fn install_and_update_asdf(
+    progress_handler: Option<Box<&dyn ProgressHandler>>,
+) -> Result<(), UpError> {
     if !is_asdf_installed() {
         if progress_handler.is_some() {
             progress_handler
@@ -144,6 +206,8 @@ pub struct UpConfigAsdfBase {
     actual_version: OnceCell<String>,
     #[serde(skip)]
     actual_versions: OnceCell<BTreeSet<String>>,
+    #[serde(skip)]
+    actual_dir_versions: OnceCell<BTreeMap<String, String>>,
 }
 
 impl UpConfigAsdfBase {
@@ -156,6 +220,7 @@ impl UpConfigAsdfBase {
             detect_version_funcs: vec![],
             actual_version: OnceCell::new(),
             actual_versions: OnceCell::new(),
+            actual_dir_versions: OnceCell::new(),
         }
     }
 
@@ -172,6 +237,7 @@ impl UpConfigAsdfBase {
             detect_version_funcs: vec![],
             actual_version: OnceCell::new(),
             actual_versions: OnceCell::new(),
+            actual_dir_versions: OnceCell::new(),
         }
     }
 
@@ -187,6 +253,26 @@ impl UpConfigAsdfBase {
         Self::from_config_value_with_params(tool, Some(tool_url.to_string()), config_value)
     }
 
+    /// Parses the configuration of a generic asdf step, for which the
+    /// tool to install is part of the configuration instead of being
+    /// determined by the step name.
+    pub fn from_config_value_generic(config_value: Option<&ConfigValue>) -> Option<Self> {
+        let config_value = config_value?;
+
+        if let Some(tool) = config_value.as_str() {
+            return Some(Self::from_config_value_with_params(&tool, None, None));
+        }
+
+        let tool = config_value.get_as_str("tool")?;
+        let tool_url = config_value.get_as_str("url");
+
+        Some(Self::from_config_value_with_params(
+            &tool,
+            tool_url,
+            Some(config_value),
+        ))
+    }
+
     fn from_config_value_with_params(
         tool: &str,
         tool_url: Option<String>,
@@ -203,8 +289,8 @@ impl UpConfigAsdfBase {
             } else if let Some(value) = config_value.as_integer() {
                 version = value.to_string();
             } else {
-                if let Some(value) = config_value.get("version") {
-                    version = value.as_str().unwrap().to_string();
+                if let Some(value) = config_value.get_as_str_forced("version") {
+                    version = value.to_string();
                 }
 
                 if let Some(value) = config_value.get_as_str("dir") {
@@ -225,6 +311,7 @@ impl UpConfigAsdfBase {
             detect_version_funcs: vec![],
             actual_version: OnceCell::new(),
             actual_versions: OnceCell::new(),
+            actual_dir_versions: OnceCell::new(),
         }
     }
 
@@ -367,70 +454,15 @@ impl UpConfigAsdfBase {
             return Err(err);
         }
 
-        if self.version == "auto" {
-            let mut detected_versions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
-
-            // Get the current directory
-            let current_dir = std::env::current_dir().expect("failed to get current directory");
-
-            let mut search_dirs = self.dirs.clone();
-            if search_dirs.is_empty() {
-                search_dirs.insert("".to_string());
-            }
-
-            let mut detect_version_funcs = self.detect_version_funcs.clone();
-            detect_version_funcs.push(detect_version_from_asdf_version_file);
-            detect_version_funcs.push(detect_version_from_version_file);
-
-            for search_dir in search_dirs.iter() {
-                // For safety, we remove any leading slashes from the search directory,
-                // as we only want to search in the workdir
-                let mut search_dir = search_dir.clone();
-                while search_dir.starts_with("/") {
-                    search_dir.remove(0);
-                }
-
-                // Append the search directory to the current directory, since we are
-                // at the root of the workdir
-                let search_path = current_dir.join(search_dir);
-
-                for entry in WalkDir::new(search_path).follow_links(true) {
-                    if let Ok(entry) = entry {
-                        if !entry.path().is_dir() {
-                            continue;
-                        }
+        if let Err(err) = self.pin_plugin(progress_handler.clone()) {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.error_with_message(format!("error: {}", err))
+            });
+            return Err(err);
+        }
 
-                        for detect_version_func in detect_version_funcs.iter() {
-                            if let Some(detected_version) =
-                                detect_version_func(self.tool.clone(), entry.path().to_path_buf())
-                            {
-                                let mut dir = entry
-                                    .path()
-                                    .strip_prefix(&current_dir)
-                                    .expect("failed to strip prefix")
-                                    .to_string_lossy()
-                                    .to_string();
-                                while dir.starts_with("/") {
-                                    dir.remove(0);
-                                }
-                                while dir.ends_with("/") {
-                                    dir.pop();
-                                }
-
-                                if let Some(dirs) = detected_versions.get_mut(&detected_version) {
-                                    dirs.insert(dir);
-                                } else {
-                                    let mut dirs = BTreeSet::new();
-                                    dirs.insert(dir);
-                                    detected_versions.insert(detected_version.to_string(), dirs);
-                                }
-
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
+        if self.version == "auto" {
+            let detected_versions = self.detect_versions();
 
             if detected_versions.is_empty() {
                 progress_handler.clone().map(|progress_handler| {
@@ -442,6 +474,7 @@ impl UpConfigAsdfBase {
             let mut installed_versions = Vec::new();
             let mut already_installed_versions = Vec::new();
             let mut all_versions = BTreeSet::new();
+            let mut dir_versions = BTreeMap::new();
 
             for (version, dirs) in detected_versions.iter() {
                 let asdf_base = self.new_from_auto(version, dirs.clone());
@@ -459,6 +492,9 @@ impl UpConfigAsdfBase {
 
                 let version = asdf_base.version(None).unwrap();
                 all_versions.insert(version.clone());
+                for dir in dirs.iter() {
+                    dir_versions.insert(dir.clone(), version.clone());
+                }
                 if installed.unwrap() {
                     installed_versions.push(version.clone());
                 } else {
@@ -471,6 +507,8 @@ impl UpConfigAsdfBase {
             self.actual_versions
                 .set(all_versions)
                 .expect("failed to set installed versions");
+            let _ = self.actual_dir_versions.set(dir_versions);
+            self.lock_plugin_commit();
 
             if progress_handler.is_some() {
                 let mut msgs = Vec::new();
@@ -524,6 +562,7 @@ impl UpConfigAsdfBase {
             }
 
             self.update_cache(progress_handler.clone());
+            self.lock_plugin_commit();
 
             if progress_handler.is_some() {
                 let msg = if install_version.unwrap() {
@@ -543,6 +582,211 @@ impl UpConfigAsdfBase {
         Ok(())
     }
 
+    /// Returns the changes that `up` would make, without making them; the
+    /// versions are resolved from the versions available in the plugin,
+    /// without updating the plugin first.
+    pub fn plan_up(&self) -> Vec<UpPlanItem> {
+        let mut items = Vec::new();
+
+        if !is_asdf_installed() {
+            items.push(UpPlanItem::install("asdf"));
+        }
+
+        let plugin_installed = is_asdf_installed() && self.is_plugin_installed();
+        if !plugin_installed {
+            items.push(UpPlanItem::install(&format!("{} plugin", self.tool)));
+        } else if let Some(commit) = locked_asdf_plugin_commit(&self.tool) {
+            if self.plugin_commit() != Some(commit.clone()) {
+                items.push(
+                    UpPlanItem::run(&format!("pin {} plugin", self.tool)).with_detail(&commit),
+                );
+            }
+        }
+
+        if self.version != "auto" {
+            items.push(self.plan_version(plugin_installed));
+            return items;
+        }
+
+        let detected_versions = self.detect_versions();
+        if detected_versions.is_empty() {
+            items.push(UpPlanItem::skip(&self.tool, "no version detected"));
+            return items;
+        }
+
+        let mut all_versions = BTreeSet::new();
+        let mut dir_versions = BTreeMap::new();
+        for (version, dirs) in detected_versions.iter() {
+            let asdf_base = self.new_from_auto(version, dirs.clone());
+            items.push(asdf_base.plan_version(plugin_installed));
+            if let Ok(version) = asdf_base.version(None) {
+                all_versions.insert(version.clone());
+                for dir in dirs.iter() {
+                    dir_versions.insert(dir.clone(), version.clone());
+                }
+            }
+        }
+
+        // Keep track of the versions so that the cleanup plan
+        // knows which versions are still expected
+        let _ = self.actual_versions.set(all_versions);
+        let _ = self.actual_dir_versions.set(dir_versions);
+
+        items
+    }
+
+    fn plan_version(&self, plugin_installed: bool) -> UpPlanItem {
+        let subject = format!("{} {}", self.tool, self.version);
+
+        // Setting the version right away guarantees that no further call
+        // will try to update the plugin to resolve the version
+        if let Some(version) = locked_asdf_version(&self.tool, &self.version) {
+            let _ = self.actual_version.set(version);
+        } else if !plugin_installed {
+            let _ = self.actual_version.set("".to_string());
+            return UpPlanItem::install(&subject)
+                .with_detail("version resolved once the plugin is installed");
+        }
+
+        let version = match self
+            .actual_version
+            .get_or_try_init(|| self.list_matching_version())
+        {
+            Ok(version) => version,
+            Err(err) => return UpPlanItem::skip(&subject, &err.to_string()),
+        };
+        if version.is_empty() {
+            return UpPlanItem::skip(&subject, "no matching version found");
+        }
+
+        let subject = format!("{} {}", self.tool, version);
+        if is_asdf_tool_version_installed(&self.tool, version) {
+            UpPlanItem::skip(&subject, "already installed")
+        } else if *version != self.version {
+            UpPlanItem::install(&subject).with_detail(&format!("matches {}", self.version))
+        } else {
+            UpPlanItem::install(&subject)
+        }
+    }
+
+    /// Returns the tool versions that `cleanup_unused` would uninstall,
+    /// which are the versions that were only required by the current
+    /// repository and are not expected by the given steps anymore.
+    pub fn plan_cleanup_unused(
+        steps: Vec<UpConfigTool>,
+        scope: Option<HashSet<String>>,
+    ) -> Vec<UpPlanItem> {
+        let expected_tools = steps
+            .iter()
+            .filter_map(|step| step.asdf_tool())
+            .flat_map(|tool| {
+                tool.versions()
+                    .into_iter()
+                    .map(|version| (tool.tool.clone(), version))
+                    .collect::<Vec<(String, String)>>()
+            })
+            .collect::<HashSet<(String, String)>>();
+
+        let repo_id = match workdir(".").id() {
+            Some(repo_id) => repo_id,
+            None => return vec![],
+        };
+
+        let cache = match Cache::shared() {
+            Ok(cache) => cache,
+            Err(_) => return vec![],
+        };
+
+        let mut items = Vec::new();
+        if let Some(asdf_cache) = &cache.asdf_operation {
+            for installed in asdf_cache.installed.iter() {
+                if installed.required_by == vec![repo_id.clone()]
+                    && in_cleanup_scope(&scope, &installed.tool)
+                    && !expected_tools
+                        .contains(&(installed.tool.clone(), installed.version.clone()))
+                {
+                    items.push(UpPlanItem::remove(&format!(
+                        "{} {}",
+                        installed.tool, installed.version
+                    )));
+                }
+            }
+        }
+
+        items
+    }
+
+    /// Walks through the directories of the step to detect the versions
+    /// of the tool requested by the version files found, returning the
+    /// directories in which each version was found.
+    fn detect_versions(&self) -> BTreeMap<String, BTreeSet<String>> {
+        let mut detected_versions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+        // Get the current directory
+        let current_dir = std::env::current_dir().expect("failed to get current directory");
+
+        let mut search_dirs = self.dirs.clone();
+        if search_dirs.is_empty() {
+            search_dirs.insert("".to_string());
+        }
+
+        let mut detect_version_funcs = self.detect_version_funcs.clone();
+        detect_version_funcs.push(detect_version_from_asdf_version_file);
+        detect_version_funcs.push(detect_version_from_version_file);
+
+        for search_dir in search_dirs.iter() {
+            // For safety, we remove any leading slashes from the search directory,
+            // as we only want to search in the workdir
+            let mut search_dir = search_dir.clone();
+            while search_dir.starts_with("/") {
+                search_dir.remove(0);
+            }
+
+            // Append the search directory to the current directory, since we are
+            // at the root of the workdir
+            let search_path = current_dir.join(search_dir);
+
+            for entry in WalkDir::new(search_path).follow_links(true) {
+                if let Ok(entry) = entry {
+                    if !entry.path().is_dir() {
+                        continue;
+                    }
+
+                    for detect_version_func in detect_version_funcs.iter() {
+                        if let Some(detected_version) =
+                            detect_version_func(self.tool.clone(), entry.path().to_path_buf())
+                        {
+                            let mut dir = entry
+                                .path()
+                                .strip_prefix(&current_dir)
+                                .expect("failed to strip prefix")
+                                .to_string_lossy()
+                                .to_string();
+                            while dir.starts_with("/") {
+                                dir.remove(0);
+                            }
+                            while dir.ends_with("/") {
+                                dir.pop();
+                            }
+
+                            if let Some(dirs) = detected_versions.get_mut(&detected_version) {
+                                dirs.insert(dir);
+                            } else {
+                                let mut dirs = BTreeSet::new();
+                                dirs.insert(dir);
+                                detected_versions.insert(detected_version.to_string(), dirs);
+                            }
+
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        detected_versions
+    }
+
     pub fn down(&self, _progress: Option<(usize, usize)>) -> Result<(), UpError> {
         Ok(())
     }
@@ -558,13 +802,144 @@ impl UpConfigAsdfBase {
         self.actual_versions.get_or_init(|| BTreeSet::new()).clone()
     }
 
+    /// Returns the versions requested for the tool, with the versions
+    /// they are locked to, as part of the inputs of the step; versions
+    /// requested through version files are detected from those files.
+    pub fn inputs_salt(&self) -> String {
+        let requested = if self.version == "auto" {
+            self.detect_versions()
+                .into_iter()
+                .map(|(version, dirs)| {
+                    let dirs = dirs.into_iter().collect::<Vec<String>>();
+                    (version, dirs.join(","))
+                })
+                .collect::<Vec<(String, String)>>()
+        } else {
+            vec![(self.version.clone(), "".to_string())]
+        };
+
+        let requested = requested
+            .iter()
+            .map(|(version, dirs)| {
+                format!(
+                    "{}={}@{}",
+                    version,
+                    locked_asdf_version(&self.tool, version).unwrap_or_default(),
+                    dirs
+                )
+            })
+            .collect::<Vec<String>>();
+
+        format!(
+            "{}:{}:{}",
+            self.tool,
+            requested.join(";"),
+            locked_asdf_plugin_commit(&self.tool).unwrap_or_default()
+        )
+    }
+
+    /// Uses the versions that were installed for the tool during a
+    /// previous run, for a step that does not need to run again; this
+    /// avoids resolving the versions again, which could require to
+    /// update the plugin. Returns `false` if any of the versions is not
+    /// installed anymore, or if the versions cannot be matched to the
+    /// requested ones, in which case the step needs to run again.
+    pub fn restore_versions(&self, previous: &[UpVersion]) -> bool {
+        if previous.is_empty()
+            || !previous
+                .iter()
+                .all(|version| is_asdf_tool_version_installed(&self.tool, &version.version))
+        {
+            return false;
+        }
+
+        if self.version != "auto" {
+            let versions = previous
+                .iter()
+                .map(|version| version.version.clone())
+                .collect::<BTreeSet<String>>();
+            if versions.len() != 1 {
+                return false;
+            }
+
+            let version = versions.into_iter().next().unwrap();
+            lock_asdf_version(&self.tool, &self.version, &version);
+            let _ = self.actual_version.set(version);
+        } else {
+            let mut all_versions = BTreeSet::new();
+            let mut dir_versions = BTreeMap::new();
+            for (requested, dirs) in self.detect_versions() {
+                let version = match previous.iter().find(|version| dirs.contains(&version.dir)) {
+                    Some(version) => version.version.clone(),
+                    None => return false,
+                };
+                lock_asdf_version(&self.tool, &requested, &version);
+                for dir in dirs.iter() {
+                    dir_versions.insert(dir.clone(), version.clone());
+                }
+                all_versions.insert(version);
+            }
+            let _ = self.actual_versions.set(all_versions);
+            let _ = self.actual_dir_versions.set(dir_versions);
+        }
+
+        self.lock_plugin_commit();
+
+        true
+    }
+
+    /// Returns the version that was resolved for the given directory
+    /// by `up`, `plan_up` or `restore_versions`, without resolving the
+    /// version specification again, which could require to update the
+    /// plugin; returns `None` if no version was resolved.
+    pub fn resolved_version(&self, dir: &str) -> Option<String> {
+        if self.version != "auto" {
+            return self
+                .actual_version
+                .get()
+                .filter(|version| !version.is_empty())
+                .cloned();
+        }
+
+        self.actual_dir_versions.get()?.get(dir).cloned()
+    }
+
+    /// Returns the reason why the version specification cannot be used
+    /// for the tool, if any; `lts` is only supported by the tools with
+    /// long-term support versions.
+    pub fn unsupported_version(&self) -> Option<String> {
+        let (_, expect) = match self.tool.as_str() {
+            "java" => split_java_version(&self.version),
+            _ => ("".to_string(), self.version.clone()),
+        };
+        if expect == "lts" && !LTS_TOOLS.contains(&self.tool.as_str()) {
+            return Some(format!(
+                "{} has no lts versions, lts is only supported for {}",
+                self.tool,
+                LTS_TOOLS.join(" and ")
+            ));
+        }
+
+        None
+    }
+
     pub fn version(
         &self,
         progress_handler: Option<Box<&dyn ProgressHandler>>,
     ) -> Result<&String, UpError> {
-        let version = self.actual_version.get_or_init(|| {
+        if let Some(reason) = self.unsupported_version() {
+            return Err(UpError::Config(reason));
+        }
+
+        let version = self.actual_version.get_or_try_init(|| {
+            // The version in the lock file is used as-is, without updating
+            // the plugin, so that it stays at the commit it was pinned to
+            if let Some(version) = locked_asdf_version(&self.tool, &self.version) {
+                return Ok(version);
+            }
+
             if let Err(_) = self.update_plugin(progress_handler.clone()) {
-                return "".to_string();
+                return Ok("".to_string());
             }
 
             if progress_handler.is_some() {
@@ -574,37 +949,8 @@ impl UpConfigAsdfBase {
                     .progress(format!("checking available versions"));
             }
 
-            let mut asdf_list_all = std::process::Command::new(format!("{}", *ASDF_BIN));
-            asdf_list_all.arg("list");
-            asdf_list_all.arg("all");
-            asdf_list_all.arg(self.tool.clone());
-            asdf_list_all.env("ASDF_DIR", &*ASDF_PATH);
-            asdf_list_all.env("ASDF_DATA_DIR", &*ASDF_PATH);
-            asdf_list_all.stdout(std::process::Stdio::piped());
-            asdf_list_all.stderr(std::process::Stdio::piped());
-
-            if let Ok(output) = asdf_list_all.output() {
-                if output.status.success() {
-                    let stdout = String::from_utf8(output.stdout).unwrap();
-                    let mut lines = stdout.lines();
-                    let mut version = "".to_string();
-                    while let Some(line) = lines.next() {
-                        let line = line.trim();
-
-                        if line.is_empty() {
-                            continue;
-                        }
-
-                        if version_match(&self.version, line) {
-                            version = line.to_string();
-                        }
-                    }
-                    return version;
-                }
-            }
-
-            "".to_string()
-        });
+            self.list_matching_version()
+        })?;
 
         if version.is_empty() {
             return Err(UpError::Exec(format!(
@@ -613,9 +959,92 @@ impl UpConfigAsdfBase {
             )));
         }
 
+        lock_asdf_version(&self.tool, &self.version, version);
+
         Ok(version)
     }
 
+    /// Returns the highest version of the tool, among the versions that
+    /// are installed or available, matching the version specification,
+    /// or an empty string if none match.
+    fn list_matching_version(&self) -> Result<String, UpError> {
+        if self.tool == "nodejs" && self.version == "lts" {
+            if let Err(err) = &*NODEJS_LTS_MAJORS {
+                return Err(UpError::Exec(format!(
+                    "failed to list the nodejs lts versions from {}: {}",
+                    NODEJS_RELEASES_URL, err
+                )));
+            }
+        }
+
+        let mut versions = self.list_versions(&["list", "all"]);
+        for installed in self.list_versions(&["list"]) {
+            if !versions.contains(&installed) {
+                versions.push(installed);
+            }
+        }
+
+        let matching = versions
+            .into_iter()
+            .filter(|version| self.version_match(version))
+            .collect::<Vec<String>>();
+
+        Ok(highest_version(&matching, |version| {
+            self.comparable_version(version)
+        }))
+    }
+
+    /// Returns the versions listed by the given `asdf` command for the
+    /// tool, in the order in which they are listed.
+    fn list_versions(&self, args: &[&str]) -> Vec<String> {
+        let mut asdf_list = std::process::Command::new(format!("{}", *ASDF_BIN));
+        asdf_list.args(args);
+        asdf_list.arg(self.tool.clone());
+        asdf_list.env("ASDF_DIR", &*ASDF_PATH);
+        asdf_list.env("ASDF_DATA_DIR", &*ASDF_PATH);
+        asdf_list.stdout(std::process::Stdio::piped());
+        asdf_list.stderr(std::process::Stdio::piped());
+
+        match asdf_list.output() {
+            Ok(output) if output.status.success() => String::from_utf8(output.stdout)
+                .unwrap_or_default()
+                .lines()
+                // Installed versions are listed with a `*` for the
+                // version currently selected
+                .map(|line| line.trim().trim_start_matches('*').trim().to_string())
+                .filter(|line| !line.is_empty() && !line.starts_with("No "))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    fn version_match(&self, version: &str) -> bool {
+        match self.tool.as_str() {
+            "java" => {
+                // Java versions are prefixed by their distribution, which needs
+                // to match exactly, while the version itself might be followed
+                // by a build number that we do not want to consider
+                let (expect_distribution, expect) = split_java_version(&self.version);
+                let (distribution, version) = split_java_version(version);
+                let version = version.split('+').next().unwrap_or_default();
+                expect_distribution == distribution && version_match(&self.tool, &expect, version)
+            }
+            _ => version_match(&self.tool, &self.version, version),
+        }
+    }
+
+    /// Returns the part of the version to use when comparing versions
+    /// of the tool, e.g. without the distribution for java.
+    fn comparable_version(&self, version: &str) -> String {
+        match self.tool.as_str() {
+            "java" => {
+                let (_, version) = split_java_version(version);
+                version.split('+').next().unwrap_or_default().to_string()
+            }
+            _ => version.to_string(),
+        }
+    }
+
     fn is_plugin_installed(&self) -> bool {
         let mut asdf_plugin_list = std::process::Command::new(format!("{}", *ASDF_BIN));
         asdf_plugin_list.arg("plugin");
@@ -704,6 +1133,71 @@ impl UpConfigAsdfBase {
         )
     }
 
+    /// Returns the commit at which the plugin of the tool currently is.
+    fn plugin_commit(&self) -> Option<String> {
+        let mut git_rev_parse = std::process::Command::new("git");
+        git_rev_parse.arg("-C");
+        git_rev_parse.arg(format!("{}/plugins/{}", *ASDF_PATH, self.tool));
+        git_rev_parse.arg("rev-parse");
+        git_rev_parse.arg("HEAD");
+        git_rev_parse.stdout(std::process::Stdio::piped());
+        git_rev_parse.stderr(std::process::Stdio::null());
+
+        let output = git_rev_parse.output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let commit = String::from_utf8(output.stdout).ok()?.trim().to_string();
+        if commit.is_empty() {
+            return None;
+        }
+
+        Some(commit)
+    }
+
+    /// Makes sure that the plugin is at the commit recorded in the lock
+    /// file, if any, so that the locked versions can be installed.
+    fn pin_plugin(
+        &self,
+        progress_handler: Option<Box<&dyn ProgressHandler>>,
+    ) -> Result<(), UpError> {
+        let commit = match locked_asdf_plugin_commit(&self.tool) {
+            Some(commit) => commit,
+            None => return Ok(()),
+        };
+
+        if self.plugin_commit() == Some(commit.clone()) {
+            return Ok(());
+        }
+
+        progress_handler.clone().map(|progress_handler| {
+            progress_handler.progress(format!("pinning {} plugin to {}", self.tool, commit))
+        });
+
+        let mut asdf_plugin_update = TokioCommand::new(format!("{}", *ASDF_BIN));
+        asdf_plugin_update.arg("plugin");
+        asdf_plugin_update.arg("update");
+        asdf_plugin_update.arg(self.tool.clone());
+        asdf_plugin_update.arg(commit);
+        asdf_plugin_update.env("ASDF_DIR", &*ASDF_PATH);
+        asdf_plugin_update.env("ASDF_DATA_DIR", &*ASDF_PATH);
+        asdf_plugin_update.stdout(std::process::Stdio::piped());
+        asdf_plugin_update.stderr(std::process::Stdio::piped());
+
+        run_progress(
+            &mut asdf_plugin_update,
+            progress_handler.clone(),
+            RunConfig::default(),
+        )
+    }
+
+    fn lock_plugin_commit(&self) {
+        if let Some(commit) = self.plugin_commit() {
+            lock_asdf_plugin_commit(&self.tool, &commit);
+        }
+    }
+
     fn is_version_installed(&self) -> bool {
         let version = self.version(None);
         if version.is_err() {
@@ -748,11 +1242,117 @@ impl UpConfigAsdfBase {
             return Err(err);
         }
 
+        record_change(UpChange::AsdfVersion {
+            tool: self.tool.clone(),
+            version: version.clone(),
+        });
+
         Ok(true)
     }
 
+    /// Uninstalls a version of a tool installed during the current run,
+    /// and removes the current repository from the repositories requiring
+    /// it, forgetting about the version if no other repository does.
+    pub fn rollback_version(tool: &str, version: &str) -> Result<(), UpError> {
+        let desc = format!("{} ({}):", tool, version).light_blue();
+        let progress_handler: Box<dyn ProgressHandler> = if ENV.interactive_shell {
+            Box::new(SpinnerProgressHandler::new(desc, None))
+        } else {
+            Box::new(PrintProgressHandler::new(desc, None))
+        };
+        let progress_handler: Option<Box<&dyn ProgressHandler>> =
+            Some(Box::new(progress_handler.as_ref()));
+
+        if is_asdf_tool_version_installed(tool, version) {
+            progress_handler.clone().map(|handler| {
+                handler.progress(format!("uninstalling {} {}", tool, version));
+            });
+
+            if let Err(err) = uninstall_asdf_tool_version(tool, version, progress_handler.clone()) {
+                progress_handler.clone().map(|handler| {
+                    handler.error_with_message(format!("failed to uninstall {} {}", tool, version));
+                });
+                return Err(err);
+            }
+        }
+
+        if let Err(err) = Cache::exclusive(|cache| {
+            let repo_id = match workdir(".").id() {
+                Some(repo_id) => repo_id,
+                None => return false,
+            };
+
+            if let Some(asdf_cache) = &mut cache.asdf_operation {
+                for exists in asdf_cache.installed.iter_mut() {
+                    if exists.tool == tool && exists.version == version {
+                        exists.required_by.retain(|id| id != &repo_id);
+                    }
+                }
+                asdf_cache.installed.retain(|exists| {
+                    exists.tool != tool
+                        || exists.version != version
+                        || !exists.required_by.is_empty()
+                });
+                return true;
+            }
+
+            false
+        }) {
+            progress_handler.clone().map(|handler| {
+                handler.error_with_message(format!("failed to update cache: {}", err));
+            });
+            return Err(UpError::Exec("failed to update cache".to_string()));
+        }
+
+        progress_handler.clone().map(|handler| {
+            handler.success_with_message(format!("{} {} uninstalled", tool, version));
+        });
+
+        Ok(())
+    }
+
+    /// Uninstalls a version of a tool that is not required by any
+    /// repository anymore; the cache is left to the caller to update.
+    pub fn uninstall_version(
+        tool: &str,
+        version: &str,
+        progress: Option<(usize, usize)>,
+    ) -> Result<(), UpError> {
+        let desc = format!("uninstall {} {}:", tool, version).light_yellow();
+        let progress_handler: Box<dyn ProgressHandler> = if ENV.interactive_shell {
+            Box::new(SpinnerProgressHandler::new(desc, progress))
+        } else {
+            Box::new(PrintProgressHandler::new(desc, progress))
+        };
+        let progress_handler: Option<Box<&dyn ProgressHandler>> =
+            Some(Box::new(progress_handler.as_ref()));
+
+        progress_handler.clone().map(|handler| {
+            handler.progress("uninstalling".to_string());
+        });
+
+        if let Err(err) = uninstall_asdf_tool_version(tool, version, progress_handler.clone()) {
+            progress_handler.clone().map(|handler| {
+                handler.error_with_message(format!("failed to uninstall {} {}", tool, version));
+            });
+            return Err(err);
+        }
+
+        progress_handler.clone().map(|handler| {
+            handler.success_with_message("uninstalled".to_string().light_green());
+        });
+
+        Ok(())
+    }
+
+    /// Removes the tool versions that are not expected by the given
+    /// steps anymore from the versions required by the repository, and
+    /// uninstalls the ones that are not required by any repository; if
+    /// a scope is given, only the versions of the tools in it are
+    /// considered, so that the versions of the other tools are kept.
     pub fn cleanup_unused(
         steps: Vec<UpConfigTool>,
+        scope: Option<HashSet<String>>,
         progress: Option<(usize, usize)>,
     ) -> Result<(), UpError> {
         let desc = format!("resources cleanup:").light_blue();
@@ -794,6 +1394,7 @@ impl UpConfigAsdfBase {
 
                 for (idx, exists) in asdf_cache.installed.iter_mut().enumerate() {
                     if exists.required_by.contains(&repo_id)
+                        && in_cleanup_scope(&scope, &exists.tool)
                         && !expected_tools.contains(&(exists.tool.clone(), exists.version.clone()))
                     {
                         exists.required_by.retain(|id| id != &repo_id);
@@ -820,20 +1421,10 @@ impl UpConfigAsdfBase {
                             ));
                         });
 
-                        let mut asdf_uninstall =
-                            tokio::process::Command::new(format!("{}", *ASDF_BIN));
-                        asdf_uninstall.arg("uninstall");
-                        asdf_uninstall.arg(to_remove.tool.clone());
-                        asdf_uninstall.arg(to_remove.version.clone());
-                        asdf_uninstall.env("ASDF_DIR", &*ASDF_PATH);
-                        asdf_uninstall.env("ASDF_DATA_DIR", &*ASDF_PATH);
-                        asdf_uninstall.stdout(std::process::Stdio::piped());
-                        asdf_uninstall.stderr(std::process::Stdio::piped());
-
-                        if let Err(_err) = run_progress(
-                            &mut asdf_uninstall,
+                        if let Err(_err) = uninstall_asdf_tool_version(
+                            &to_remove.tool,
+                            &to_remove.version,
                             progress_handler.clone(),
-                            RunConfig::default(),
                         ) {
                             progress_handler.clone().map(|handler| {
                                 handler.error_with_message(format!(
@@ -878,13 +1469,45 @@ impl UpConfigAsdfBase {
     }
 }
 
-fn version_match(expect: &str, version: &str) -> bool {
-    if expect == "latest" {
+fn in_cleanup_scope(scope: &Option<HashSet<String>>, tool: &str) -> bool {
+    match scope {
+        Some(scope) => scope.contains(tool),
+        None => true,
+    }
+}
+
+fn uninstall_asdf_tool_version(
+    tool: &str,
+    version: &str,
+    progress_handler: Option<Box<&dyn ProgressHandler>>,
+) -> Result<(), UpError> {
+    let mut asdf_uninstall = tokio::process::Command::new(format!("{}", *ASDF_BIN));
+    asdf_uninstall.arg("uninstall");
+    asdf_uninstall.arg(tool);
+    asdf_uninstall.arg(version);
+    asdf_uninstall.env("ASDF_DIR", &*ASDF_PATH);
+    asdf_uninstall.env("ASDF_DATA_DIR", &*ASDF_PATH);
+    asdf_uninstall.stdout(std::process::Stdio::piped());
+    asdf_uninstall.stderr(std::process::Stdio::piped());
+
+    run_progress(&mut asdf_uninstall, progress_handler, RunConfig::default())
+}
+
+/// Returns whether the version matches the version specification,
+/// which can be `latest`, `lts`, an exact version, a version prefix
+/// (e.g. `3.11` for `3.11.4`) or a semver range (e.g. `^1.2`, `~3.1`,
+/// `>=18 <21`).
+fn version_match(tool: &str, expect: &str, version: &str) -> bool {
+    if expect == "latest" || expect == version {
         return true;
     }
 
+    if expect == "lts" {
+        return is_lts_version(tool, version);
+    }
+
     if let Ok(requirements) = semverRange::from_str(expect) {
-        if let Ok(version) = semverVersion::from_str(version) {
+        if let Some(version) = coerce_version(version) {
             // By not directly returning, we allow to keep the prefix
             // check in case the version is not a semver version
             if version.satisfies(&requirements) {
@@ -902,6 +1525,75 @@ fn version_match(expect: &str, version: &str) -> bool {
     rest_of_line.chars().all(|c| c.is_digit(10) || c == '.')
 }
 
+/// Parses a version as a semver version, completing the missing minor
+/// and patch numbers (e.g. `1.21` becomes `1.21.0`); returns `None` for
+/// versions that do not follow semver (e.g. `3.12.0a1`, `jruby-9.4`).
+fn coerce_version(version: &str) -> Option<semverVersion> {
+    let captures = VERSION_REGEX.captures(version)?;
+
+    let part = |name: &str| captures.name(name).map_or("0", |part| part.as_str());
+    semverVersion::from_str(&format!(
+        "{}.{}.{}{}",
+        part("major"),
+        part("minor"),
+        part("patch"),
+        captures.name("rest").map_or("", |rest| rest.as_str()),
+    ))
+    .ok()
+}
+
+/// Returns the highest of the given versions; stable versions rank
+/// above pre-releases, which rank above versions that do not follow
+/// semver, for which the order of the list is kept.
+fn highest_version<F>(versions: &[String], comparable: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let rank = |version: &str| match coerce_version(&comparable(version)) {
+        Some(semver) if !semver.is_prerelease() => (2, Some(semver)),
+        Some(semver) => (1, Some(semver)),
+        None => (0, None),
+    };
+
+    let mut highest: Option<(&String, (u8, Option<semverVersion>))> = None;
+    for version in versions {
+        let version_rank = rank(version);
+        match &highest {
+            Some((_, highest_rank)) if version_rank < *highest_rank => {}
+            _ => highest = Some((version, version_rank)),
+        }
+    }
+
+    highest
+        .map(|(version, _)| version.to_string())
+        .unwrap_or_default()
+}
+
+/// Returns whether the version is a long-term support version of the
+/// tool; only node and java have long-term support versions.
+fn is_lts_version(tool: &str, version: &str) -> bool {
+    let semver = match coerce_version(version) {
+        Some(semver) if !semver.is_prerelease() => semver,
+        _ => return false,
+    };
+
+    match tool {
+        // Failing to fetch the list of releases is reported when
+        // resolving the version, see `list_matching_version`
+        "nodejs" => match &*NODEJS_LTS_MAJORS {
+            Ok(majors) => majors.contains(&semver.major),
+            Err(_) => false,
+        },
+        // Java 8 and 11 are LTS, then every 4th version since 17
+        "java" => {
+            semver.major == 8
+                || semver.major == 11
+                || (semver.major >= 17 && (semver.major - 17) % 4 == 0)
+        }
+        _ => false,
+    }
+}
+
 fn detect_version_from_asdf_version_file(tool_name: String, path: PathBuf) -> Option<String> {
     let version_file_path = path.join(".tool-versions");
     if !version_file_path.exists() || version_file_path.is_dir() {
@@ -950,7 +1642,7 @@ fn detect_version_from_version_file(tool_name: String, path: PathBuf) -> Option<
     let tool_name = tool_name.to_lowercase();
     let version_file_prefixes = match tool_name.as_str() {
         "golang" => vec!["go", "golang"],
-        "node" => vec!["node", "nodejs"],
+        "node" | "nodejs" => vec!["node", "nodejs"],
         _ => vec![tool_name.as_str()],
     };
 
@@ -1,21 +1,40 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
 use serde::Deserialize;
 use serde::Serialize;
 use time::OffsetDateTime;
 
+use crate::internal::cache::UpEnvironment;
 use crate::internal::cache::UpEnvironments;
+use crate::internal::cache::UpVersion;
+use indicatif::MultiProgress;
+
+use crate::internal::config::up::step::schedule;
+use crate::internal::config::up::utils::set_multiprogress;
+use crate::internal::config::up::utils::set_up_env_hash;
+use crate::internal::config::up::utils::PrintProgressHandler;
+use crate::internal::config::up::utils::ProgressHandler;
+use crate::internal::config::up::UpCheck;
+use crate::internal::config::up::UpCheckStatus;
 use crate::internal::config::up::UpConfigAsdfBase;
+use crate::internal::config::up::UpConfigStep;
 use crate::internal::config::up::UpConfigTool;
 use crate::internal::config::up::UpError;
+use crate::internal::config::up::UpPlan;
+use crate::internal::config::up::UpPlanItem;
+use crate::internal::config::up::UpStepSelection;
 use crate::internal::config::ConfigValue;
 use crate::internal::dynenv::update_dynamic_env_for_command;
 use crate::internal::user_interface::colors::StringColor;
 use crate::internal::workdir;
 use crate::internal::Cache;
+use crate::internal::ENV;
 use crate::omni_warning;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UpConfig {
-    pub steps: Vec<UpConfigTool>,
+    pub steps: Vec<UpConfigStep>,
     pub errors: Vec<UpError>,
 }
 
@@ -35,7 +54,8 @@ impl UpConfig {
         for (value, index) in config_value.as_array().unwrap().iter().zip(0..) {
             if value.is_str() {
                 let up_name = value.as_str().unwrap();
-                if let Some(up_config) = UpConfigTool::from_config_value(&up_name, None) {
+                if let Some(up_config) = UpConfigStep::from_config_value(index + 1, &up_name, None)
+                {
                     steps.push(up_config);
                 } else {
                     errors.push(UpError::Config(format!(
@@ -57,7 +77,7 @@ impl UpConfig {
 
                 let (up_name, config_value) = table.iter().next().unwrap();
                 if let Some(up_config) =
-                    UpConfigTool::from_config_value(up_name, Some(config_value))
+                    UpConfigStep::from_config_value(index + 1, up_name, Some(config_value))
                 {
                     steps.push(up_config);
                 } else {
@@ -77,6 +97,32 @@ impl UpConfig {
             }
         }
 
+        for (idx, step) in steps.iter().enumerate() {
+            for need in step.needs.iter() {
+                if !steps.iter().any(|other| other.has_name(need)) {
+                    errors.push(UpError::Config(format!(
+                        "invalid config for step {}: needs unknown step '{}'",
+                        idx + 1,
+                        need
+                    )));
+                }
+            }
+        }
+
+        for (idx, step) in steps.iter().enumerate() {
+            if let Some(reason) = step
+                .tool
+                .asdf_tool()
+                .and_then(|asdf_tool| asdf_tool.unsupported_version())
+            {
+                errors.push(UpError::Config(format!(
+                    "invalid config for step {}: {}",
+                    idx + 1,
+                    reason
+                )));
+            }
+        }
+
         if steps.len() == 0 && errors.len() == 0 {
             return None;
         }
@@ -103,7 +149,45 @@ impl UpConfig {
         !self.errors.is_empty()
     }
 
-    pub fn clear_cache() {
+    /// Returns whether a step of the configuration uses the given asdf
+    /// tool, whether it ran or not during this run.
+    pub fn uses_asdf_tool(&self, tool: &str) -> bool {
+        self.steps
+            .iter()
+            .filter_map(|step| step.tool.asdf_tool())
+            .any(|asdf_tool| asdf_tool.tool == tool)
+    }
+
+    /// Returns whether a step of the configuration could have resolved
+    /// the given version of an asdf tool; the versions detected by the
+    /// steps using `auto` are only known when running them, so all the
+    /// versions of a tool are considered used if one of its steps does.
+    pub fn uses_asdf_version(&self, tool: &str, version: &str) -> bool {
+        self.steps
+            .iter()
+            .filter_map(|step| step.tool.asdf_tool())
+            .filter(|asdf_tool| asdf_tool.tool == tool)
+            .any(|asdf_tool| asdf_tool.version == "auto" || asdf_tool.version == version)
+    }
+
+    /// Returns whether a homebrew step of the configuration installs
+    /// the given package, whether it ran or not during this run.
+    pub fn uses_homebrew_package(&self, package: &str) -> bool {
+        self.steps.iter().any(|step| match &step.tool {
+            UpConfigTool::Homebrew(config) => config
+                .package_ids()
+                .iter()
+                .any(|package_id| package_id == package),
+            _ => false,
+        })
+    }
+
+    /// Clears the up environment of the current repository from the cache;
+    /// when `keep_hashes` is set, the hashes of the steps inputs are kept so
+    /// that steps can still identify if they need to run again. Returns the
+    /// up environment that was cleared, if any.
+    pub fn clear_cache(keep_hashes: bool) -> Option<UpEnvironment> {
+        let mut previous_env = None;
         if let Err(err) = Cache::exclusive(|cache| {
             let workdir = workdir(".");
             let repo_id = workdir.id();
@@ -123,7 +207,14 @@ impl UpConfig {
                 return false;
             }
 
-            up_env.remove(&repo_id);
+            let repo_up_env = up_env.remove(&repo_id).unwrap();
+            if keep_hashes && !repo_up_env.hashes.is_empty() {
+                let mut new_up_env = UpEnvironment::new();
+                new_up_env.hashes = repo_up_env.hashes.clone();
+                up_env.insert(repo_id, new_up_env);
+            }
+            previous_env = Some(repo_up_env);
+
             cache.up_environments = Some(UpEnvironments {
                 env: up_env.clone(),
                 updated_at: OffsetDateTime::now_utc(),
@@ -133,25 +224,367 @@ impl UpConfig {
         }) {
             omni_warning!(format!("failed to update cache: {}", err));
         }
+
+        previous_env
     }
 
-    pub fn up(&self) -> Result<(), UpError> {
-        // Get current directory
-        let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    /// Returns the up environment of the current repository, if any.
+    fn current_environment() -> Option<UpEnvironment> {
+        let repo_id = workdir(".").id()?;
+        let cache = Cache::shared().ok()?;
+        cache.up_environments?.env.get(&repo_id).cloned()
+    }
+
+    /// Copies the versions and environment variables that the step had
+    /// set in the up environment of the repository during its previous
+    /// run, for a step that does not need to run again.
+    fn restore_environment(step: &UpConfigStep, previous_env: &UpEnvironment) {
+        let versions = match step.tool.asdf_tool() {
+            Some(asdf_tool) => previous_env
+                .versions
+                .iter()
+                .filter(|version| version.tool == asdf_tool.tool)
+                .cloned()
+                .collect::<Vec<_>>(),
+            None => vec![],
+        };
+
+        Self::extend_environment(versions, Self::previous_env_vars(step, previous_env));
+    }
+
+    /// Copies the environment variables that the step had set in the up
+    /// environment of the repository during its previous run, for a step
+    /// about to run again; the step overrides them if it sets them again,
+    /// but it might not need to (e.g. a custom step already met).
+    fn keep_env_vars(step: &UpConfigStep, previous_env: &UpEnvironment) {
+        Self::extend_environment(vec![], Self::previous_env_vars(step, previous_env));
+    }
+
+    fn previous_env_vars(
+        step: &UpConfigStep,
+        previous_env: &UpEnvironment,
+    ) -> Vec<(String, String)> {
+        step.tool
+            .env_vars()
+            .into_iter()
+            .filter_map(|key| {
+                let value = previous_env.env_vars.get(&key)?.clone();
+                Some((key, value))
+            })
+            .collect()
+    }
+
+    fn extend_environment(versions: Vec<UpVersion>, env_vars: Vec<(String, String)>) {
+        if versions.is_empty() && env_vars.is_empty() {
+            return;
+        }
+
+        if let Err(err) = Cache::exclusive(|cache| {
+            let repo_id = match workdir(".").id() {
+                Some(repo_id) => repo_id,
+                None => return false,
+            };
+
+            let mut up_env = HashMap::new();
+            if let Some(up_cache) = &cache.up_environments {
+                up_env = up_cache.env.clone();
+            }
+            let repo_up_env = up_env.entry(repo_id).or_insert_with(UpEnvironment::new);
+
+            for version in versions.iter() {
+                if !repo_up_env.versions.iter().any(|exists| {
+                    exists.tool == version.tool
+                        && exists.version == version.version
+                        && exists.dir == version.dir
+                }) {
+                    repo_up_env.versions.push(version.clone());
+                }
+            }
+            repo_up_env.env_vars.extend(env_vars.clone());
+
+            cache.up_environments = Some(UpEnvironments {
+                env: up_env.clone(),
+                updated_at: OffsetDateTime::now_utc(),
+            });
+
+            true
+        }) {
+            omni_warning!(format!("failed to update cache: {}", err));
+        }
+    }
+
+    /// Returns whether the step can be skipped, which is the case if its
+    /// inputs have not changed since its last successful run, and what
+    /// it set up during that run is still there.
+    fn is_unchanged(
+        step: &UpConfigStep,
+        hash: &Option<String>,
+        previous_env: Option<&UpEnvironment>,
+    ) -> bool {
+        let previous_env = match previous_env {
+            Some(previous_env) => previous_env,
+            None => return false,
+        };
+
+        hash.is_some()
+            && hash.as_ref() == previous_env.hashes.get(&step.hash_key())
+            && step
+                .tool
+                .env_vars()
+                .iter()
+                .all(|key| previous_env.env_vars.contains_key(key))
+            && step.tool.restore(previous_env)
+    }
+
+    /// Returns the selected steps that are available on the current
+    /// system and for which the `if` condition is met; the selected
+    /// steps skipped because of their condition are reported with the
+    /// reason.
+    fn available_steps(&self, selection: &UpStepSelection) -> Vec<UpConfigStep> {
+        let mut steps = Vec::new();
 
-        // Filter the steps to only the available ones
-        let steps = self
-            .steps
+        for (step, skip_reason) in self.steps_with_skip_reason() {
+            if !selection.includes(&step) {
+                continue;
+            }
+
+            if let Some(reason) = skip_reason {
+                let desc = format!("{}:", step.name()).light_blue();
+                PrintProgressHandler::new(desc, None)
+                    .success_with_message(format!("skipping ({})", reason).light_black());
+                continue;
+            }
+
+            steps.push(step);
+        }
+
+        steps
+    }
+
+    /// Returns the steps that are available on the current system, each
+    /// with the reason why it should be skipped if its `if` condition is
+    /// not met.
+    fn steps_with_skip_reason(&self) -> Vec<(UpConfigStep, Option<String>)> {
+        self.steps
+            .iter()
+            .filter(|step| step.tool.is_available())
+            .map(|step| (step.clone(), step.skip_reason()))
+            .collect()
+    }
+
+    /// Returns the steps that are not part of the selection.
+    fn unselected_steps(&self, selection: &UpStepSelection) -> Vec<UpConfigStep> {
+        self.steps
             .iter()
-            .filter(|step| step.is_available())
+            .filter(|step| !selection.includes(step))
             .cloned()
-            .collect::<Vec<UpConfigTool>>();
+            .collect()
+    }
+
+    /// Returns the asdf tools for which the resources cleanup can
+    /// uninstall versions; when only some of the steps are selected,
+    /// those are the tools of the selected steps that are not used by
+    /// any other step, so that the versions the other steps installed
+    /// are left alone. Returns `None` when all the steps are selected.
+    fn cleanup_scope(&self, selection: &UpStepSelection) -> Option<HashSet<String>> {
+        if selection.is_empty() {
+            return None;
+        }
+
+        let asdf_tools = |selected: bool| {
+            self.steps
+                .iter()
+                .filter(|step| selection.includes(step) == selected)
+                .filter_map(|step| step.tool.asdf_tool())
+                .map(|asdf_tool| asdf_tool.tool.clone())
+                .collect::<HashSet<String>>()
+        };
+
+        let unselected_tools = asdf_tools(false);
+        Some(
+            asdf_tools(true)
+                .into_iter()
+                .filter(|tool| !unselected_tools.contains(tool))
+                .collect(),
+        )
+    }
+
+    /// Keeps what the steps that are not part of the selection had set
+    /// up during their previous run, so that running only some of the
+    /// steps does not drop the others from the up environment.
+    fn restore_unselected(&self, selection: &UpStepSelection, previous_env: &UpEnvironment) {
+        for step in self.unselected_steps(selection) {
+            step.tool.restore(previous_env);
+            Self::restore_environment(&step, previous_env);
+        }
+    }
+
+    /// Returns the plan of the changes that `up` would make, without
+    /// making any change to the system or the cache; steps for which
+    /// the inputs have not changed are skipped unless `force` is set,
+    /// and only the selected steps are considered.
+    pub fn plan_up(&self, trusted: bool, force: bool, selection: &UpStepSelection) -> UpPlan {
+        let current_dir = std::env::current_dir().expect("Failed to get current directory");
+        let current_env = Self::current_environment();
+
+        let mut plan = UpPlan::default();
+        let mut tools = Vec::new();
+
+        for (step, skip_reason) in self.steps_with_skip_reason() {
+            if !selection.includes(&step) {
+                continue;
+            }
+
+            if let Some(reason) = skip_reason {
+                plan.add_step(&step.name(), vec![UpPlanItem::skip(&step.name(), &reason)]);
+                continue;
+            }
+
+            // Steps with a specific directory need to be checked from
+            // that directory, the same way they would be run
+            let step_dir = current_dir.join(step.tool.dir().unwrap_or("".to_string()));
+            if std::env::set_current_dir(&step_dir).is_err() {
+                plan.add_step(
+                    &step.name(),
+                    vec![UpPlanItem::run(&step.name())
+                        .with_detail(&format!("{} does not exist yet", step_dir.display()))],
+                );
+                continue;
+            }
+
+            if !force && Self::is_unchanged(&step, &step.inputs_hash(), current_env.as_ref()) {
+                plan.add_step(
+                    &step.name(),
+                    vec![UpPlanItem::skip(&step.name(), "inputs unchanged")],
+                );
+                tools.push(step.tool);
+                continue;
+            }
+
+            plan.add_step(&step.name(), step.tool.plan_up(trusted));
+            tools.push(step.tool);
+        }
+
+        let _ = std::env::set_current_dir(&current_dir);
+
+        plan.add_step(
+            "resources cleanup",
+            UpConfigAsdfBase::plan_cleanup_unused(tools, self.cleanup_scope(selection)),
+        );
+
+        plan
+    }
+
+    /// Returns whether each of the selected steps is met on the current
+    /// system, which is the case when running it would not make any
+    /// change, without making any change to the system or the cache.
+    pub fn check(&self, trusted: bool, selection: &UpStepSelection) -> UpCheck {
+        let current_dir = std::env::current_dir().expect("Failed to get current directory");
+
+        let mut check = UpCheck::new();
+
+        for (step, skip_reason) in self.steps_with_skip_reason() {
+            if !selection.includes(&step) {
+                continue;
+            }
+
+            if let Some(reason) = skip_reason {
+                check.add_step(&step.name(), UpCheckStatus::Skipped, Some(&reason), vec![]);
+                continue;
+            }
+
+            // Steps with a specific directory need to be checked from
+            // that directory, the same way they would be run
+            let step_dir = current_dir.join(step.tool.dir().unwrap_or("".to_string()));
+            if std::env::set_current_dir(&step_dir).is_err() {
+                check.add_step(
+                    &step.name(),
+                    UpCheckStatus::Unmet,
+                    Some(&format!("{} does not exist", step_dir.display())),
+                    vec![],
+                );
+                continue;
+            }
+
+            if !step.tool.can_check(trusted) {
+                let reason = if trusted {
+                    "no met? command"
+                } else {
+                    "met? not evaluated, repository not trusted"
+                };
+                check.add_step(&step.name(), UpCheckStatus::Unchecked, Some(reason), vec![]);
+                continue;
+            }
+
+            let items = step.tool.plan_up(trusted);
+            if items.iter().any(|item| item.is_change()) {
+                check.add_step(&step.name(), UpCheckStatus::Unmet, None, items);
+            } else {
+                check.add_step(&step.name(), UpCheckStatus::Met, None, items);
+            }
+        }
+
+        let _ = std::env::set_current_dir(&current_dir);
+
+        check
+    }
+
+    /// Returns the plan of the changes that `down` would make, without
+    /// making any change to the system or the cache; only the selected
+    /// steps are considered.
+    pub fn plan_down(&self, trusted: bool, selection: &UpStepSelection) -> UpPlan {
+        let mut plan = UpPlan::default();
+
+        for (step, skip_reason) in self.steps_with_skip_reason().into_iter().rev() {
+            if !selection.includes(&step) {
+                continue;
+            }
+
+            if let Some(reason) = skip_reason {
+                plan.add_step(&step.name(), vec![UpPlanItem::skip(&step.name(), &reason)]);
+                continue;
+            }
+
+            plan.add_step(&step.name(), step.tool.plan_down(trusted));
+        }
+
+        plan.add_step(
+            "resources cleanup",
+            UpConfigAsdfBase::plan_cleanup_unused(Vec::new(), self.cleanup_scope(selection)),
+        );
+
+        plan
+    }
 
-        // Go through the steps
+    /// Runs the steps of the configuration; steps for which the inputs
+    /// have not changed since their last successful run are skipped,
+    /// using what they set up in `previous_env`, unless `force` is set.
+    /// Only the selected steps are run, the other ones keeping what
+    /// they set up in `previous_env`.
+    pub fn up(
+        &self,
+        previous_env: Option<&UpEnvironment>,
+        force: bool,
+        selection: &UpStepSelection,
+    ) -> Result<(), UpError> {
+        // Get current directory
+        let current_dir = std::env::current_dir().expect("Failed to get current directory");
+
+        // Filter the steps to only the selected and available ones
+        let steps = self.available_steps(selection);
+        if let Some(previous_env) = previous_env {
+            self.restore_unselected(selection, previous_env);
+        }
+
+        // Identify which steps can run concurrently
+        let waves = schedule(&steps)?;
+
+        // Go through the waves of steps
         let num_steps = steps.len() + 1;
-        for (idx, step) in steps.iter().enumerate() {
-            // Make sure that we're in the right directory
-            let step_dir = current_dir.join(step.dir().unwrap_or("".to_string()));
+        for wave in waves.iter() {
+            // Make sure that we're in the right directory; steps with a
+            // specific directory always run alone in their wave
+            let step_dir = current_dir.join(steps[wave[0]].tool.dir().unwrap_or("".to_string()));
             if let Err(error) = std::env::set_current_dir(&step_dir) {
                 return Err(UpError::Exec(format!(
                     "failed to change directory to {}: {}",
@@ -164,27 +597,122 @@ impl UpConfig {
             // the command can consider it right away
             update_dynamic_env_for_command(".");
 
-            if let Err(error) = step.up(Some((idx + 1, num_steps))) {
-                return Err(error);
+            // Identify the steps of the wave that need to run, computing
+            // the hash of their inputs before they run, as running them
+            // might change their inputs (e.g. lock files)
+            let mut to_run = Vec::new();
+            for idx in wave.iter() {
+                let step = &steps[*idx];
+                let hash = step.inputs_hash();
+
+                if !force && Self::is_unchanged(step, &hash, previous_env) {
+                    Self::restore_environment(step, previous_env.unwrap());
+                    let desc = format!("{}:", step.name()).light_blue();
+                    PrintProgressHandler::new(desc, Some((idx + 1, num_steps)))
+                        .success_with_message(
+                            "skipping (inputs unchanged)".to_string().light_black(),
+                        );
+                    continue;
+                }
+
+                if let Some(previous_env) = previous_env {
+                    Self::keep_env_vars(step, previous_env);
+                }
+                to_run.push((*idx, hash));
+            }
+
+            if to_run.len() == 1 {
+                let (idx, hash) = to_run.remove(0);
+                let result = steps[idx].tool.up(Some((idx + 1, num_steps)));
+                Self::store_hash(&steps[idx], hash, &result);
+                if let Err(err) = result {
+                    let _ = std::env::set_current_dir(&current_dir);
+                    return Err(err);
+                }
+                continue;
+            } else if to_run.is_empty() {
+                continue;
+            }
+
+            // Run the steps of the wave concurrently, each with its own
+            // line in the multi-progress
+            if ENV.interactive_shell {
+                set_multiprogress(Some(MultiProgress::new()));
+            }
+
+            let results = std::thread::scope(|scope| {
+                let handles = to_run
+                    .iter()
+                    .map(|(idx, _)| {
+                        let idx = *idx;
+                        let step = &steps[idx];
+                        scope.spawn(move || step.tool.up(Some((idx + 1, num_steps))))
+                    })
+                    .collect::<Vec<_>>();
+
+                handles
+                    .into_iter()
+                    .map(|handle| match handle.join() {
+                        Ok(result) => result,
+                        Err(_) => Err(UpError::Exec("step panicked".to_string())),
+                    })
+                    .collect::<Vec<Result<(), UpError>>>()
+            });
+
+            set_multiprogress(None);
+
+            for ((idx, hash), result) in to_run.into_iter().zip(results.iter()) {
+                Self::store_hash(&steps[idx], hash, result);
+            }
+
+            // Return the first error, if any, once all the steps
+            // of the wave are done
+            if let Some(Err(err)) = results.into_iter().find(|result| result.is_err()) {
+                let _ = std::env::set_current_dir(&current_dir);
+                return Err(err);
             }
         }
 
+        // Go back to the directory we started from
+        let _ = std::env::set_current_dir(&current_dir);
+
         // This is a special case, as we could have multiple versions of a single
         // tool loaded in the same repo (for some reason...) we need to clean up
         // the unused ones _at the end_ of the process
-        UpConfigAsdfBase::cleanup_unused(steps.clone(), Some((num_steps, num_steps)))?;
+        let tools = steps
+            .iter()
+            .map(|step| step.tool.clone())
+            .collect::<Vec<UpConfigTool>>();
+        UpConfigAsdfBase::cleanup_unused(
+            tools,
+            self.cleanup_scope(selection),
+            Some((num_steps, num_steps)),
+        )?;
 
         Ok(())
     }
 
-    pub fn down(&self) -> Result<(), UpError> {
-        // Filter the steps to only the available ones
-        let steps = self
-            .steps
-            .iter()
-            .filter(|step| step.is_available())
-            .cloned()
-            .collect::<Vec<UpConfigTool>>();
+    /// Stores the hash of the inputs of the step if it ran successfully,
+    /// or removes it if it failed, so that the step runs again next time.
+    fn store_hash(step: &UpConfigStep, hash: Option<String>, result: &Result<(), UpError>) {
+        let hash = if result.is_ok() { hash } else { None };
+        if let Err(err) = set_up_env_hash(&step.hash_key(), hash) {
+            omni_warning!(format!("failed to update cache: {}", err));
+        }
+    }
+
+    /// Tears down the selected steps of the configuration, in reverse
+    /// order; the other steps keep what they set up in `previous_env`.
+    pub fn down(
+        &self,
+        previous_env: Option<&UpEnvironment>,
+        selection: &UpStepSelection,
+    ) -> Result<(), UpError> {
+        // Filter the steps to only the selected and available ones
+        let steps = self.available_steps(selection);
+        if let Some(previous_env) = previous_env {
+            self.restore_unselected(selection, previous_env);
+        }
 
         // Go through the steps, in reverse
         let num_steps = steps.len();
@@ -193,12 +721,21 @@ impl UpConfig {
             // the command can consider it right away
             update_dynamic_env_for_command(".");
 
-            if let Err(error) = step.down(Some((idx + 1, num_steps))) {
+            if let Err(error) = step.tool.down(Some((idx + 1, num_steps))) {
                 return Err(error);
             }
+
+            // The step needs to run again on the next `up`
+            if let Err(err) = set_up_env_hash(&step.hash_key(), None) {
+                omni_warning!(format!("failed to update cache: {}", err));
+            }
         }
 
-        UpConfigAsdfBase::cleanup_unused(Vec::new(), Some((num_steps, num_steps)))?;
+        UpConfigAsdfBase::cleanup_unused(
+            Vec::new(),
+            self.cleanup_scope(selection),
+            Some((num_steps, num_steps)),
+        )?;
 
         Ok(())
     }
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::path::Path;
+use std::path::PathBuf;
 
 use serde::Deserialize;
 use serde::Serialize;
@@ -9,6 +10,7 @@ use tokio::process::Command as TokioCommand;
 use crate::internal::cache::UpEnvironment;
 use crate::internal::cache::UpEnvironments;
 use crate::internal::commands::utils::abs_path;
+use crate::internal::config::up::plan::UpPlanItem;
 use crate::internal::config::up::utils::run_progress;
 use crate::internal::config::up::utils::PrintProgressHandler;
 use crate::internal::config::up::utils::ProgressHandler;
@@ -218,6 +220,68 @@ impl UpConfigBundler {
         Ok(())
     }
 
+    pub fn plan_up(&self) -> Vec<UpPlanItem> {
+        if self.is_satisfied() {
+            return vec![UpPlanItem::skip("bundle install", "already satisfied")];
+        }
+
+        let mut items = Vec::new();
+        if let Some(path) = &self.path {
+            items.push(UpPlanItem::run(&format!(
+                "bundle config --local path {}",
+                path
+            )));
+        }
+        items.push(UpPlanItem::run("bundle install"));
+        items
+    }
+
+    /// Returns whether the dependencies of the Gemfile are all installed
+    /// in the bundle path, according to `bundle check`.
+    fn is_satisfied(&self) -> bool {
+        let mut bundle_check = std::process::Command::new("bundle");
+        bundle_check.arg("check");
+        bundle_check.env("BUNDLE_GEMFILE", self.gemfile_abs_path());
+        if let Some(path) = &self.path {
+            bundle_check.env("BUNDLE_PATH", abs_path(path));
+        }
+        bundle_check.stdout(std::process::Stdio::null());
+        bundle_check.stderr(std::process::Stdio::null());
+
+        match bundle_check.status() {
+            Ok(status) => status.success(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn plan_down(&self) -> Vec<UpPlanItem> {
+        match &self.path {
+            Some(path) if Path::new(path).exists() => {
+                vec![UpPlanItem::remove(abs_path(path).to_str().unwrap())]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Returns the files that, if changed, require to install the
+    /// dependencies again.
+    pub fn input_files(&self) -> Vec<PathBuf> {
+        let gemfile = self.gemfile_abs_path();
+        vec![
+            PathBuf::from(&gemfile),
+            PathBuf::from(format!("{}.lock", gemfile)),
+        ]
+    }
+
+    /// Returns whether the dependencies installed during a previous
+    /// run are still where they were installed.
+    pub fn is_set_up(&self) -> bool {
+        match &self.path {
+            Some(path) => Path::new(path).exists(),
+            None => true,
+        }
+    }
+
     fn gemfile_abs_path(&self) -> String {
         let gemfile = if let Some(gemfile) = &self.gemfile {
             gemfile.clone()
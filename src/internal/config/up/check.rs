@@ -0,0 +1,105 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::internal::config::up::UpPlanItem;
+use crate::internal::user_interface::StringColor;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpCheckStatus {
+    Met,
+    Unmet,
+    Skipped,
+    Unchecked,
+}
+
+/// The state of a single step on the current system, as reported when
+/// running in check mode, with the items that led to that state.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpCheckStep {
+    pub name: String,
+    pub status: UpCheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<UpPlanItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UpCheck {
+    pub met: bool,
+    pub steps: Vec<UpCheckStep>,
+}
+
+impl UpCheck {
+    pub fn new() -> Self {
+        Self {
+            met: true,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn add_step(
+        &mut self,
+        name: &str,
+        status: UpCheckStatus,
+        reason: Option<&str>,
+        items: Vec<UpPlanItem>,
+    ) {
+        if status == UpCheckStatus::Unmet {
+            self.met = false;
+        }
+
+        self.steps.push(UpCheckStep {
+            name: name.to_string(),
+            status,
+            reason: reason.map(|reason| reason.to_string()),
+            items,
+        });
+    }
+
+    pub fn num_unmet(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| step.status == UpCheckStatus::Unmet)
+            .count()
+    }
+
+    pub fn print(&self) {
+        let num_steps = self.steps.len();
+        let padding = format!("{}", num_steps).len();
+
+        for (idx, step) in self.steps.iter().enumerate() {
+            let status = match step.status {
+                UpCheckStatus::Met => "met".to_string().green(),
+                UpCheckStatus::Unmet => "unmet".to_string().red(),
+                UpCheckStatus::Skipped => "skipped".to_string().light_black(),
+                UpCheckStatus::Unchecked => "unchecked".to_string().yellow(),
+            };
+            let reason = if let Some(reason) = &step.reason {
+                format!(" {}", format!("({})", reason).light_black())
+            } else {
+                "".to_string()
+            };
+
+            eprintln!(
+                "{} {} {}{}",
+                format!(
+                    "[{:padding$}/{:padding$}]",
+                    idx + 1,
+                    num_steps,
+                    padding = padding
+                )
+                .bold()
+                .light_black(),
+                format!("{}:", step.name).light_blue(),
+                status,
+                reason,
+            );
+
+            for item in step.items.iter() {
+                eprintln!("  {}", item.format());
+            }
+        }
+    }
+}
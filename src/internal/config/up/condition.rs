@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::internal::config::up::utils::command_exists;
+use crate::internal::config::ConfigValue;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum UpConfigConditionEnv {
+    Equals(String),
+    Present(bool),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UpConfigCondition {
+    pub os: Vec<String>,
+    pub arch: Vec<String>,
+    pub env: BTreeMap<String, UpConfigConditionEnv>,
+    pub command: Vec<String>,
+}
+
+impl UpConfigCondition {
+    pub fn from_config_value(config_value: Option<&ConfigValue>) -> Option<Self> {
+        let config_value = config_value?;
+        let config_value = config_value.get("if")?;
+
+        let mut condition = UpConfigCondition {
+            os: string_or_list(config_value.get("os")),
+            arch: string_or_list(config_value.get("arch")),
+            command: string_or_list(config_value.get("command")),
+            ..Default::default()
+        };
+
+        if let Some(env) = config_value.get("env").and_then(|env| env.as_table()) {
+            for (key, value) in env.iter() {
+                let expected = if let Some(present) = value.as_bool() {
+                    UpConfigConditionEnv::Present(present)
+                } else if let Some(value) = value.as_str_forced() {
+                    UpConfigConditionEnv::Equals(value)
+                } else {
+                    continue;
+                };
+                condition.env.insert(key.to_string(), expected);
+            }
+        }
+
+        Some(condition)
+    }
+
+    /// Returns the reason why the condition is not met, or `None` if all
+    /// the parts of the condition are satisfied.
+    pub fn unmet_reason(&self) -> Option<String> {
+        let current_os = std::env::consts::OS;
+        if !self.os.is_empty() && !self.os.iter().any(|os| normalize_os(os) == current_os) {
+            return Some(format!(
+                "os is {}, not {}",
+                current_os,
+                self.os.join(" or ")
+            ));
+        }
+
+        let current_arch = std::env::consts::ARCH;
+        if !self.arch.is_empty()
+            && !self
+                .arch
+                .iter()
+                .any(|arch| normalize_arch(arch) == current_arch)
+        {
+            return Some(format!(
+                "arch is {}, not {}",
+                current_arch,
+                self.arch.join(" or ")
+            ));
+        }
+
+        for (key, expected) in self.env.iter() {
+            let value = std::env::var(key).ok();
+            match (expected, value) {
+                (UpConfigConditionEnv::Present(true), None) => {
+                    return Some(format!("${} is not set", key));
+                }
+                (UpConfigConditionEnv::Present(false), Some(_)) => {
+                    return Some(format!("${} is set", key));
+                }
+                (UpConfigConditionEnv::Equals(expected), value)
+                    if value.as_ref() != Some(expected) =>
+                {
+                    return Some(format!("${} is not '{}'", key, expected));
+                }
+                _ => {}
+            }
+        }
+
+        for command in self.command.iter() {
+            if !command_exists(command) {
+                return Some(format!("{} not found in PATH", command));
+            }
+        }
+
+        None
+    }
+}
+
+fn string_or_list(config_value: Option<ConfigValue>) -> Vec<String> {
+    let mut values = Vec::new();
+    if let Some(config_value) = config_value {
+        if let Some(array) = config_value.as_array() {
+            for value in array {
+                if let Some(value) = value.as_str_forced() {
+                    values.push(value);
+                }
+            }
+        } else if let Some(value) = config_value.as_str_forced() {
+            values.push(value);
+        }
+    }
+    values
+}
+
+/// Normalizes the name of an operating system to the name used by
+/// rust, so that common alternative names can be used in the config.
+fn normalize_os(os: &str) -> String {
+    match os.to_lowercase().as_str() {
+        "darwin" | "macos" | "osx" | "mac" => "macos".to_string(),
+        os => os.to_string(),
+    }
+}
+
+/// Normalizes the name of an architecture to the name used by rust,
+/// so that common alternative names can be used in the config.
+fn normalize_arch(arch: &str) -> String {
+    match arch.to_lowercase().as_str() {
+        "amd64" | "x64" | "x86_64" => "x86_64".to_string(),
+        "arm64" | "aarch64" => "aarch64".to_string(),
+        arch => arch.to_string(),
+    }
+}
@@ -1,7 +1,18 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
 use serde::Deserialize;
 use serde::Serialize;
+use time::OffsetDateTime;
 use tokio::process::Command as TokioCommand;
 
+use crate::internal::cache::UpEnvironment;
+use crate::internal::cache::UpEnvironments;
+use crate::internal::config::up::plan::UpPlanItem;
+use crate::internal::config::up::transaction::record_change;
+use crate::internal::config::up::transaction::UpChange;
+use crate::internal::config::up::utils::run_command_with_handler;
 use crate::internal::config::up::utils::run_progress;
 use crate::internal::config::up::utils::PrintProgressHandler;
 use crate::internal::config::up::utils::ProgressHandler;
@@ -10,8 +21,14 @@ use crate::internal::config::up::utils::SpinnerProgressHandler;
 use crate::internal::config::up::UpError;
 use crate::internal::config::ConfigValue;
 use crate::internal::user_interface::StringColor;
+use crate::internal::workdir;
+use crate::internal::Cache;
 use crate::internal::ENV;
 
+/// The environment variable pointing to the file in which the `meet`
+/// command can write the values of the outputs of the step.
+const OUTPUT_FILE_ENV_VAR: &str = "OMNI_UP_OUTPUT";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UpConfigCustom {
     pub meet: String,
@@ -19,6 +36,11 @@ pub struct UpConfigCustom {
     pub unmeet: Option<String>,
     pub name: Option<String>,
     pub dir: Option<String>,
+    pub retries: u64,
+    pub retry_delay: u64,
+    pub timeout: Option<u64>,
+    pub shell: Option<String>,
+    pub outputs: Vec<String>,
 }
 
 impl UpConfigCustom {
@@ -28,6 +50,11 @@ impl UpConfigCustom {
         let mut unmeet = None;
         let mut name = None;
         let mut dir = None;
+        let mut retries = 0;
+        let mut retry_delay = 1;
+        let mut timeout = None;
+        let mut shell = None;
+        let mut outputs = Vec::new();
 
         if let Some(config_value) = config_value {
             if let Some(value) = config_value.get_as_str_forced("meet") {
@@ -45,6 +72,28 @@ impl UpConfigCustom {
             if let Some(value) = config_value.get_as_str_forced("dir") {
                 dir = Some(value.to_string());
             }
+            if let Some(value) = config_value.get_as_unsigned_integer("retries") {
+                retries = value;
+            }
+            if let Some(value) = config_value.get_as_unsigned_integer("retry_delay") {
+                retry_delay = value;
+            }
+            if let Some(value) = config_value.get_as_unsigned_integer("timeout") {
+                timeout = Some(value);
+            }
+            if let Some(value) = config_value
+                .get_as_str_forced("shell")
+                .or_else(|| config_value.get_as_str_forced("interpreter"))
+            {
+                shell = Some(value.to_string());
+            }
+            if let Some(value) = config_value.get("outputs") {
+                if let Some(array) = value.as_array() {
+                    outputs.extend(array.iter().filter_map(|value| value.as_str_forced()));
+                } else if let Some(value) = value.as_str_forced() {
+                    outputs.push(value);
+                }
+            }
         }
 
         if meet == None {
@@ -57,6 +106,11 @@ impl UpConfigCustom {
             unmeet: unmeet,
             name: name,
             dir: dir,
+            retries: retries,
+            retry_delay: retry_delay,
+            timeout: timeout,
+            shell: shell,
+            outputs: outputs,
         }
     }
 
@@ -102,6 +156,7 @@ impl UpConfigCustom {
             });
             return Err(err);
         }
+        record_change(UpChange::Custom(self.clone()));
 
         progress_handler
             .clone()
@@ -161,21 +216,150 @@ impl UpConfigCustom {
         Ok(())
     }
 
+    /// Returns whether it can be verified that the step is met, which
+    /// requires a `met?` command and a trusted repository to run it.
+    pub fn can_check(&self, trusted: bool) -> bool {
+        self.met.is_some() && trusted
+    }
+
+    /// Returns the changes that `up` would make; the `met?` command is
+    /// only evaluated when the repository is trusted, as it could run
+    /// anything.
+    pub fn plan_up(&self, trusted: bool) -> Vec<UpPlanItem> {
+        if self.met.is_none() {
+            return vec![UpPlanItem::run("meet command")];
+        }
+
+        if !trusted {
+            return vec![UpPlanItem::run("meet command")
+                .with_detail("met? not evaluated, repository not trusted")];
+        }
+
+        if self.met().unwrap_or(false) {
+            vec![UpPlanItem::skip("meet command", "already met")]
+        } else {
+            vec![UpPlanItem::run("meet command").with_detail("not met")]
+        }
+    }
+
+    pub fn plan_down(&self, trusted: bool) -> Vec<UpPlanItem> {
+        if self.unmeet.is_none() {
+            return vec![];
+        }
+
+        if self.met.is_some() && !trusted {
+            return vec![UpPlanItem::run("unmeet command")
+                .with_detail("met? not evaluated, repository not trusted")];
+        }
+
+        if !self.met().unwrap_or(true) {
+            vec![UpPlanItem::skip("unmeet command", "not met")]
+        } else {
+            vec![UpPlanItem::run("unmeet command")]
+        }
+    }
+
     fn met(&self) -> Option<bool> {
         if let Some(met) = &self.met {
-            let mut command = std::process::Command::new("bash");
-            command.arg("-c");
-            command.arg(met);
-            command.stdout(std::process::Stdio::null());
-            command.stderr(std::process::Stdio::null());
-
-            let output = command.output().unwrap();
-            Some(output.status.success())
+            let mut command = self.command(met);
+            command.stdout(std::process::Stdio::piped());
+            command.stderr(std::process::Stdio::piped());
+
+            Some(run_command_with_handler(&mut command, |_, _| {}, self.run_config()).is_ok())
         } else {
             None
         }
     }
 
+    /// Returns the command to run the given code with the shell of the
+    /// step, which can be any interpreter accepting code as parameter
+    /// (e.g. `zsh`, `python3`, `node`), with its own arguments if any;
+    /// defaults to `bash`.
+    fn command(&self, code: &str) -> TokioCommand {
+        let mut shell = match &self.shell {
+            Some(shell) => shell_words::split(shell).unwrap_or_else(|_| vec![shell.clone()]),
+            None => vec![],
+        };
+        if shell.is_empty() {
+            shell.push("bash".to_string());
+        }
+
+        let interpreter = Path::new(&shell[0])
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let code_arg = match interpreter.as_str() {
+            "node" | "nodejs" | "ruby" | "perl" | "deno" => "-e",
+            _ => "-c",
+        };
+
+        let mut command = TokioCommand::new(&shell[0]);
+        command.args(&shell[1..]);
+        command.arg(code_arg);
+        command.arg(code);
+        command
+    }
+
+    fn run_config(&self) -> RunConfig {
+        match self.timeout {
+            Some(timeout) => RunConfig::with_timeout(timeout),
+            None => RunConfig::default(),
+        }
+    }
+
+    /// Runs the given code, retrying it up to the number of retries of
+    /// the step if it fails; the delay between two attempts doubles
+    /// after each failed attempt.
+    fn run_with_retries(
+        &self,
+        code: &str,
+        output_file: Option<&Path>,
+        progress_handler: Option<Box<&dyn ProgressHandler>>,
+    ) -> Result<(), UpError> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+
+            let mut command = self.command(code);
+            if let Some(output_file) = output_file {
+                command.env(OUTPUT_FILE_ENV_VAR, output_file);
+            }
+            command.stdout(std::process::Stdio::piped());
+            command.stderr(std::process::Stdio::piped());
+
+            let err = match run_progress(&mut command, progress_handler.clone(), self.run_config())
+            {
+                Ok(_) => return Ok(()),
+                Err(err) => err,
+            };
+
+            if attempt > self.retries {
+                return Err(match err {
+                    UpError::Exec(message) if attempt > 1 => {
+                        UpError::Exec(format!("{} (failed {} times)", message, attempt))
+                    }
+                    UpError::Timeout(message) if attempt > 1 => {
+                        UpError::Timeout(format!("{} (failed {} times)", message, attempt))
+                    }
+                    err => err,
+                });
+            }
+
+            let delay = self
+                .retry_delay
+                .saturating_mul(2u64.saturating_pow(attempt as u32 - 1));
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.progress(format!(
+                    "attempt {}/{} failed, retrying in {}s",
+                    attempt,
+                    self.retries + 1,
+                    delay,
+                ))
+            });
+            std::thread::sleep(Duration::from_secs(delay));
+        }
+    }
+
     fn meet(&self, progress_handler: Option<Box<&dyn ProgressHandler>>) -> Result<(), UpError> {
         if self.meet != "" {
             // eprintln!("{}", format!("$ {}", self.meet).light_black());
@@ -183,13 +367,91 @@ impl UpConfigCustom {
                 progress_handler.progress("running (meet) command".to_string())
             });
 
-            let mut command = TokioCommand::new("bash");
-            command.arg("-c");
-            command.arg(&self.meet);
-            command.stdout(std::process::Stdio::piped());
-            command.stderr(std::process::Stdio::piped());
+            if self.outputs.is_empty() {
+                self.run_with_retries(&self.meet, None, progress_handler.clone())?;
+            } else {
+                let output_file = tempfile::NamedTempFile::new().map_err(|err| {
+                    UpError::Exec(format!("failed to create output file: {}", err))
+                })?;
+                self.run_with_retries(
+                    &self.meet,
+                    Some(output_file.path()),
+                    progress_handler.clone(),
+                )?;
+                self.store_outputs(output_file.path(), progress_handler.clone())?;
+            }
+        }
+
+        Ok(())
+    }
 
-            run_progress(&mut command, progress_handler.clone(), RunConfig::default())?;
+    /// Reads the `KEY=value` lines written by the `meet` command to the
+    /// output file, and stores the values of the declared outputs in the
+    /// up environment of the repository; other keys are ignored.
+    fn store_outputs(
+        &self,
+        output_file: &Path,
+        progress_handler: Option<Box<&dyn ProgressHandler>>,
+    ) -> Result<(), UpError> {
+        let contents = std::fs::read_to_string(output_file)
+            .map_err(|err| UpError::Exec(format!("failed to read output file: {}", err)))?;
+
+        let mut values = HashMap::new();
+        for line in contents.lines() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value),
+                None => {
+                    return Err(UpError::Exec(format!(
+                        "invalid output line '{}', expected KEY=value",
+                        line
+                    )))
+                }
+            };
+
+            if self.outputs.iter().any(|output| output == key) {
+                values.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        if values.is_empty() {
+            return Ok(());
+        }
+
+        progress_handler.clone().map(|progress_handler| {
+            progress_handler.progress(format!(
+                "setting {}",
+                values.keys().cloned().collect::<Vec<_>>().join(", ")
+            ))
+        });
+
+        let result = Cache::exclusive(|cache| {
+            let repo_id = match workdir(".").id() {
+                Some(repo_id) => repo_id,
+                None => return false,
+            };
+
+            let mut up_env = HashMap::new();
+            if let Some(up_cache) = &cache.up_environments {
+                up_env = up_cache.env.clone();
+            }
+            let repo_up_env = up_env.entry(repo_id).or_insert_with(UpEnvironment::new);
+            repo_up_env.env_vars.extend(values.clone());
+
+            cache.up_environments = Some(UpEnvironments {
+                env: up_env.clone(),
+                updated_at: OffsetDateTime::now_utc(),
+            });
+
+            true
+        });
+
+        if let Err(err) = result {
+            return Err(UpError::Exec(format!("failed to update cache: {}", err)));
         }
 
         Ok(())
@@ -202,13 +464,7 @@ impl UpConfigCustom {
                 progress_handler.progress("running (unmeet) command".to_string())
             });
 
-            let mut command = TokioCommand::new("bash");
-            command.arg("-c");
-            command.arg(unmeet);
-            command.stdout(std::process::Stdio::piped());
-            command.stderr(std::process::Stdio::piped());
-
-            run_progress(&mut command, progress_handler.clone(), RunConfig::default())?;
+            self.run_with_retries(unmeet, None, progress_handler.clone())?;
         }
 
         Ok(())
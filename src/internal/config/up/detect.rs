@@ -0,0 +1,295 @@
+use std::path::Path;
+
+use serde_yaml::Mapping;
+use serde_yaml::Value;
+
+use crate::internal::config::ConfigSource;
+use crate::internal::config::ConfigValue;
+
+/// The tools that have their own step, with the name used for them
+/// by asdf and the name of the step to use to install them.
+static ASDF_TOOL_STEPS: &[(&str, &str)] = &[
+    ("ruby", "ruby"),
+    ("nodejs", "node"),
+    ("python", "python"),
+    ("golang", "go"),
+    ("rust", "rust"),
+    ("java", "java"),
+    ("kotlin", "kotlin"),
+    ("bash", "bash"),
+];
+
+/// Infers the `up` configuration of a repository from the files found
+/// at the root of its work directory, e.g. a `Gemfile` requires ruby
+/// and bundler; returns `None` if nothing could be detected. This needs
+/// to be called from the root of the work directory.
+pub fn detect_up_config() -> Option<ConfigValue> {
+    let tool_versions = tool_versions();
+    let mut steps = Vec::new();
+
+    if Path::new("Brewfile").exists() {
+        steps.push(Value::String("homebrew".to_string()));
+    }
+
+    if Path::new("Gemfile").exists() {
+        steps.push(asdf_step("ruby", &tool_versions));
+        steps.push(Value::String("bundler".to_string()));
+    }
+
+    if Path::new("package.json").exists() {
+        steps.push(asdf_step("nodejs", &tool_versions));
+        steps.push(Value::String(node_packages_step_name().to_string()));
+    }
+
+    if let Some(step) = python_step(&tool_versions) {
+        steps.push(step);
+    }
+
+    if Path::new("go.mod").exists() {
+        steps.push(step_with_params(
+            "go",
+            vec![("version_file", Value::String("go.mod".to_string()))],
+        ));
+    } else if has_version_file("golang", &tool_versions) {
+        steps.push(asdf_step("golang", &tool_versions));
+    }
+
+    if let Some(step) = rust_step(&tool_versions) {
+        steps.push(step);
+    }
+
+    // Any other tool in the .tool-versions file gets installed with
+    // the step dedicated to it if any, or with the generic asdf step
+    for tool in tool_versions.iter() {
+        if ["ruby", "nodejs", "python", "golang", "rust"].contains(&tool.as_str()) {
+            continue;
+        }
+
+        match ASDF_TOOL_STEPS
+            .iter()
+            .find(|(asdf_tool, _)| asdf_tool == tool)
+        {
+            Some(_) => steps.push(asdf_step(tool, &tool_versions)),
+            None => steps.push(step_with_params(
+                "asdf",
+                vec![
+                    ("tool", Value::String(tool.clone())),
+                    ("version", Value::String("auto".to_string())),
+                ],
+            )),
+        }
+    }
+
+    if steps.is_empty() {
+        return None;
+    }
+
+    Some(ConfigValue::from_value(
+        ConfigSource::Null,
+        vec![],
+        Value::Sequence(steps),
+    ))
+}
+
+/// Returns the tools listed in the `.tool-versions` file, in the order
+/// in which they are listed.
+fn tool_versions() -> Vec<String> {
+    let contents = match std::fs::read_to_string(".tool-versions") {
+        Ok(contents) => contents,
+        Err(_) => return vec![],
+    };
+
+    let mut tools = Vec::new();
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if let Some(tool) = line.split_whitespace().next() {
+            if !tools.contains(&tool.to_string()) {
+                tools.push(tool.to_string());
+            }
+        }
+    }
+    tools
+}
+
+/// Returns whether a version file that the `auto` version of the step
+/// would detect exists for the given tool.
+fn has_version_file(tool: &str, tool_versions: &[String]) -> bool {
+    if tool_versions.iter().any(|listed| listed == tool) {
+        return true;
+    }
+
+    let prefixes = match tool {
+        "golang" => vec!["go", "golang"],
+        "nodejs" => vec!["node", "nodejs"],
+        _ => vec![tool],
+    };
+    if prefixes
+        .iter()
+        .any(|prefix| Path::new(&format!(".{}-version", prefix)).exists())
+    {
+        return true;
+    }
+
+    tool == "nodejs" && package_json_has_node_engine()
+}
+
+fn package_json_has_node_engine() -> bool {
+    let contents = match std::fs::read_to_string("package.json") {
+        Ok(contents) => contents,
+        Err(_) => return false,
+    };
+    let package_json: serde_json::Value = match serde_json::from_str(&contents) {
+        Ok(package_json) => package_json,
+        Err(_) => return false,
+    };
+    package_json
+        .get("engines")
+        .and_then(|engines| engines.get("node"))
+        .is_some()
+}
+
+/// Returns the step installing the given asdf tool, using the versions
+/// from the version files if there are any, or the latest version.
+fn asdf_step(tool: &str, tool_versions: &[String]) -> Value {
+    let step_name = ASDF_TOOL_STEPS
+        .iter()
+        .find(|(asdf_tool, _)| *asdf_tool == tool)
+        .map(|(_, step_name)| *step_name)
+        .unwrap_or(tool);
+
+    if has_version_file(tool, tool_versions) {
+        step_with_value(step_name, Value::String("auto".to_string()))
+    } else {
+        Value::String(step_name.to_string())
+    }
+}
+
+/// Returns the name of the step to install the node dependencies,
+/// depending on the lockfile found.
+fn node_packages_step_name() -> &'static str {
+    if Path::new("pnpm-lock.yaml").exists() {
+        "pnpm"
+    } else if Path::new("yarn.lock").exists() {
+        "yarn"
+    } else {
+        "npm"
+    }
+}
+
+fn python_step(tool_versions: &[String]) -> Option<Value> {
+    let has_requirements = std::fs::read_dir(".")
+        .map(|entries| {
+            entries.flatten().any(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                file_name.starts_with("requirements") && file_name.ends_with(".txt")
+            })
+        })
+        .unwrap_or(false);
+
+    let packages_tool = if Path::new("uv.lock").exists() {
+        Some("uv")
+    } else if Path::new("poetry.lock").exists() {
+        Some("poetry")
+    } else if Path::new("Pipfile").exists() {
+        Some("pipenv")
+    } else if has_requirements || Path::new("pyproject.toml").exists() {
+        Some("pip")
+    } else {
+        None
+    };
+
+    if packages_tool.is_none() && !has_version_file("python", tool_versions) {
+        return None;
+    }
+
+    // The python step does not support detecting the version from the
+    // version files, so we read the version directly from them
+    let mut params = Vec::new();
+    if let Some(version) =
+        read_version_file(".python-version").or_else(|| read_tool_versions_version("python"))
+    {
+        params.push(("version", Value::String(version)));
+    }
+    if let Some(packages_tool) = packages_tool {
+        params.push((packages_tool, Value::String("auto".to_string())));
+    }
+
+    if params.is_empty() {
+        return Some(Value::String("python".to_string()));
+    }
+
+    Some(step_with_params("python", params))
+}
+
+fn rust_step(tool_versions: &[String]) -> Option<Value> {
+    if has_version_file("rust", tool_versions) {
+        return Some(asdf_step("rust", tool_versions));
+    }
+
+    let channel = match ["rust-toolchain.toml", "rust-toolchain"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+    {
+        Some(contents) => toolchain_channel(&contents),
+        None => return None,
+    };
+
+    // Only numeric channels can be installed as a specific version,
+    // other channels (e.g. stable) are installed as the latest version
+    if channel.starts_with(|c: char| c.is_ascii_digit()) {
+        Some(step_with_value("rust", Value::String(channel)))
+    } else {
+        Some(Value::String("rust".to_string()))
+    }
+}
+
+/// Returns the channel of a rust toolchain file, which can either be
+/// a toml file with a `channel` key in its `[toolchain]` section, or,
+/// for the legacy format, only contain the channel.
+fn toolchain_channel(contents: &str) -> String {
+    for line in contents.lines() {
+        let line = line.trim();
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim() == "channel" {
+                return value.trim().trim_matches('"').to_string();
+            }
+        } else if !line.is_empty() && !line.starts_with('[') && !line.starts_with('#') {
+            return line.to_string();
+        }
+    }
+    "".to_string()
+}
+
+fn read_version_file(path: &str) -> Option<String> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let version = contents.lines().next()?.trim();
+    if version.is_empty() {
+        return None;
+    }
+    Some(version.to_string())
+}
+
+fn read_tool_versions_version(tool: &str) -> Option<String> {
+    let contents = std::fs::read_to_string(".tool-versions").ok()?;
+    contents.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        if parts.next()? != tool {
+            return None;
+        }
+        parts.next().map(|version| version.to_string())
+    })
+}
+
+fn step_with_value(name: &str, value: Value) -> Value {
+    let mut step = Mapping::new();
+    step.insert(Value::String(name.to_string()), value);
+    Value::Mapping(step)
+}
+
+fn step_with_params(name: &str, params: Vec<(&str, Value)>) -> Value {
+    let mut mapping = Mapping::new();
+    for (key, value) in params {
+        mapping.insert(Value::String(key.to_string()), value);
+    }
+    step_with_value(name, Value::Mapping(mapping))
+}
@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use time::OffsetDateTime;
+use walkdir::WalkDir;
+
+use crate::internal::cache::HomebrewInstalled;
+use crate::internal::cache::HomebrewTapped;
+use crate::internal::cache::UpEnvironment;
+use crate::internal::cache::UpEnvironments;
+use crate::internal::config::up::homebrew::HomebrewInstall;
+use crate::internal::config::up::homebrew::HomebrewTap;
+use crate::internal::config::up::python::VENV_PATH;
+use crate::internal::config::up::utils::PrintProgressHandler;
+use crate::internal::config::up::utils::ProgressHandler;
+use crate::internal::config::up::utils::SpinnerProgressHandler;
+use crate::internal::config::up::UpConfigAsdfBase;
+use crate::internal::config::up::UpError;
+use crate::internal::config::up::UpPlanItem;
+use crate::internal::config::up::ASDF_PATH;
+use crate::internal::user_interface::StringColor;
+use crate::internal::workdir;
+use crate::internal::Cache;
+use crate::internal::ENV;
+
+/// A resource installed by `omni up` that is not referenced by any
+/// repository anymore.
+#[derive(Debug, Clone)]
+pub enum UpGcResource {
+    AsdfVersion { tool: String, version: String },
+    PythonVenv { path: PathBuf },
+    HomebrewInstall(HomebrewInstalled),
+    HomebrewTap(HomebrewTapped),
+}
+
+#[derive(Debug, Clone)]
+pub struct UpGcItem {
+    pub resource: UpGcResource,
+    pub size: Option<u64>,
+}
+
+impl UpGcItem {
+    fn subject(&self) -> String {
+        match &self.resource {
+            UpGcResource::AsdfVersion { tool, version } => format!("{} {}", tool, version),
+            UpGcResource::PythonVenv { path } => format!("venv {}", path.display()),
+            UpGcResource::HomebrewInstall(install) => format!(
+                "brew {}{}{}",
+                if install.cask { "cask " } else { "" },
+                install.name,
+                if let Some(version) = &install.version {
+                    format!("@{}", version)
+                } else {
+                    "".to_string()
+                }
+            ),
+            UpGcResource::HomebrewTap(tap) => format!("brew tap {}", tap.name),
+        }
+    }
+
+    fn remove(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
+        match &self.resource {
+            UpGcResource::AsdfVersion { tool, version } => {
+                UpConfigAsdfBase::uninstall_version(tool, version, progress)
+            }
+            UpGcResource::PythonVenv { path } => remove_venv(path, progress),
+            UpGcResource::HomebrewInstall(install) => {
+                HomebrewInstall::from_cache(install).remove(progress)
+            }
+            UpGcResource::HomebrewTap(tap) => HomebrewTap::from_name(&tap.name).remove(progress),
+        }
+    }
+}
+
+/// The up environments of repositories that do not exist anymore, and
+/// the tool versions, venvs and homebrew packages that are not referenced
+/// by any of the remaining repositories, across all repositories.
+#[derive(Debug, Clone, Default)]
+pub struct UpGc {
+    pub stale_environments: Vec<(String, String)>,
+    pub items: Vec<UpGcItem>,
+}
+
+impl UpGc {
+    /// Identifies what can be garbage collected from the cache and the
+    /// resources currently installed on the system.
+    pub fn new() -> Self {
+        let cache = Cache::shared().unwrap_or_else(|_| Cache::new_empty());
+        let up_env = cache
+            .up_environments
+            .as_ref()
+            .map(|up_cache| up_cache.env.clone())
+            .unwrap_or_default();
+
+        // The environments of repositories that do not exist anymore
+        // are stale; the environments for which we do not know the path
+        // are considered as still in use
+        let mut stale_environments = Vec::new();
+        let mut live_environments = HashMap::new();
+        for (repo_id, repo_up_env) in up_env.iter() {
+            if is_stale(repo_id, repo_up_env) {
+                stale_environments.push((repo_id.clone(), repo_up_env.path.clone()));
+            } else {
+                live_environments.insert(repo_id.clone(), repo_up_env.clone());
+            }
+        }
+        stale_environments.sort();
+
+        let is_live = |required_by: &Vec<String>| {
+            required_by
+                .iter()
+                .any(|repo_id| live_environments.contains_key(repo_id))
+        };
+
+        let mut items = Vec::new();
+
+        // The asdf installs that are not loaded by any repository, and not
+        // required by any repository as far as the cache knows
+        let mut referenced_versions = HashSet::new();
+        for repo_up_env in live_environments.values() {
+            for version in repo_up_env.versions.iter() {
+                referenced_versions.insert((version.tool.clone(), version.version.clone()));
+            }
+        }
+        if let Some(asdf_cache) = &cache.asdf_operation {
+            for installed in asdf_cache.installed.iter() {
+                if is_live(&installed.required_by) {
+                    referenced_versions.insert((installed.tool.clone(), installed.version.clone()));
+                }
+            }
+        }
+        for (tool, version, path) in list_subdirs(&PathBuf::from(&*ASDF_PATH).join("installs")) {
+            if !referenced_versions.contains(&(tool.clone(), version.clone())) {
+                items.push(UpGcItem {
+                    resource: UpGcResource::AsdfVersion { tool, version },
+                    size: Some(dir_size(&path)),
+                });
+            }
+        }
+
+        // The venvs that are not used by any repository
+        let referenced_venvs = live_environments
+            .values()
+            .filter_map(|repo_up_env| repo_up_env.env_vars.get("VIRTUAL_ENV"))
+            .map(PathBuf::from)
+            .collect::<HashSet<_>>();
+        for path in list_venvs(&PathBuf::from(&*VENV_PATH)) {
+            // Never remove a venv that is, contains, or is contained in a
+            // venv that is still referenced
+            let referenced = referenced_venvs
+                .iter()
+                .any(|venv| venv.starts_with(&path) || path.starts_with(venv));
+            if !referenced {
+                items.push(UpGcItem {
+                    resource: UpGcResource::PythonVenv { path: path.clone() },
+                    size: Some(dir_size(&path)),
+                });
+            }
+        }
+
+        // The homebrew formulae, casks and taps that omni installed and
+        // that are not required by any repository; the ones that were
+        // already there before omni are left alone
+        if let Some(brew_cache) = &cache.homebrew_operation {
+            let brew_prefix = homebrew_prefix();
+            for install in brew_cache.installed.iter() {
+                if install.installed && !is_live(&install.required_by) {
+                    items.push(UpGcItem {
+                        resource: UpGcResource::HomebrewInstall(install.clone()),
+                        size: brew_prefix
+                            .as_ref()
+                            .map(|prefix| homebrew_install_size(prefix, install)),
+                    });
+                }
+            }
+            for tap in brew_cache.tapped.iter() {
+                if tap.tapped && !is_live(&tap.required_by) {
+                    items.push(UpGcItem {
+                        resource: UpGcResource::HomebrewTap(tap.clone()),
+                        size: None,
+                    });
+                }
+            }
+        }
+
+        Self {
+            stale_environments,
+            items,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stale_environments.is_empty() && self.items.is_empty()
+    }
+
+    /// Returns the total size of the resources that would be removed.
+    pub fn size(&self) -> u64 {
+        self.items.iter().filter_map(|item| item.size).sum()
+    }
+
+    pub fn print(&self) {
+        if !self.stale_environments.is_empty() {
+            eprintln!("{}", "environments:".to_string().light_blue());
+            for (repo_id, path) in self.stale_environments.iter() {
+                let item = UpPlanItem::remove(repo_id).with_detail(&format!("{} not found", path));
+                eprintln!("  {}", item.format());
+            }
+        }
+
+        if !self.items.is_empty() {
+            eprintln!("{}", "resources:".to_string().light_blue());
+            for item in self.items.iter() {
+                let plan_item = UpPlanItem::remove(&item.subject());
+                let plan_item = match item.size {
+                    Some(size) => plan_item.with_detail(&format_size(size)),
+                    None => plan_item,
+                };
+                eprintln!("  {}", plan_item.format());
+            }
+        }
+    }
+
+    /// Removes the unused resources and forgets about the stale
+    /// environments; returns the total size of the resources that
+    /// were removed.
+    pub fn apply(&self) -> Result<u64, UpError> {
+        let num_items = self.items.len();
+        let mut removed = Vec::new();
+        let mut errors = Vec::new();
+        for (idx, item) in self.items.iter().enumerate() {
+            match item.remove(Some((idx + 1, num_items))) {
+                Ok(()) => removed.push(item.clone()),
+                // The tap is kept, and will be removed once the formulae
+                // installed from it are not around anymore
+                Err(UpError::HomebrewTapInUse) => {}
+                Err(err) => errors.push(format!("{}: {}", item.subject(), err)),
+            }
+        }
+
+        let stale_ids = self
+            .stale_environments
+            .iter()
+            .map(|(repo_id, _)| repo_id.clone())
+            .collect::<HashSet<_>>();
+
+        if let Err(err) = Cache::exclusive(|cache| {
+            if let Some(up_cache) = &cache.up_environments {
+                let mut up_env = up_cache.env.clone();
+                up_env.retain(|repo_id, _| !stale_ids.contains(repo_id));
+                cache.up_environments = Some(UpEnvironments {
+                    env: up_env,
+                    updated_at: OffsetDateTime::now_utc(),
+                });
+            }
+
+            if let Some(asdf_cache) = &mut cache.asdf_operation {
+                for installed in asdf_cache.installed.iter_mut() {
+                    installed.required_by.retain(|id| !stale_ids.contains(id));
+                }
+                asdf_cache.installed.retain(|installed| {
+                    !installed.required_by.is_empty()
+                        && !removed.iter().any(|item| match &item.resource {
+                            UpGcResource::AsdfVersion { tool, version } => {
+                                installed.tool == *tool && installed.version == *version
+                            }
+                            _ => false,
+                        })
+                });
+                asdf_cache.updated_at = OffsetDateTime::now_utc();
+            }
+
+            if let Some(brew_cache) = &mut cache.homebrew_operation {
+                for install in brew_cache.installed.iter_mut() {
+                    install.required_by.retain(|id| !stale_ids.contains(id));
+                }
+                brew_cache.installed.retain(|install| {
+                    (install.installed || !install.required_by.is_empty())
+                        && !removed.iter().any(|item| match &item.resource {
+                            UpGcResource::HomebrewInstall(removed) => {
+                                install.name == removed.name && install.version == removed.version
+                            }
+                            _ => false,
+                        })
+                });
+
+                for tap in brew_cache.tapped.iter_mut() {
+                    tap.required_by.retain(|id| !stale_ids.contains(id));
+                }
+                brew_cache.tapped.retain(|tap| {
+                    (tap.tapped || !tap.required_by.is_empty())
+                        && !removed.iter().any(|item| match &item.resource {
+                            UpGcResource::HomebrewTap(removed) => tap.name == removed.name,
+                            _ => false,
+                        })
+                });
+                brew_cache.updated_at = OffsetDateTime::now_utc();
+            }
+
+            if let Some(system_cache) = &mut cache.system_package_operation {
+                for install in system_cache.installed.iter_mut() {
+                    install.required_by.retain(|id| !stale_ids.contains(id));
+                }
+            }
+
+            true
+        }) {
+            errors.push(format!("failed to update cache: {}", err));
+        }
+
+        if !errors.is_empty() {
+            return Err(UpError::Exec(errors.join(", ")));
+        }
+
+        Ok(removed.iter().filter_map(|item| item.size).sum())
+    }
+}
+
+/// Returns whether the repository for which the up environment was
+/// recorded does not exist at its path anymore.
+fn is_stale(repo_id: &str, repo_up_env: &UpEnvironment) -> bool {
+    if repo_up_env.path.is_empty() {
+        return false;
+    }
+
+    let path = Path::new(&repo_up_env.path);
+    if !path.is_dir() {
+        return true;
+    }
+
+    workdir(&repo_up_env.path).id().as_deref() != Some(repo_id)
+}
+
+/// Lists the directories two levels down from the given path, as
+/// `(parent name, name, path)`, e.g. `installs/<tool>/<version>`.
+fn list_subdirs(path: &Path) -> Vec<(String, String, PathBuf)> {
+    let mut subdirs = Vec::new();
+
+    let parents = match std::fs::read_dir(path) {
+        Ok(parents) => parents,
+        Err(_) => return subdirs,
+    };
+
+    for parent in parents.flatten() {
+        let parent_path = parent.path();
+        if !parent_path.is_dir() {
+            continue;
+        }
+
+        let children = match std::fs::read_dir(&parent_path) {
+            Ok(children) => children,
+            Err(_) => continue,
+        };
+
+        for child in children.flatten() {
+            let child_path = child.path();
+            if !child_path.is_dir() {
+                continue;
+            }
+
+            subdirs.push((
+                parent.file_name().to_string_lossy().to_string(),
+                child.file_name().to_string_lossy().to_string(),
+                child_path,
+            ));
+        }
+    }
+
+    subdirs.sort();
+    subdirs
+}
+
+/// Lists the venvs found under the given path, identified by their
+/// `pyvenv.cfg` file, without looking inside of the venvs themselves.
+fn list_venvs(path: &Path) -> Vec<PathBuf> {
+    let mut venvs = Vec::new();
+
+    let mut walker = WalkDir::new(path).min_depth(1).into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+
+        if entry.path().join("pyvenv.cfg").is_file() {
+            venvs.push(entry.path().to_path_buf());
+            walker.skip_current_dir();
+        }
+    }
+
+    venvs.sort();
+    venvs
+}
+
+fn dir_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn homebrew_prefix() -> Option<PathBuf> {
+    let mut brew_prefix = std::process::Command::new("brew");
+    brew_prefix.arg("--prefix");
+    brew_prefix.stdout(std::process::Stdio::piped());
+    brew_prefix.stderr(std::process::Stdio::null());
+
+    let output = brew_prefix.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let prefix = String::from_utf8(output.stdout).ok()?;
+    Some(PathBuf::from(prefix.trim()))
+}
+
+fn homebrew_install_size(prefix: &Path, install: &HomebrewInstalled) -> u64 {
+    // Formulae from taps are referred to by their full name, but are
+    // installed in a directory named after the formula only
+    let name = install.name.rsplit('/').next().unwrap_or(&install.name);
+    let name = match &install.version {
+        Some(version) => format!("{}@{}", name, version),
+        None => name.to_string(),
+    };
+
+    let dir = if install.cask { "Caskroom" } else { "Cellar" };
+    dir_size(&prefix.join(dir).join(name))
+}
+
+fn remove_venv(path: &Path, progress: Option<(usize, usize)>) -> Result<(), UpError> {
+    let desc = format!("remove venv {}:", path.display()).light_yellow();
+    let progress_handler: Box<dyn ProgressHandler> = if ENV.interactive_shell {
+        Box::new(SpinnerProgressHandler::new(desc, progress))
+    } else {
+        Box::new(PrintProgressHandler::new(desc, progress))
+    };
+
+    if let Err(err) = std::fs::remove_dir_all(path) {
+        let msg = format!("failed to remove venv {}: {}", path.display(), err);
+        progress_handler.error_with_message(msg.clone());
+        return Err(UpError::Exec(msg));
+    }
+
+    // Cleanup the parent directories, up to the version directory, if
+    // they do not contain any other venv
+    let venv_path = PathBuf::from(&*VENV_PATH);
+    for parent in path.ancestors().skip(1) {
+        if !parent.starts_with(&venv_path) || parent == venv_path {
+            break;
+        }
+        if std::fs::remove_dir(parent).is_err() {
+            break;
+        }
+    }
+
+    progress_handler.success_with_message("removed".to_string().light_green());
+
+    Ok(())
+}
+
+/// Formats a size in bytes in a human readable way, e.g. `1.3 GiB`.
+pub fn format_size(size: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", size, units[unit])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
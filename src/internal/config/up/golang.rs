@@ -1,21 +1,53 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::path::Path;
 use std::path::PathBuf;
 
+use lazy_static::lazy_static;
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::process::Command as TokioCommand;
 
+use crate::internal::cache::UpEnvironment;
+use crate::internal::cache::UpEnvironments;
 use crate::internal::commands::utils::abs_path;
+use crate::internal::config::up::plan::UpPlanItem;
+use crate::internal::config::up::transaction::record_change;
+use crate::internal::config::up::transaction::UpChange;
+use crate::internal::config::up::utils::hash_files;
+use crate::internal::config::up::utils::run_progress;
+use crate::internal::config::up::utils::set_up_env_hash;
+use crate::internal::config::up::utils::up_env_hash;
+use crate::internal::config::up::utils::PrintProgressHandler;
+use crate::internal::config::up::utils::ProgressHandler;
+use crate::internal::config::up::utils::RunConfig;
+use crate::internal::config::up::utils::SpinnerProgressHandler;
 use crate::internal::config::up::UpConfigAsdfBase;
 use crate::internal::config::up::UpError;
+use crate::internal::config::up::ASDF_PATH;
+use crate::internal::user_interface::StringColor;
+use crate::internal::workdir;
+use crate::internal::Cache;
 use crate::internal::ConfigValue;
+use crate::internal::ENV;
+
+lazy_static! {
+    pub static ref GOBIN_PATH: String = {
+        let omni_data_home = ENV.data_home.clone();
+        format!("{}/go-bin", omni_data_home)
+    };
+}
+
+const GO_TOOLS_HASH_KEY: &str = "go-tools";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UpConfigGolang {
     pub version: Option<String>,
     pub version_file: Option<String>,
+    pub tools: Vec<String>,
     #[serde(skip)]
     pub asdf_base: OnceCell<UpConfigAsdfBase>,
 }
@@ -24,6 +56,7 @@ impl UpConfigGolang {
     pub fn from_config_value(config_value: Option<&ConfigValue>) -> Self {
         let mut version = None;
         let mut version_file = None;
+        let mut tools = Vec::new();
 
         if let Some(config_value) = config_value {
             if let Some(value) = config_value.as_str() {
@@ -38,6 +71,18 @@ impl UpConfigGolang {
                 } else if let Some(value) = value.get("version_file") {
                     version_file = Some(value.as_str().unwrap().to_string());
                 }
+
+                if let Some(value) = value.get("tools") {
+                    if let Some(array) = value.as_array() {
+                        for tool in array {
+                            if let Some(tool) = tool.as_str() {
+                                tools.push(tool.to_string());
+                            }
+                        }
+                    } else if let Some(tool) = value.as_str() {
+                        tools.push(tool.to_string());
+                    }
+                }
             }
         }
 
@@ -45,17 +90,201 @@ impl UpConfigGolang {
             asdf_base: OnceCell::new(),
             version: version,
             version_file: version_file,
+            tools,
         }
     }
 
     pub fn up(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
-        self.asdf_base()?.up(progress)
+        self.asdf_base()?.up(progress)?;
+
+        let has_gomod = abs_path("go.mod").exists();
+        let tools = self.tools_to_install();
+        if !has_gomod && tools.is_empty() {
+            return Ok(());
+        }
+
+        let desc = "install go dependencies:".to_string().light_blue();
+        let progress_handler: Box<dyn ProgressHandler> = if ENV.interactive_shell {
+            Box::new(SpinnerProgressHandler::new(desc, progress))
+        } else {
+            Box::new(PrintProgressHandler::new(desc, progress))
+        };
+        let progress_handler: Option<Box<&dyn ProgressHandler>> =
+            Some(Box::new(progress_handler.as_ref()));
+
+        // Use the version resolved while installing go, instead of
+        // resolving the version specification again
+        let version = match self.asdf_base()?.resolved_version("") {
+            Some(version) => version,
+            None => {
+                progress_handler.clone().map(|progress_handler| {
+                    progress_handler.success_with_message(
+                        "skipping (no go version installed)"
+                            .to_string()
+                            .light_black(),
+                    )
+                });
+                return Ok(());
+            }
+        };
+        let version = &version;
+
+        let gobin = match self.gobin_dir() {
+            Some(gobin) => gobin,
+            None => {
+                progress_handler.clone().map(|progress_handler| {
+                    progress_handler.success_with_message(
+                        "skipping (repository has no id)".to_string().light_black(),
+                    )
+                });
+                return Ok(());
+            }
+        };
+
+        if !tools.is_empty() {
+            self.update_cache(&gobin, progress_handler.clone());
+        }
+
+        let hash = self.dependencies_hash(version, &tools);
+        if self.is_up_to_date(&hash, &tools) {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.success_with_message(
+                    "skipping (dependencies already up to date)"
+                        .to_string()
+                        .light_black(),
+                )
+            });
+            return Ok(());
+        }
+
+        if has_gomod {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.progress("downloading modules".to_string())
+            });
+
+            let mut mod_download = self.go_command(version, &gobin);
+            mod_download.arg("mod");
+            mod_download.arg("download");
+
+            if let Err(err) = run_progress(
+                &mut mod_download,
+                progress_handler.clone(),
+                RunConfig::default(),
+            ) {
+                progress_handler.clone().map(|progress_handler| {
+                    progress_handler.error_with_message(format!("go mod download failed: {}", err))
+                });
+                return Err(err);
+            }
+        }
+
+        let gobin_created = !tools.is_empty() && !gobin.exists();
+        for tool in tools.iter() {
+            progress_handler
+                .clone()
+                .map(|progress_handler| progress_handler.progress(format!("installing {}", tool)));
+
+            // Outside of a module, go install requires a version to be
+            // specified; inside of a module, the version pinned in the
+            // go.mod file will be used if none is specified
+            let tool = if !has_gomod && !tool.contains('@') {
+                format!("{}@latest", tool)
+            } else {
+                tool.clone()
+            };
+
+            let mut go_install = self.go_command(version, &gobin);
+            go_install.arg("install");
+            go_install.arg(&tool);
+
+            if let Err(err) = run_progress(
+                &mut go_install,
+                progress_handler.clone(),
+                RunConfig::default(),
+            ) {
+                progress_handler.clone().map(|progress_handler| {
+                    progress_handler
+                        .error_with_message(format!("failed to install {}: {}", tool, err))
+                });
+                return Err(err);
+            }
+        }
+        if gobin_created {
+            record_change(UpChange::GoBin(self.clone()));
+        }
+
+        if let Err(err) = set_up_env_hash(GO_TOOLS_HASH_KEY, Some(hash)) {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.progress(format!("failed to update cache: {}", err))
+            });
+        }
+
+        progress_handler
+            .clone()
+            .map(|progress_handler| progress_handler.success());
+
+        Ok(())
     }
 
     pub fn down(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
+        if let Some(gobin) = self.gobin_dir().filter(|gobin| gobin.exists()) {
+            let desc = "remove go tools:".to_string().light_blue();
+            let progress_handler: Box<dyn ProgressHandler> = if ENV.interactive_shell {
+                Box::new(SpinnerProgressHandler::new(desc, progress))
+            } else {
+                Box::new(PrintProgressHandler::new(desc, progress))
+            };
+
+            progress_handler.progress(format!("removing {}", gobin.display()));
+            if let Err(err) = std::fs::remove_dir_all(&gobin) {
+                let msg = format!("failed to remove {}: {}", gobin.display(), err);
+                progress_handler.error_with_message(msg.clone());
+                return Err(UpError::Exec(msg));
+            }
+
+            progress_handler.success();
+        }
+
         self.asdf_base()?.down(progress)
     }
 
+    pub fn plan_up(&self) -> Vec<UpPlanItem> {
+        let asdf_base = match self.asdf_base() {
+            Ok(asdf_base) => asdf_base,
+            Err(err) => return vec![UpPlanItem::skip("golang", &format!("{}", err))],
+        };
+        let mut items = asdf_base.plan_up();
+
+        let has_gomod = abs_path("go.mod").exists();
+        let tools = self.tools_to_install();
+        if !has_gomod && tools.is_empty() {
+            return items;
+        }
+
+        if let Some(version) = asdf_base.resolved_version("") {
+            if self.is_up_to_date(&self.dependencies_hash(&version, &tools), &tools) {
+                items.push(UpPlanItem::skip("go dependencies", "already up to date"));
+                return items;
+            }
+        }
+
+        if has_gomod {
+            items.push(UpPlanItem::run("go mod download"));
+        }
+        for tool in tools.iter() {
+            items.push(UpPlanItem::install(tool));
+        }
+
+        items
+    }
+
+    pub fn plan_down(&self) -> Vec<UpPlanItem> {
+        match self.gobin_dir().filter(|gobin| gobin.exists()) {
+            Some(gobin) => vec![UpPlanItem::remove(&format!("{}", gobin.display()))],
+            None => vec![],
+        }
+    }
+
     pub fn asdf_base(&self) -> Result<&UpConfigAsdfBase, UpError> {
         self.asdf_base.get_or_try_init(|| {
             let version = if let Some(version) = &self.version {
@@ -80,6 +309,157 @@ impl UpConfigGolang {
 
         extract_version_from_gomod_file(self.version_file.as_ref().unwrap().clone())
     }
+
+    /// Returns the tools to install, both from the configuration and
+    /// from the `tools.go` file of the repository, if any.
+    fn tools_to_install(&self) -> Vec<String> {
+        let mut tools = self.tools.clone();
+        for tool in extract_tools_from_toolsgo_file(abs_path("tools.go")) {
+            if !tools.contains(&tool) {
+                tools.push(tool);
+            }
+        }
+        tools
+    }
+
+    /// Returns the files that, if changed, require to install the
+    /// dependencies again.
+    pub fn input_files(&self) -> Vec<PathBuf> {
+        vec![abs_path("go.mod"), abs_path("go.sum"), abs_path("tools.go")]
+    }
+
+    /// Returns the names of the environment variables set by the step.
+    pub fn env_vars(&self) -> Vec<String> {
+        if self.tools_to_install().is_empty() {
+            vec![]
+        } else {
+            vec!["GOBIN".to_string()]
+        }
+    }
+
+    /// Returns whether the tools installed during a previous run are
+    /// still where they were installed.
+    pub fn is_set_up(&self) -> bool {
+        self.tools_to_install().is_empty() || self.gobin_exists()
+    }
+
+    fn dependencies_hash(&self, version: &str, tools: &[String]) -> String {
+        hash_files(
+            &format!(
+                "{}:{}:{}",
+                version,
+                self.gobin_dir()
+                    .map(|gobin| gobin.display().to_string())
+                    .unwrap_or_default(),
+                tools.join(",")
+            ),
+            &self.input_files(),
+        )
+    }
+
+    fn is_up_to_date(&self, hash: &str, tools: &[String]) -> bool {
+        (tools.is_empty() || self.gobin_exists())
+            && up_env_hash(GO_TOOLS_HASH_KEY).as_deref() == Some(hash)
+    }
+
+    /// Returns the directory in which the tools of the repository are
+    /// installed; this directory is scoped to the repository so that
+    /// different repositories can pin different versions of a tool.
+    /// Returns `None` if the work directory does not have an id, e.g.
+    /// when checking a repository that has not been set up yet.
+    fn gobin_dir(&self) -> Option<PathBuf> {
+        // TODO: it would be better to fix the dynenv's management of `:` appearing in a name
+        let repo_id = workdir(".").id()?.replace(":", "--");
+        Some(PathBuf::from(&*GOBIN_PATH).join(repo_id))
+    }
+
+    fn gobin_exists(&self) -> bool {
+        self.gobin_dir()
+            .map(|gobin| gobin.exists())
+            .unwrap_or(false)
+    }
+
+    fn go_command(&self, version: &str, gobin: &Path) -> TokioCommand {
+        let goroot = PathBuf::from(format!("{}/installs/golang/{}/go", *ASDF_PATH, version));
+        let gobin = gobin.to_path_buf();
+
+        let path_env = std::env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![goroot.join("bin"), gobin.clone()];
+        paths.extend(std::env::split_paths(&path_env));
+
+        let mut command = TokioCommand::new(goroot.join("bin").join("go"));
+        command.env("GOROOT", &goroot);
+        command.env("GOBIN", &gobin);
+        if let Ok(path_env) = std::env::join_paths(paths) {
+            command.env("PATH", path_env);
+        }
+        command.stdout(std::process::Stdio::piped());
+        command.stderr(std::process::Stdio::piped());
+        command
+    }
+
+    fn update_cache(&self, gobin: &Path, progress_handler: Option<Box<&dyn ProgressHandler>>) {
+        progress_handler
+            .clone()
+            .map(|progress_handler| progress_handler.progress("updating cache".to_string()));
+
+        let result = Cache::exclusive(|cache| {
+            let workdir = workdir(".");
+            let repo_id = workdir.id();
+            if repo_id.is_none() {
+                return false;
+            }
+            let repo_id = repo_id.unwrap();
+
+            // Update the repository up cache
+            let mut up_env = HashMap::new();
+            if let Some(up_cache) = &cache.up_environments {
+                up_env = up_cache.env.clone();
+            }
+
+            if !up_env.contains_key(&repo_id) {
+                up_env.insert(repo_id.clone(), UpEnvironment::new());
+            }
+            let repo_up_env = up_env.get_mut(&repo_id).unwrap();
+
+            repo_up_env
+                .env_vars
+                .insert("GOBIN".to_string(), gobin.to_string_lossy().to_string());
+
+            cache.up_environments = Some(UpEnvironments {
+                env: up_env.clone(),
+                updated_at: OffsetDateTime::now_utc(),
+            });
+
+            true
+        });
+
+        if let Err(err) = result {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.progress(format!("failed to update cache: {}", err))
+            });
+        } else {
+            progress_handler
+                .clone()
+                .map(|progress_handler| progress_handler.progress("updated cache".to_string()));
+        }
+    }
+}
+
+/// Extracts the tools imported in a `tools.go` file, following the
+/// convention of blank-importing the tools a module depends on, e.g.:
+///   import _ "github.com/golangci/golangci-lint/cmd/golangci-lint"
+fn extract_tools_from_toolsgo_file(tools_file: impl AsRef<Path>) -> Vec<String> {
+    let contents = match std::fs::read_to_string(tools_file) {
+        Ok(contents) => contents,
+        Err(_) => return Vec::new(),
+    };
+
+    let blank_import = regex::Regex::new(r#"(?m)^\s*(?:import\s+)?_\s+"([^"]+)""#).unwrap();
+    blank_import
+        .captures_iter(&contents)
+        .map(|captures| captures[1].to_string())
+        .collect()
 }
 
 fn detect_version_from_gomod(_tool_name: String, path: PathBuf) -> Option<String> {
@@ -1,3 +1,6 @@
+use std::path::Path;
+use std::path::PathBuf;
+
 use duct::cmd;
 use once_cell::sync::OnceCell;
 use serde::Deserialize;
@@ -9,6 +12,12 @@ use crate::internal::cache::Cache;
 use crate::internal::cache::HomebrewInstalled;
 use crate::internal::cache::HomebrewOperation;
 use crate::internal::cache::HomebrewTapped;
+use crate::internal::commands::utils::abs_path;
+use crate::internal::config::up::lock::lock_homebrew_version;
+use crate::internal::config::up::lock::locked_homebrew_version;
+use crate::internal::config::up::plan::UpPlanItem;
+use crate::internal::config::up::transaction::record_change;
+use crate::internal::config::up::transaction::UpChange;
 use crate::internal::config::up::utils::run_progress;
 use crate::internal::config::up::utils::PrintProgressHandler;
 use crate::internal::config::up::utils::ProgressHandler;
@@ -22,23 +31,127 @@ use crate::internal::workdir;
 use crate::omni_warning;
 
 static LOCAL_TAP: &str = "omni/local";
+static BREWFILE: &str = "Brewfile";
 static BREW_UPDATED: OnceCell<bool> = OnceCell::new();
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UpConfigHomebrew {
     pub install: Vec<HomebrewInstall>,
     pub tap: Vec<HomebrewTap>,
+    pub brewfile: Option<String>,
 }
 
 impl UpConfigHomebrew {
     pub fn from_config_value(config_value: Option<&ConfigValue>) -> Self {
-        let install = HomebrewInstall::from_config_value(config_value);
-        let tap = HomebrewTap::from_config_value(config_value);
+        let mut install = HomebrewInstall::from_config_value(config_value);
+        let mut tap = HomebrewTap::from_config_value(config_value);
+
+        // Use the Brewfile given in the configuration, or the one at the
+        // root of the repository if nothing else is configured
+        let brewfile = match config_value.and_then(|config_value| config_value.get("brewfile")) {
+            Some(brewfile) => brewfile.as_str_forced(),
+            None if install.is_empty() && tap.is_empty() && Path::new(BREWFILE).exists() => {
+                Some(BREWFILE.to_string())
+            }
+            None => None,
+        };
+        let brewfile = brewfile.map(|brewfile| abs_path(brewfile).to_string_lossy().to_string());
+
+        if let Some(brewfile) = &brewfile {
+            if let Ok(contents) = std::fs::read_to_string(brewfile) {
+                let (brewfile_taps, brewfile_installs) = Self::parse_brewfile(&contents);
+                tap.extend(brewfile_taps);
+                install.extend(brewfile_installs);
+            }
+        }
 
         UpConfigHomebrew {
             install: install,
             tap: tap,
+            brewfile: brewfile,
+        }
+    }
+
+    /// Parses the `tap`, `brew` and `cask` entries of a Brewfile; other
+    /// entries (e.g. `mas`, `vscode`) and the options of the entries
+    /// are not supported and are ignored.
+    fn parse_brewfile(contents: &str) -> (Vec<HomebrewTap>, Vec<HomebrewInstall>) {
+        let mut taps = Vec::new();
+        let mut installs = Vec::new();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            let (entry_type, args) = match line.split_once(|c: char| c.is_whitespace() || c == '(')
+            {
+                Some((entry_type, args)) => (entry_type, brewfile_args(args)),
+                None => continue,
+            };
+
+            let name = match args.first() {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+
+            match entry_type {
+                "tap" => taps.push(HomebrewTap {
+                    name: name,
+                    url: args.get(1).cloned(),
+                    was_handled: OnceCell::new(),
+                }),
+                "brew" | "cask" => installs.push(HomebrewInstall {
+                    install_type: if entry_type == "cask" {
+                        HomebrewInstallType::Cask
+                    } else {
+                        HomebrewInstallType::Formula
+                    },
+                    name: name,
+                    version: None,
+                    was_handled: OnceCell::new(),
+                }),
+                _ => {}
+            }
         }
+
+        (taps, installs)
+    }
+
+    /// Returns the taps and formulae of the step in the Brewfile format.
+    pub fn to_brewfile(&self) -> String {
+        let mut lines = Vec::new();
+
+        for tap in self.tap.iter() {
+            match &tap.url {
+                Some(url) => lines.push(format!("tap {:?}, {:?}", tap.name, url)),
+                None => lines.push(format!("tap {:?}", tap.name)),
+            }
+        }
+
+        for install in self.install.iter() {
+            let entry_type = match install.install_type {
+                HomebrewInstallType::Formula => "brew",
+                HomebrewInstallType::Cask => "cask",
+            };
+            lines.push(format!("{} {:?}", entry_type, install.package_id()));
+        }
+
+        lines.join("\n")
+    }
+
+    /// Returns the files that, if changed, require to run the step again.
+    pub fn input_files(&self) -> Vec<PathBuf> {
+        match &self.brewfile {
+            Some(brewfile) => vec![PathBuf::from(brewfile)],
+            None => vec![],
+        }
+    }
+
+    /// Returns the identifiers under which the formulae are recorded
+    /// in the lock file.
+    pub fn package_ids(&self) -> Vec<String> {
+        self.install
+            .iter()
+            .map(|install| install.package_id())
+            .collect()
     }
 
     pub fn up(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
@@ -46,6 +159,14 @@ impl UpConfigHomebrew {
         let main_progress_handler = PrintProgressHandler::new(desc, progress);
         main_progress_handler.progress("".to_string());
 
+        if let Some(brewfile) = &self.brewfile {
+            if !Path::new(brewfile).exists() {
+                let err = UpError::Config(format!("{} does not exist", brewfile));
+                main_progress_handler.error_with_message(format!("{}", err));
+                return Err(err);
+            }
+        }
+
         let num_taps = self.tap.len();
         for (idx, tap) in self.tap.iter().enumerate() {
             if let Err(err) = tap.up(progress.clone(), Some((idx + 1, num_taps))) {
@@ -198,6 +319,87 @@ impl UpConfigHomebrew {
         return_value
     }
 
+    pub fn plan_up(&self) -> Vec<UpPlanItem> {
+        let mut items = Vec::new();
+
+        for tap in self.tap.iter() {
+            let subject = format!("tap {}", tap.name);
+            if tap.is_tapped() {
+                items.push(UpPlanItem::skip(&subject, "already tapped"));
+            } else {
+                items.push(UpPlanItem::install(&subject));
+            }
+        }
+
+        for install in self.install.iter() {
+            let subject = install.package_id();
+            if !install.is_installed() {
+                items.push(UpPlanItem::install(&subject));
+            } else if install.version.is_some() {
+                items.push(UpPlanItem::skip(&subject, "already installed"));
+            } else if let Some(version) = locked_homebrew_version(&subject) {
+                items.push(UpPlanItem::skip(
+                    &subject,
+                    &format!("already installed, locked to {}", version),
+                ));
+            } else {
+                items.push(
+                    UpPlanItem::install(&subject)
+                        .with_detail("already installed, upgraded if outdated"),
+                );
+            }
+        }
+
+        items
+    }
+
+    /// Returns whether the taps and formulae handled during a previous
+    /// run are still there, keeping the versions of the formulae in the
+    /// lock file if that is the case.
+    pub fn restore(&self) -> bool {
+        if !self.tap.iter().all(|tap| tap.is_tapped())
+            || !self.install.iter().all(|install| install.is_installed())
+        {
+            return false;
+        }
+
+        for install in self.install.iter() {
+            install.lock_version(locked_homebrew_version(&install.package_id()));
+        }
+
+        true
+    }
+
+    pub fn plan_down(&self) -> Vec<UpPlanItem> {
+        let repo_id = match workdir(".").id() {
+            Some(repo_id) => repo_id,
+            None => return vec![],
+        };
+
+        let cache = match Cache::shared() {
+            Ok(cache) => cache,
+            Err(_) => return vec![],
+        };
+
+        let mut items = Vec::new();
+        if let Some(brew_cache) = &cache.homebrew_operation {
+            for install in brew_cache.installed.iter() {
+                if install.installed && install.required_by.iter().all(|id| id == &repo_id) {
+                    let install = HomebrewInstall::from_cache(install);
+                    items.push(UpPlanItem::remove(&install.package_id()));
+                }
+            }
+
+            for tap in brew_cache.tapped.iter() {
+                if tap.tapped && tap.required_by.iter().all(|id| id == &repo_id) {
+                    items.push(UpPlanItem::remove(&format!("tap {}", tap.name)));
+                }
+            }
+        }
+
+        items
+    }
+
     pub fn is_available(&self) -> bool {
         if let Ok(_) = cmd!("command", "-v", "brew")
             .stdout_null()
@@ -210,6 +412,38 @@ impl UpConfigHomebrew {
     }
 }
 
+/// Returns the positional string arguments of a Brewfile entry, which
+/// are the quoted strings before any option or comment.
+fn brewfile_args(args: &str) -> Vec<String> {
+    let mut parsed = Vec::new();
+    let mut chars = args.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                let mut value = String::new();
+                while let Some(next) = chars.next() {
+                    if next == c {
+                        break;
+                    } else if next == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            value.push(escaped);
+                        }
+                    } else {
+                        value.push(next);
+                    }
+                }
+                parsed.push(value);
+            }
+            ',' | '(' | ')' => {}
+            c if c.is_whitespace() => {}
+            _ => break,
+        }
+    }
+
+    parsed
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct HomebrewTap {
     name: String,
@@ -302,7 +536,7 @@ impl HomebrewTap {
         }
     }
 
-    fn from_name(name: &str) -> Self {
+    pub fn from_name(name: &str) -> Self {
         Self {
             name: name.to_string(),
             url: None,
@@ -418,6 +652,12 @@ impl HomebrewTap {
             });
             return Err(err);
         }
+        // Record a copy that was not handled yet, so that it can be
+        // handled again when untapping it during a rollback
+        record_change(UpChange::HomebrewTap(Self {
+            was_handled: OnceCell::new(),
+            ..self.clone()
+        }));
 
         self.update_cache(progress_handler.clone());
         progress_handler
@@ -494,6 +734,47 @@ impl HomebrewTap {
         Ok(())
     }
 
+    /// Untaps the tap if it is not required by any repository anymore;
+    /// fails with `UpError::HomebrewTapInUse` if formulae installed from
+    /// the tap are still around.
+    pub fn remove(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
+        self.down(progress, None)
+    }
+
+    /// Undoes the tap made during the current run, and removes the
+    /// current repository from the repositories requiring it.
+    pub fn rollback(&self) -> Result<(), UpError> {
+        match self.down(None, None) {
+            Ok(()) | Err(UpError::HomebrewTapInUse) => {}
+            Err(err) => return Err(err),
+        }
+
+        if let Err(err) = Cache::exclusive(|cache| {
+            let repo_id = match workdir(".").id() {
+                Some(repo_id) => repo_id,
+                None => return false,
+            };
+
+            if let Some(brew_cache) = &mut cache.homebrew_operation {
+                for tap in brew_cache.tapped.iter_mut() {
+                    if tap.name == self.name {
+                        tap.required_by.retain(|id| id != &repo_id);
+                    }
+                }
+                brew_cache
+                    .tapped
+                    .retain(|tap| tap.name != self.name || !tap.required_by.is_empty());
+                return true;
+            }
+
+            false
+        }) {
+            return Err(UpError::Exec(format!("failed to update cache: {}", err)));
+        }
+
+        Ok(())
+    }
+
     fn is_tapped(&self) -> bool {
         let mut brew_tap_list = std::process::Command::new("brew");
         brew_tap_list.arg("tap");
@@ -647,7 +928,7 @@ impl HomebrewInstall {
         installs
     }
 
-    fn from_cache(cached: &HomebrewInstalled) -> Self {
+    pub fn from_cache(cached: &HomebrewInstalled) -> Self {
         let install_type = if cached.cask {
             HomebrewInstallType::Cask
         } else {
@@ -761,11 +1042,16 @@ impl HomebrewInstall {
         let progress_handler: Option<Box<&dyn ProgressHandler>> =
             Some(Box::new(progress_handler.as_ref()));
 
+        // Formulae that are in the lock file are not upgraded, so that
+        // the version stays the same as the one that was locked
         let installed = self.is_installed();
-        if installed && self.version.is_some() {
+        let locked_version = locked_homebrew_version(&self.package_id());
+        if installed && (self.version.is_some() || locked_version.is_some()) {
             self.update_cache(progress_handler.clone());
+            let message = self.lock_version(locked_version);
             progress_handler.clone().map(|progress_handler| {
-                progress_handler.success_with_message("already installed".to_string().light_black())
+                progress_handler
+                    .success_with_message(format!("already installed{}", message).light_black())
             });
             return Ok(());
         }
@@ -776,13 +1062,25 @@ impl HomebrewInstall {
             });
             return Err(err);
         }
+        if !installed {
+            // Record a copy that was not handled yet, so that it can be
+            // handled again when uninstalling it during a rollback
+            record_change(UpChange::HomebrewInstall(Self {
+                was_handled: OnceCell::new(),
+                ..self.clone()
+            }));
+        }
 
         self.update_cache(progress_handler.clone());
+        let message = self.lock_version(locked_version);
         progress_handler.clone().map(|progress_handler| {
             progress_handler.success_with_message(
-                if installed { "up to date" } else { "installed" }
-                    .to_string()
-                    .light_green(),
+                format!(
+                    "{}{}",
+                    if installed { "up to date" } else { "installed" },
+                    message
+                )
+                .light_green(),
             );
         });
 
@@ -881,6 +1179,47 @@ impl HomebrewInstall {
         Ok(())
     }
 
+    /// Uninstalls the formula or cask if it is not required by any
+    /// repository anymore.
+    pub fn remove(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
+        self.down(progress, None)
+    }
+
+    /// Uninstalls the formula or cask installed during the current run,
+    /// and removes the current repository from the repositories
+    /// requiring it.
+    pub fn rollback(&self) -> Result<(), UpError> {
+        self.down(None, None)?;
+
+        if let Err(err) = Cache::exclusive(|cache| {
+            let repo_id = match workdir(".").id() {
+                Some(repo_id) => repo_id,
+                None => return false,
+            };
+
+            if let Some(brew_cache) = &mut cache.homebrew_operation {
+                let is_self = |install: &HomebrewInstalled| {
+                    install.name == self.name && install.version == self.version
+                };
+                for install in brew_cache.installed.iter_mut() {
+                    if is_self(install) {
+                        install.required_by.retain(|id| id != &repo_id);
+                    }
+                }
+                brew_cache
+                    .installed
+                    .retain(|install| !is_self(install) || !install.required_by.is_empty());
+                return true;
+            }
+
+            false
+        }) {
+            return Err(UpError::Exec(format!("failed to update cache: {}", err)));
+        }
+
+        Ok(())
+    }
+
     fn package_id(&self) -> String {
         format!(
             "{}{}",
@@ -893,6 +1232,57 @@ impl HomebrewInstall {
         )
     }
 
+    /// Records the version of the formula in the lock file; the locked
+    /// version is kept if there was one, as homebrew does not allow to
+    /// install a specific version, in which case a message explaining
+    /// the difference with the installed version is returned.
+    fn lock_version(&self, locked_version: Option<String>) -> String {
+        let installed_version = self.installed_version();
+        match (locked_version, installed_version) {
+            (Some(locked_version), installed_version) => {
+                lock_homebrew_version(&self.package_id(), &locked_version);
+                match installed_version {
+                    Some(installed_version) if installed_version != locked_version => format!(
+                        " ({} installed, {} locked)",
+                        installed_version, locked_version
+                    ),
+                    _ => "".to_string(),
+                }
+            }
+            (None, Some(installed_version)) => {
+                lock_homebrew_version(&self.package_id(), &installed_version);
+                "".to_string()
+            }
+            (None, None) => "".to_string(),
+        }
+    }
+
+    fn installed_version(&self) -> Option<String> {
+        let mut brew_list = std::process::Command::new("brew");
+        brew_list.arg("list");
+        brew_list.arg("--versions");
+        if self.is_cask() {
+            brew_list.arg("--cask");
+        }
+        brew_list.arg(self.package_id());
+        brew_list.stdout(std::process::Stdio::piped());
+        brew_list.stderr(std::process::Stdio::null());
+
+        let output = brew_list.output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        // The output is the name of the formula followed by the installed
+        // versions, the most recent one being last
+        let stdout = String::from_utf8(output.stdout).ok()?;
+        stdout
+            .split_whitespace()
+            .skip(1)
+            .last()
+            .map(|v| v.to_string())
+    }
+
     fn is_cask(&self) -> bool {
         self.install_type == HomebrewInstallType::Cask
     }
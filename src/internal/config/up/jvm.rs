@@ -0,0 +1,142 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::internal::config::up::UpConfigAsdfBase;
+use crate::internal::ConfigValue;
+
+/// The distribution of java installed when the version does not
+/// explicitly specify one.
+pub static DEFAULT_JAVA_DISTRIBUTION: &str = "temurin";
+
+/// Mapping between the vendor identifiers used by sdkman and the
+/// distribution names used by the asdf-java plugin.
+static SDKMAN_JAVA_VENDORS: &[(&str, &str)] = &[
+    ("amzn", "corretto"),
+    ("graal", "graalvm"),
+    ("graalce", "graalvm-community"),
+    ("librca", "liberica"),
+    ("ms", "microsoft"),
+    ("open", "openjdk"),
+    ("oracle", "oracle"),
+    ("sapmchn", "sapmachine"),
+    ("sem", "semeru-openj9"),
+    ("tem", "temurin"),
+    ("zulu", "zulu"),
+];
+
+pub fn java_from_config_value(config_value: Option<&ConfigValue>) -> UpConfigAsdfBase {
+    let mut asdf_base = UpConfigAsdfBase::from_config_value("java", config_value);
+    asdf_base.add_detect_version_func(detect_version_from_sdkmanrc);
+    asdf_base.add_detect_version_func(detect_java_version_from_gradle);
+    asdf_base
+}
+
+pub fn kotlin_from_config_value(config_value: Option<&ConfigValue>) -> UpConfigAsdfBase {
+    let mut asdf_base = UpConfigAsdfBase::from_config_value("kotlin", config_value);
+    asdf_base.add_detect_version_func(detect_version_from_sdkmanrc);
+    asdf_base.add_detect_version_func(detect_kotlin_version_from_gradle);
+    asdf_base
+}
+
+/// Splits a java version into its distribution and the version itself,
+/// e.g. `temurin-17.0.8+7` will return `("temurin", "17.0.8+7")`; if no
+/// distribution is specified, the default one is returned.
+pub fn split_java_version(version: &str) -> (String, String) {
+    let distribution_regex = Regex::new(r"^([A-Za-z][\w.-]*?)-([\d^~<>=].*|latest|lts)$").unwrap();
+    if let Some(captures) = distribution_regex.captures(version) {
+        return (captures[1].to_string(), captures[2].to_string());
+    }
+
+    (DEFAULT_JAVA_DISTRIBUTION.to_string(), version.to_string())
+}
+
+fn detect_version_from_sdkmanrc(tool_name: String, path: PathBuf) -> Option<String> {
+    let sdkmanrc_path = path.join(".sdkmanrc");
+    if !sdkmanrc_path.exists() || sdkmanrc_path.is_dir() {
+        return None;
+    }
+
+    let contents = std::fs::read_to_string(&sdkmanrc_path).ok()?;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let (candidate, version) = match line.split_once('=') {
+            Some((candidate, version)) => (candidate.trim(), version.trim()),
+            None => continue,
+        };
+        if candidate != tool_name || version.is_empty() {
+            continue;
+        }
+
+        if tool_name != "java" {
+            return Some(version.to_string());
+        }
+
+        // sdkman java versions are in the `<version>-<vendor>` format,
+        // which we need to convert to the `<distribution>-<version>`
+        // format used by asdf
+        if let Some((version, vendor)) = version.rsplit_once('-') {
+            if let Some((_, distribution)) = SDKMAN_JAVA_VENDORS
+                .iter()
+                .find(|(sdkman_vendor, _)| *sdkman_vendor == vendor)
+            {
+                return Some(format!("{}-{}", distribution, version));
+            }
+            return Some(version.to_string());
+        }
+
+        return Some(version.to_string());
+    }
+
+    None
+}
+
+fn gradle_build_files(path: &Path) -> Vec<String> {
+    ["build.gradle.kts", "build.gradle"]
+        .iter()
+        .map(|file| path.join(file))
+        .filter(|file| file.is_file())
+        .filter_map(|file| std::fs::read_to_string(file).ok())
+        .collect()
+}
+
+fn detect_java_version_from_gradle(_tool_name: String, path: PathBuf) -> Option<String> {
+    // Matches the java toolchain configuration, in any of the forms:
+    //   languageVersion = JavaLanguageVersion.of(17)
+    //   languageVersion.set(JavaLanguageVersion.of("17"))
+    //   jvmToolchain(17)
+    let toolchain_regex =
+        Regex::new(r#"(?:JavaLanguageVersion\.of|jvmToolchain)\(\s*["']?(\d+)["']?\s*\)"#).unwrap();
+
+    for contents in gradle_build_files(&path) {
+        if let Some(captures) = toolchain_regex.captures(&contents) {
+            return Some(captures[1].to_string());
+        }
+    }
+
+    None
+}
+
+fn detect_kotlin_version_from_gradle(_tool_name: String, path: PathBuf) -> Option<String> {
+    // Matches the kotlin plugin configuration, in any of the forms:
+    //   kotlin("jvm") version "1.9.0"
+    //   id("org.jetbrains.kotlin.jvm") version "1.9.0"
+    //   id 'org.jetbrains.kotlin.jvm' version '1.9.0'
+    let plugin_regex = Regex::new(
+        r#"(?:kotlin\(\s*"[\w.-]+"\s*\)|id\s*\(?\s*["']org\.jetbrains\.kotlin\.[\w.-]+["']\s*\)?)\s*version\s*["']([\d.]+)["']"#,
+    )
+    .unwrap();
+
+    for contents in gradle_build_files(&path) {
+        if let Some(captures) = plugin_regex.captures(&contents) {
+            return Some(captures[1].to_string());
+        }
+    }
+
+    None
+}
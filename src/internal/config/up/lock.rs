@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::internal::config::up::UpConfig;
+use crate::internal::config::up::UpError;
+use crate::internal::workdir;
+
+const LOCK_FILE_NAME: &str = "omni.lock";
+const LOCK_FILE_HEADER: &str = concat!(
+    "# This file is generated by `omni up` to make sure that everyone working\n",
+    "# on the repository gets the same versions; run `omni up --update-lock`\n",
+    "# to resolve the versions again and update it.\n",
+);
+
+lazy_static! {
+    static ref UP_LOCK: Mutex<Option<UpLockState>> = Mutex::new(None);
+}
+
+/// The versions resolved by `omni up` for a repository, stored in the
+/// `omni.lock` file next to the configuration of the repository.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UpLock {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub asdf: BTreeMap<String, UpLockAsdfTool>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub homebrew: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UpLockAsdfTool {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: BTreeMap<String, String>,
+}
+
+impl UpLock {
+    fn read(path: &Path) -> Result<Self, UpError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| UpError::Exec(format!("failed to read {}: {}", path.display(), err)))?;
+        serde_yaml::from_str(&contents)
+            .map_err(|err| UpError::Config(format!("failed to parse {}: {}", path.display(), err)))
+    }
+
+    fn write(&self, path: &Path) -> Result<(), UpError> {
+        let contents = serde_yaml::to_string(self).map_err(|err| {
+            UpError::Exec(format!("failed to serialize {}: {}", path.display(), err))
+        })?;
+        std::fs::write(path, format!("{}{}", LOCK_FILE_HEADER, contents))
+            .map_err(|err| UpError::Exec(format!("failed to write {}: {}", path.display(), err)))
+    }
+}
+
+#[derive(Debug)]
+struct UpLockState {
+    path: PathBuf,
+    locked: UpLock,
+    resolved: UpLock,
+}
+
+/// Returns the path of the lock file of the current repository; the
+/// lock file is next to the configuration file of the repository.
+fn lock_path() -> Option<PathBuf> {
+    let wd = workdir(".");
+    let root = PathBuf::from(wd.root()?);
+
+    if !root.join(".omni.yaml").exists() && root.join(".omni").join("config.yaml").exists() {
+        return Some(root.join(".omni").join(LOCK_FILE_NAME));
+    }
+
+    Some(root.join(LOCK_FILE_NAME))
+}
+
+/// Loads the lock file of the current repository so that the versions
+/// it contains are used when resolving versions; if `update` is set,
+/// the lock file is ignored and all the versions are resolved again.
+pub fn load_lock(update: bool) -> Result<(), UpError> {
+    let path = match lock_path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let locked = if !update && path.exists() {
+        UpLock::read(&path)?
+    } else {
+        UpLock::default()
+    };
+
+    *UP_LOCK.lock().unwrap() = Some(UpLockState {
+        path,
+        locked,
+        resolved: UpLock::default(),
+    });
+
+    Ok(())
+}
+
+/// Writes the versions resolved during the run to the lock file, if
+/// they differ from the ones it already contains; the entries of the
+/// steps that did not run (e.g. not selected, unavailable on this
+/// system or with an unmet condition) are kept, and only the entries
+/// of the steps that are not in the configuration anymore are removed.
+pub fn write_lock(config: &UpConfig) -> Result<(), UpError> {
+    let mut state = match UP_LOCK.lock().unwrap().take() {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+
+    let mut merged = if state.path.exists() {
+        UpLock::read(&state.path)?
+    } else {
+        UpLock::default()
+    };
+
+    for (tool, resolved) in state.resolved.asdf {
+        let merged_tool = merged.asdf.entry(tool).or_default();
+        if resolved.plugin_commit.is_some() {
+            merged_tool.plugin_commit = resolved.plugin_commit;
+        }
+        merged_tool.versions.extend(resolved.versions);
+    }
+    merged.homebrew.extend(state.resolved.homebrew);
+
+    merged.asdf.retain(|tool, locked| {
+        locked
+            .versions
+            .retain(|version, _| config.uses_asdf_version(tool, version));
+        config.uses_asdf_tool(tool)
+    });
+    merged
+        .homebrew
+        .retain(|package, _| config.uses_homebrew_package(package));
+
+    state.resolved = merged;
+
+    if state.resolved == UpLock::default() && !state.path.exists() {
+        return Ok(());
+    }
+
+    if state.path.exists() && UpLock::read(&state.path).ok() == Some(state.resolved.clone()) {
+        return Ok(());
+    }
+
+    state.resolved.write(&state.path)
+}
+
+pub fn locked_asdf_version(tool: &str, version: &str) -> Option<String> {
+    let state = UP_LOCK.lock().unwrap();
+    let tool = state.as_ref()?.locked.asdf.get(tool)?;
+    tool.versions.get(version).cloned()
+}
+
+pub fn locked_asdf_plugin_commit(tool: &str) -> Option<String> {
+    let state = UP_LOCK.lock().unwrap();
+    state.as_ref()?.locked.asdf.get(tool)?.plugin_commit.clone()
+}
+
+pub fn locked_homebrew_version(package: &str) -> Option<String> {
+    let state = UP_LOCK.lock().unwrap();
+    state.as_ref()?.locked.homebrew.get(package).cloned()
+}
+
+pub fn lock_asdf_version(tool: &str, version: &str, resolved_version: &str) {
+    if let Some(state) = UP_LOCK.lock().unwrap().as_mut() {
+        state
+            .resolved
+            .asdf
+            .entry(tool.to_string())
+            .or_default()
+            .versions
+            .insert(version.to_string(), resolved_version.to_string());
+    }
+}
+
+pub fn lock_asdf_plugin_commit(tool: &str, commit: &str) {
+    if let Some(state) = UP_LOCK.lock().unwrap().as_mut() {
+        state
+            .resolved
+            .asdf
+            .entry(tool.to_string())
+            .or_default()
+            .plugin_commit = Some(commit.to_string());
+    }
+}
+
+pub fn lock_homebrew_version(package: &str, version: &str) {
+    if let Some(state) = UP_LOCK.lock().unwrap().as_mut() {
+        state
+            .resolved
+            .homebrew
+            .insert(package.to_string(), version.to_string());
+    }
+}
@@ -1,6 +1,30 @@
 pub mod base;
 pub use base::UpConfig;
 
+pub mod plan;
+pub use plan::UpPlan;
+pub use plan::UpPlanItem;
+
+pub mod check;
+pub use check::UpCheck;
+pub use check::UpCheckStatus;
+
+pub mod condition;
+pub use condition::UpConfigCondition;
+
+pub mod step;
+pub use step::UpConfigStep;
+pub use step::UpStepSelection;
+
+pub mod detect;
+
+pub mod gc;
+pub use gc::UpGc;
+
+pub mod lock;
+
+pub mod transaction;
+
 pub mod tool;
 pub use tool::UpConfigTool;
 
@@ -13,15 +37,24 @@ pub use custom::UpConfigCustom;
 pub mod golang;
 pub use golang::UpConfigGolang;
 
+pub mod jvm;
+
 pub mod nodejs;
 pub use nodejs::UpConfigNodejs;
 
+pub mod node_packages;
+pub use node_packages::UpConfigNodePackages;
+
 pub mod python;
 pub use python::UpConfigPython;
 
 pub mod homebrew;
 pub use homebrew::UpConfigHomebrew;
 
+pub mod system_packages;
+pub use system_packages::SystemPackageManager;
+pub use system_packages::UpConfigSystemPackages;
+
 pub mod asdf_base;
 pub use asdf_base::UpConfigAsdfBase;
 pub use asdf_base::ASDF_BIN;
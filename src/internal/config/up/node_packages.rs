@@ -0,0 +1,406 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::process::Command as TokioCommand;
+
+use crate::internal::commands::utils::abs_path;
+use crate::internal::config::up::plan::UpPlanItem;
+use crate::internal::config::up::transaction::record_change;
+use crate::internal::config::up::transaction::UpChange;
+use crate::internal::config::up::utils::command_exists;
+use crate::internal::config::up::utils::hash_files;
+use crate::internal::config::up::utils::run_progress;
+use crate::internal::config::up::utils::set_up_env_hash;
+use crate::internal::config::up::utils::up_env_hash;
+use crate::internal::config::up::utils::PrintProgressHandler;
+use crate::internal::config::up::utils::ProgressHandler;
+use crate::internal::config::up::utils::RunConfig;
+use crate::internal::config::up::utils::SpinnerProgressHandler;
+use crate::internal::config::up::UpError;
+use crate::internal::config::ConfigValue;
+use crate::internal::user_interface::StringColor;
+use crate::internal::ENV;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum NodePackageManager {
+    Npm,
+    Pnpm,
+    Yarn,
+}
+
+impl NodePackageManager {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "npm" => Some(NodePackageManager::Npm),
+            "pnpm" => Some(NodePackageManager::Pnpm),
+            "yarn" => Some(NodePackageManager::Yarn),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodePackageManager::Npm => "npm",
+            NodePackageManager::Pnpm => "pnpm",
+            NodePackageManager::Yarn => "yarn",
+        }
+    }
+
+    fn lockfiles(&self) -> Vec<&'static str> {
+        match self {
+            NodePackageManager::Npm => vec!["package-lock.json", "npm-shrinkwrap.json"],
+            NodePackageManager::Pnpm => vec!["pnpm-lock.yaml"],
+            NodePackageManager::Yarn => vec!["yarn.lock"],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpConfigNodePackages {
+    pub default_manager: NodePackageManager,
+    pub dir: Option<String>,
+}
+
+impl UpConfigNodePackages {
+    pub fn from_config_value(up_name: &str, config_value: Option<&ConfigValue>) -> Self {
+        let default_manager =
+            NodePackageManager::from_name(up_name).unwrap_or(NodePackageManager::Npm);
+
+        let mut dir = None;
+        if let Some(config_value) = config_value {
+            if let Some(value) = config_value.as_str() {
+                dir = Some(value.to_string());
+            } else if let Some(value) = config_value.get_as_str("dir") {
+                dir = Some(value.to_string());
+            }
+        }
+
+        UpConfigNodePackages {
+            default_manager,
+            dir,
+        }
+    }
+
+    pub fn up(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
+        let manager = self.package_manager();
+
+        let desc = format!("install {} dependencies:", manager.name()).light_blue();
+        let progress_handler: Box<dyn ProgressHandler> = if ENV.interactive_shell {
+            Box::new(SpinnerProgressHandler::new(desc, progress))
+        } else {
+            Box::new(PrintProgressHandler::new(desc, progress))
+        };
+        let progress_handler: Option<Box<&dyn ProgressHandler>> =
+            Some(Box::new(progress_handler.as_ref()));
+
+        if !self.package_json_path().exists() {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.success_with_message(
+                    "skipping (no package.json found)".to_string().light_black(),
+                )
+            });
+            return Ok(());
+        }
+
+        let hash = self.inputs_hash(manager);
+        if self.is_up_to_date(&hash) {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.success_with_message(
+                    "skipping (dependencies already up to date)"
+                        .to_string()
+                        .light_black(),
+                )
+            });
+            return Ok(());
+        }
+
+        if let Err(err) = self.enable_package_manager(manager, progress_handler.clone()) {
+            progress_handler
+                .clone()
+                .map(|progress_handler| progress_handler.error_with_message(format!("{}", err)));
+            return Err(err);
+        }
+
+        progress_handler.clone().map(|progress_handler| {
+            progress_handler.progress("installing dependencies".to_string())
+        });
+
+        let node_modules_created = !self.node_modules_path().exists();
+        let mut install = TokioCommand::new(manager.name());
+        install.current_dir(self.project_dir());
+        install.args(self.install_args(manager));
+        install.env("COREPACK_ENABLE_DOWNLOAD_PROMPT", "0");
+        install.stdout(std::process::Stdio::piped());
+        install.stderr(std::process::Stdio::piped());
+
+        let result = run_progress(&mut install, progress_handler.clone(), RunConfig::default());
+        if let Err(err) = &result {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.error_with_message(format!(
+                    "{} install failed: {}",
+                    manager.name(),
+                    err
+                ))
+            });
+            return result;
+        }
+        if node_modules_created {
+            record_change(UpChange::NodeModules(self.clone()));
+        }
+
+        if let Err(err) = set_up_env_hash(&self.hash_key(), Some(hash)) {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.progress(format!("failed to update cache: {}", err))
+            });
+        }
+
+        progress_handler
+            .clone()
+            .map(|progress_handler| progress_handler.success());
+
+        Ok(())
+    }
+
+    pub fn down(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
+        let manager = self.package_manager();
+
+        let desc = format!("remove {} dependencies:", manager.name()).light_blue();
+        let progress_handler: Box<dyn ProgressHandler> = if ENV.interactive_shell {
+            Box::new(SpinnerProgressHandler::new(desc, progress))
+        } else {
+            Box::new(PrintProgressHandler::new(desc, progress))
+        };
+        let progress_handler: Option<Box<&dyn ProgressHandler>> =
+            Some(Box::new(progress_handler.as_ref()));
+
+        let node_modules = self.node_modules_path();
+        if !node_modules.exists() {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler
+                    .success_with_message("skipping (nothing to do)".to_string().light_black())
+            });
+            return Ok(());
+        }
+
+        progress_handler.clone().map(|progress_handler| {
+            progress_handler.progress(format!("removing {}", node_modules.display()))
+        });
+
+        if let Err(err) = std::fs::remove_dir_all(&node_modules) {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.error_with_message(format!(
+                    "failed to remove {}: {}",
+                    node_modules.display(),
+                    err
+                ))
+            });
+            return Err(UpError::Exec(format!(
+                "failed to remove {}: {}",
+                node_modules.display(),
+                err
+            )));
+        }
+
+        progress_handler
+            .clone()
+            .map(|progress_handler| progress_handler.success());
+
+        Ok(())
+    }
+
+    pub fn plan_up(&self) -> Vec<UpPlanItem> {
+        let manager = self.package_manager();
+        let subject = format!("{} dependencies", manager.name());
+
+        if !self.package_json_path().exists() {
+            return vec![UpPlanItem::skip(&subject, "no package.json found")];
+        }
+
+        if self.is_up_to_date(&self.inputs_hash(manager)) {
+            return vec![UpPlanItem::skip(&subject, "already up to date")];
+        }
+
+        vec![UpPlanItem::run(&format!(
+            "{} {}",
+            manager.name(),
+            self.install_args(manager).join(" ")
+        ))]
+    }
+
+    pub fn plan_down(&self) -> Vec<UpPlanItem> {
+        let node_modules = self.node_modules_path();
+        if node_modules.exists() {
+            vec![UpPlanItem::remove(&format!("{}", node_modules.display()))]
+        } else {
+            vec![]
+        }
+    }
+
+    /// Returns the files that, if changed, require to install the
+    /// dependencies again; this includes the lockfiles of all the
+    /// package managers, as they are used to identify which one to use.
+    pub fn input_files(&self) -> Vec<PathBuf> {
+        let project_dir = self.project_dir();
+        let mut files = vec![self.package_json_path()];
+        for manager in [
+            NodePackageManager::Npm,
+            NodePackageManager::Pnpm,
+            NodePackageManager::Yarn,
+        ] {
+            for lockfile in manager.lockfiles() {
+                files.push(project_dir.join(lockfile));
+            }
+        }
+        files
+    }
+
+    /// Returns whether the dependencies installed during a previous
+    /// run are still where they were installed.
+    pub fn is_set_up(&self) -> bool {
+        !self.package_json_path().exists() || self.node_modules_path().exists()
+    }
+
+    fn is_up_to_date(&self, hash: &str) -> bool {
+        self.node_modules_path().exists() && up_env_hash(&self.hash_key()).as_deref() == Some(hash)
+    }
+
+    fn project_dir(&self) -> PathBuf {
+        let dir = self.dir.clone().unwrap_or(".".to_string());
+        abs_path(Path::new(&dir))
+    }
+
+    fn package_json_path(&self) -> PathBuf {
+        self.project_dir().join("package.json")
+    }
+
+    fn node_modules_path(&self) -> PathBuf {
+        self.project_dir().join("node_modules")
+    }
+
+    fn hash_key(&self) -> String {
+        format!("node-packages:{}", self.dir.clone().unwrap_or_default())
+    }
+
+    /// Returns the `packageManager` field of the package.json file, split
+    /// between the name of the package manager and its version, if any.
+    fn package_json_manager(&self) -> Option<(String, Option<String>)> {
+        let contents = std::fs::read_to_string(self.package_json_path()).ok()?;
+        let package_json: serde_json::Value = serde_json::from_str(&contents).ok()?;
+        let package_manager = package_json.get("packageManager")?.as_str()?;
+
+        match package_manager.split_once('@') {
+            Some((name, version)) => Some((name.to_string(), Some(version.to_string()))),
+            None => Some((package_manager.to_string(), None)),
+        }
+    }
+
+    /// Identifies the package manager to use, first using the
+    /// `packageManager` field of the package.json file, then looking
+    /// at the lockfile available, and finally using the default package
+    /// manager for the step.
+    fn package_manager(&self) -> NodePackageManager {
+        if let Some((name, _)) = self.package_json_manager() {
+            if let Some(manager) = NodePackageManager::from_name(&name) {
+                return manager;
+            }
+        }
+
+        let project_dir = self.project_dir();
+        for manager in [
+            NodePackageManager::Pnpm,
+            NodePackageManager::Yarn,
+            NodePackageManager::Npm,
+        ] {
+            if manager
+                .lockfiles()
+                .iter()
+                .any(|lockfile| project_dir.join(lockfile).exists())
+            {
+                return manager;
+            }
+        }
+
+        self.default_manager
+    }
+
+    fn lockfile_path(&self, manager: NodePackageManager) -> Option<PathBuf> {
+        let project_dir = self.project_dir();
+        manager
+            .lockfiles()
+            .iter()
+            .map(|lockfile| project_dir.join(lockfile))
+            .find(|lockfile| lockfile.exists())
+    }
+
+    fn inputs_hash(&self, manager: NodePackageManager) -> String {
+        let mut files = vec![self.package_json_path()];
+        for lockfile in manager.lockfiles() {
+            files.push(self.project_dir().join(lockfile));
+        }
+        hash_files(manager.name(), &files)
+    }
+
+    fn install_args(&self, manager: NodePackageManager) -> Vec<&'static str> {
+        let has_lockfile = self.lockfile_path(manager).is_some();
+        match manager {
+            NodePackageManager::Npm if has_lockfile => vec!["ci"],
+            NodePackageManager::Npm => vec!["install"],
+            NodePackageManager::Pnpm if has_lockfile => vec!["install", "--frozen-lockfile"],
+            NodePackageManager::Pnpm => vec!["install"],
+            NodePackageManager::Yarn if !has_lockfile => vec!["install"],
+            NodePackageManager::Yarn => {
+                // Yarn berry uses a different flag than yarn classic to
+                // make sure the lockfile is not updated during install
+                if self.project_dir().join(".yarnrc.yml").exists() {
+                    vec!["install", "--immutable"]
+                } else {
+                    vec!["install", "--frozen-lockfile"]
+                }
+            }
+        }
+    }
+
+    /// Enables the package manager through corepack, which will make sure
+    /// that the version specified in the `packageManager` field of the
+    /// package.json file is the one being used.
+    fn enable_package_manager(
+        &self,
+        manager: NodePackageManager,
+        progress_handler: Option<Box<&dyn ProgressHandler>>,
+    ) -> Result<(), UpError> {
+        if manager == NodePackageManager::Npm && self.package_json_manager().is_none() {
+            return Ok(());
+        }
+
+        if !command_exists("corepack") {
+            if command_exists(manager.name()) {
+                return Ok(());
+            }
+
+            return Err(UpError::Exec(format!(
+                "{} is not available and corepack cannot be used to enable it",
+                manager.name()
+            )));
+        }
+
+        progress_handler.clone().map(|progress_handler| {
+            progress_handler.progress(format!("enabling {} through corepack", manager.name()))
+        });
+
+        let mut corepack_enable = TokioCommand::new("corepack");
+        corepack_enable.current_dir(self.project_dir());
+        corepack_enable.arg("enable");
+        corepack_enable.arg(manager.name());
+        corepack_enable.env("COREPACK_ENABLE_DOWNLOAD_PROMPT", "0");
+        corepack_enable.stdout(std::process::Stdio::piped());
+        corepack_enable.stderr(std::process::Stdio::piped());
+
+        run_progress(
+            &mut corepack_enable,
+            progress_handler.clone(),
+            RunConfig::default(),
+        )
+    }
+}
@@ -5,6 +5,7 @@ use node_semver::Range as semverRange;
 use package_json::PackageJsonManager;
 use serde::{Deserialize, Serialize};
 
+use crate::internal::config::up::plan::UpPlanItem;
 use crate::internal::config::up::UpConfigAsdfBase;
 use crate::internal::config::up::UpError;
 use crate::internal::ConfigValue;
@@ -32,6 +33,10 @@ impl UpConfigNodejs {
     pub fn down(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
         self.asdf_base.down(progress)
     }
+
+    pub fn plan_up(&self) -> Vec<UpPlanItem> {
+        self.asdf_base.plan_up()
+    }
 }
 
 fn detect_version_from_package_json(_tool_name: String, path: PathBuf) -> Option<String> {
@@ -0,0 +1,138 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::internal::user_interface::StringColor;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpPlanAction {
+    Install,
+    Run,
+    Skip,
+    Remove,
+}
+
+/// A single change that an up or down operation would make to the
+/// system, as reported when running in plan mode.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpPlanItem {
+    pub action: UpPlanAction,
+    pub subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl UpPlanItem {
+    pub fn new(action: UpPlanAction, subject: &str, detail: Option<&str>) -> Self {
+        Self {
+            action,
+            subject: subject.to_string(),
+            detail: detail.map(|detail| detail.to_string()),
+        }
+    }
+
+    pub fn install(subject: &str) -> Self {
+        Self::new(UpPlanAction::Install, subject, None)
+    }
+
+    pub fn run(subject: &str) -> Self {
+        Self::new(UpPlanAction::Run, subject, None)
+    }
+
+    pub fn skip(subject: &str, reason: &str) -> Self {
+        Self::new(UpPlanAction::Skip, subject, Some(reason))
+    }
+
+    pub fn remove(subject: &str) -> Self {
+        Self::new(UpPlanAction::Remove, subject, None)
+    }
+
+    pub fn with_detail(mut self, detail: &str) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    pub fn is_change(&self) -> bool {
+        self.action != UpPlanAction::Skip
+    }
+
+    pub fn format(&self) -> String {
+        let (symbol, verb) = match self.action {
+            UpPlanAction::Install => ("+".to_string().green(), "install"),
+            UpPlanAction::Run => ("~".to_string().yellow(), "run"),
+            UpPlanAction::Skip => ("=".to_string().light_black(), "skip"),
+            UpPlanAction::Remove => ("-".to_string().red(), "remove"),
+        };
+
+        let line = format!("{} {}", verb, self.subject);
+        let line = match self.action {
+            UpPlanAction::Install => line.green(),
+            UpPlanAction::Run => line.yellow(),
+            UpPlanAction::Skip => line.light_black(),
+            UpPlanAction::Remove => line.red(),
+        };
+
+        let detail = if let Some(detail) = &self.detail {
+            format!(" {}", format!("({})", detail).light_black())
+        } else {
+            "".to_string()
+        };
+
+        format!("{} {}{}", symbol, line, detail)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpPlanStep {
+    pub name: String,
+    pub items: Vec<UpPlanItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UpPlan {
+    pub steps: Vec<UpPlanStep>,
+}
+
+impl UpPlan {
+    pub fn add_step(&mut self, name: &str, items: Vec<UpPlanItem>) {
+        self.steps.push(UpPlanStep {
+            name: name.to_string(),
+            items,
+        });
+    }
+
+    pub fn num_changes(&self) -> usize {
+        self.steps
+            .iter()
+            .map(|step| step.items.iter().filter(|item| item.is_change()).count())
+            .sum()
+    }
+
+    pub fn print(&self) {
+        let num_steps = self.steps.len();
+        let padding = format!("{}", num_steps).len();
+
+        for (idx, step) in self.steps.iter().enumerate() {
+            eprintln!(
+                "{} {}",
+                format!(
+                    "[{:padding$}/{:padding$}]",
+                    idx + 1,
+                    num_steps,
+                    padding = padding
+                )
+                .bold()
+                .light_black(),
+                format!("{}:", step.name).light_blue(),
+            );
+
+            if step.items.is_empty() {
+                eprintln!("  {}", "nothing to do".to_string().light_black());
+            }
+
+            for item in step.items.iter() {
+                eprintln!("  {}", item.format());
+            }
+        }
+    }
+}
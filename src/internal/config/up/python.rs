@@ -1,15 +1,27 @@
+use std::path::Path;
 use std::path::PathBuf;
 
 use lazy_static::lazy_static;
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
+use tokio::process::Command as TokioCommand;
 
 use crate::internal::cache::Cache;
 use crate::internal::cache::UpEnvironments;
 use crate::internal::cache::UpVersion;
+use crate::internal::commands::utils::abs_path;
+use crate::internal::config::up::plan::UpPlanItem;
+use crate::internal::config::up::transaction::record_change;
+use crate::internal::config::up::transaction::UpChange;
+use crate::internal::config::up::utils::command_exists;
+use crate::internal::config::up::utils::hash_files;
+use crate::internal::config::up::utils::run_progress;
+use crate::internal::config::up::utils::set_up_env_hash;
+use crate::internal::config::up::utils::up_env_hash;
 use crate::internal::config::up::utils::PrintProgressHandler;
 use crate::internal::config::up::utils::ProgressHandler;
+use crate::internal::config::up::utils::RunConfig;
 use crate::internal::config::up::utils::SpinnerProgressHandler;
 use crate::internal::config::up::UpConfigAsdfBase;
 use crate::internal::config::up::UpError;
@@ -30,6 +42,7 @@ lazy_static! {
 pub struct UpConfigPython {
     pub version: Option<String>,
     pub with_venv: Option<bool>,
+    pub packages: Option<PythonPackages>,
     #[serde(skip)]
     pub asdf_base: OnceCell<UpConfigAsdfBase>,
 }
@@ -39,6 +52,7 @@ impl UpConfigPython {
         UpConfigPython {
             version: version_from_config(config_value),
             with_venv: with_venv_from_config(config_value),
+            packages: PythonPackages::from_config_value(config_value),
             asdf_base: OnceCell::new(),
         }
     }
@@ -58,26 +72,119 @@ impl UpConfigPython {
 
         if matches!(self.with_venv, Some(true) | None) {
             self.update_cache_for_venv(progress_handler.cloned());
-            if !self.venv_present() {
-                let msg = "setting up venv".to_string().green();
-                progress_handler.map(|ph| ph.success_with_message(msg));
-                self.venv_setup().unwrap();
+
+            let mut msgs = Vec::new();
+            let venv_created = !self.venv_present();
+            if venv_created {
+                progress_handler.map(|ph| ph.progress("setting up venv".to_string()));
+                if let Err(err) = self.venv_setup(progress_handler.cloned()) {
+                    progress_handler.map(|ph| ph.error_with_message(format!("{}", err)));
+                    return Err(err);
+                }
+                record_change(UpChange::PythonVenv(self.clone()));
+                msgs.push("venv set up".to_string().green());
             } else {
-                let msg = "venv already set up".to_string().light_black();
-                progress_handler.map(|ph| ph.success_with_message(msg));
+                msgs.push("venv already set up".to_string().light_black());
+            }
+
+            if let Some(packages) = &self.packages {
+                match packages.install(&self.venv_dir(), venv_created, progress_handler.cloned()) {
+                    Ok(true) => msgs
+                        .push(format!("{} dependencies installed", packages.tool.name()).green()),
+                    Ok(false) => msgs.push(
+                        format!("{} dependencies already up to date", packages.tool.name())
+                            .light_black(),
+                    ),
+                    Err(err) => {
+                        progress_handler.map(|ph| ph.error_with_message(format!("{}", err)));
+                        return Err(err);
+                    }
+                }
             }
+
+            progress_handler.map(|ph| ph.success_with_message(msgs.join(", ")));
+        } else if self.packages.is_some() {
+            let msg = "skipping dependencies (venv disabled)".to_string();
+            progress_handler.map(|ph| ph.success_with_message(msg.light_black()));
         }
 
         Ok(())
     }
 
     pub fn down(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
-        if self.venv_present() {
-            std::fs::remove_dir_all(self.venv_dir()).unwrap()
+        let venv_dir = self.venv_dir();
+        if venv_dir.exists() {
+            let desc = "python".to_string().light_blue();
+            let progress_handler: Box<dyn ProgressHandler> = if ENV.interactive_shell {
+                Box::new(SpinnerProgressHandler::new(desc, progress))
+            } else {
+                Box::new(PrintProgressHandler::new(desc, progress))
+            };
+
+            progress_handler.progress(format!("removing venv {}", venv_dir.display()));
+            if let Err(err) = std::fs::remove_dir_all(&venv_dir) {
+                let msg = format!("failed to remove venv {}: {}", venv_dir.display(), err);
+                progress_handler.error_with_message(msg.clone());
+                return Err(UpError::Exec(msg));
+            }
+
+            // Cleanup the version directory if it does not contain any other venv
+            if let Some(version_dir) = venv_dir.parent() {
+                let _ = std::fs::remove_dir(version_dir);
+            }
+
+            progress_handler.success_with_message("venv removed".to_string());
         }
+
         self.asdf_base()?.down(progress)
     }
 
+    pub fn plan_up(&self) -> Vec<UpPlanItem> {
+        let asdf_base = match self.asdf_base() {
+            Ok(asdf_base) => asdf_base,
+            Err(err) => return vec![UpPlanItem::skip("python", &format!("{}", err))],
+        };
+        let mut items = asdf_base.plan_up();
+
+        if matches!(self.with_venv, Some(false)) {
+            if let Some(packages) = &self.packages {
+                items.push(UpPlanItem::skip(
+                    &format!("{} dependencies", packages.tool.name()),
+                    "venv disabled",
+                ));
+            }
+            return items;
+        }
+
+        let venv_dir = self.venv_dir();
+        let venv_present = self.venv_present();
+        if venv_present {
+            items.push(UpPlanItem::skip("venv", "already set up"));
+        } else {
+            items.push(UpPlanItem::install("venv").with_detail(&format!("{}", venv_dir.display())));
+        }
+
+        if let Some(packages) = &self.packages {
+            let subject = format!("{} dependencies", packages.tool.name());
+            if venv_present && packages.is_up_to_date(&venv_dir) {
+                items.push(UpPlanItem::skip(&subject, "already up to date"));
+            } else {
+                items.push(UpPlanItem::install(&subject));
+            }
+        }
+
+        items
+    }
+
+    pub fn plan_down(&self) -> Vec<UpPlanItem> {
+        let venv_dir = self.venv_dir();
+        if venv_dir.exists() {
+            vec![UpPlanItem::remove(&format!("venv {}", venv_dir.display()))]
+        } else {
+            vec![]
+        }
+    }
+
     pub fn asdf_base(&self) -> Result<&UpConfigAsdfBase, UpError> {
         self.asdf_base.get_or_try_init(|| {
             let version = if let Some(version) = &self.version {
@@ -92,36 +199,71 @@ impl UpConfigPython {
         })
     }
 
-    fn venv_present(&self) -> bool {
-        let dir = self.venv_dir();
-        let dir_exists = dir.try_exists().unwrap();
-        let pyvenv_exists = dir.join("pyvenv.cfg").try_exists().unwrap();
-        if dir_exists ^ pyvenv_exists {
-            panic!("a venv dir exists, but it was not set up");
+    /// Returns the files that, if changed, require to install the
+    /// dependencies again.
+    pub fn input_files(&self) -> Vec<PathBuf> {
+        match &self.packages {
+            Some(packages) => packages.input_files(),
+            None => vec![],
         }
-        pyvenv_exists
     }
 
-    fn venv_setup(&self) -> std::io::Result<()> {
-        let version = &self.asdf_base().unwrap().version;
+    /// Returns the names of the environment variables set by the step.
+    pub fn env_vars(&self) -> Vec<String> {
+        if matches!(self.with_venv, Some(false)) {
+            vec![]
+        } else {
+            vec!["VIRTUAL_ENV".to_string()]
+        }
+    }
+
+    /// Returns whether the venv set up during a previous run is still
+    /// present, if the step uses a venv.
+    pub fn is_set_up(&self) -> bool {
+        matches!(self.with_venv, Some(false)) || self.venv_present()
+    }
+
+    fn venv_present(&self) -> bool {
+        self.venv_dir().join("pyvenv.cfg").exists()
+    }
+
+    fn venv_setup(
+        &self,
+        progress_handler: Option<Box<&dyn ProgressHandler>>,
+    ) -> Result<(), UpError> {
+        let version = self.asdf_base()?.version(None)?;
         let tool_prefix = PathBuf::from(format!("{}/installs/python/{}", *ASDF_PATH, version));
         let python_path = tool_prefix.join("bin").join("python3");
         let venv_dir = self.venv_dir();
-        let venv_bin = &venv_dir.join("bin");
 
-        std::fs::create_dir_all(&venv_dir).unwrap();
-        std::process::Command::new(python_path)
-            .args(["-m", "venv", venv_dir.to_str().unwrap()])
-            .output()
-            .expect("failed to create venv");
+        // If the directory exists but is not a venv, it was most likely left
+        // by a previous failed attempt; remove it so we can start fresh
+        if venv_dir.exists() {
+            if let Err(err) = std::fs::remove_dir_all(&venv_dir) {
+                return Err(UpError::Exec(format!(
+                    "failed to remove {}: {}",
+                    venv_dir.display(),
+                    err
+                )));
+            }
+        }
 
-        // The venv activate scripts can break a given shell session since the activate/deactivate
-        // stashes and unstashes environment values (include PATH) outside of omni's dynenv.
-        for script in ["activate", "activate.csh", "activate.fish", "Activate.ps1"] {
-            //std::fs::remove_file(&venv_bin.join(script))?;
+        if let Err(err) = std::fs::create_dir_all(&venv_dir) {
+            return Err(UpError::Exec(format!(
+                "failed to create {}: {}",
+                venv_dir.display(),
+                err
+            )));
         }
 
-        Ok(())
+        let mut venv_create = TokioCommand::new(python_path);
+        venv_create.arg("-m");
+        venv_create.arg("venv");
+        venv_create.arg(&venv_dir);
+        venv_create.stdout(std::process::Stdio::piped());
+        venv_create.stderr(std::process::Stdio::piped());
+
+        run_progress(&mut venv_create, progress_handler, RunConfig::default())
     }
 
     fn update_cache_for_venv(&self, progress_handler: Option<Box<&dyn ProgressHandler>>) {
@@ -135,7 +277,13 @@ impl UpConfigPython {
             } else {
                 return false;
             };
-            let version = &self.asdf_base().unwrap().version;
+            let version = match self
+                .asdf_base()
+                .and_then(|asdf_base| asdf_base.version(None))
+            {
+                Ok(version) => version,
+                Err(_) => return false,
+            };
 
             let mut up_env = cache.up_environments.as_ref().unwrap().env.clone();
             let repo_up_env = up_env.get_mut(&repo_id).unwrap();
@@ -202,3 +350,275 @@ fn version_from_config(config: Option<&ConfigValue>) -> Option<String> {
             .or(value.get("version").and_then(|version| version.as_str()))
     })
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum PythonPackagesTool {
+    Pip,
+    Pipenv,
+    Poetry,
+    Uv,
+}
+
+impl PythonPackagesTool {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PythonPackagesTool::Pip => "pip",
+            PythonPackagesTool::Pipenv => "pipenv",
+            PythonPackagesTool::Poetry => "poetry",
+            PythonPackagesTool::Uv => "uv",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PythonPackages {
+    pub tool: PythonPackagesTool,
+    pub files: Vec<String>,
+}
+
+impl PythonPackages {
+    fn from_config_value(config_value: Option<&ConfigValue>) -> Option<Self> {
+        let config_value = config_value?;
+
+        for tool in [
+            PythonPackagesTool::Pip,
+            PythonPackagesTool::Pipenv,
+            PythonPackagesTool::Poetry,
+            PythonPackagesTool::Uv,
+        ] {
+            let value = match config_value.get(tool.name()) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            // The tool can be disabled explicitly, e.g. `pip: false`
+            if value.as_bool() == Some(false) {
+                continue;
+            }
+
+            let mut files = Vec::new();
+            if let Some(array) = value.as_array() {
+                for file in array {
+                    if let Some(file) = file.as_str() {
+                        files.push(file.to_string());
+                    }
+                }
+            } else if let Some(file) = value.as_str() {
+                if file != "auto" {
+                    files.push(file.to_string());
+                }
+            }
+
+            return Some(PythonPackages { tool, files });
+        }
+
+        None
+    }
+
+    /// Returns the requirements files to install with pip or uv, either
+    /// as specified in the configuration, or all the `requirements*.txt`
+    /// files at the root of the work directory.
+    fn requirements_files(&self) -> Vec<PathBuf> {
+        if !self.files.is_empty() {
+            return self.files.iter().map(abs_path).collect();
+        }
+
+        let mut files = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(".") {
+            for entry in entries.flatten() {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                if file_name.starts_with("requirements") && file_name.ends_with(".txt") {
+                    files.push(abs_path(&file_name));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    /// Returns the files that, if changed, require to install the
+    /// dependencies again.
+    fn input_files(&self) -> Vec<PathBuf> {
+        let project_files = ["pyproject.toml", "setup.py", "setup.cfg"]
+            .iter()
+            .map(abs_path)
+            .collect::<Vec<PathBuf>>();
+
+        match self.tool {
+            PythonPackagesTool::Pip => {
+                let requirements = self.requirements_files();
+                if requirements.is_empty() {
+                    project_files
+                } else {
+                    requirements
+                }
+            }
+            PythonPackagesTool::Pipenv => vec![abs_path("Pipfile"), abs_path("Pipfile.lock")],
+            PythonPackagesTool::Poetry => vec![abs_path("pyproject.toml"), abs_path("poetry.lock")],
+            PythonPackagesTool::Uv => {
+                if abs_path("uv.lock").exists() {
+                    vec![abs_path("pyproject.toml"), abs_path("uv.lock")]
+                } else {
+                    self.requirements_files()
+                }
+            }
+        }
+    }
+
+    fn hash_key(&self) -> String {
+        format!("python-packages:{}", self.tool.name())
+    }
+
+    fn inputs_hash(&self, venv_dir: &Path) -> String {
+        hash_files(
+            &format!("{}:{}", self.tool.name(), venv_dir.display()),
+            &self.input_files(),
+        )
+    }
+
+    fn is_up_to_date(&self, venv_dir: &Path) -> bool {
+        up_env_hash(&self.hash_key()).as_deref() == Some(self.inputs_hash(venv_dir).as_str())
+    }
+
+    /// Installs the dependencies in the given venv, returning `false` if
+    /// the dependencies were already up to date; the dependencies are
+    /// always installed if `force` is set, e.g. for a newly created venv.
+    fn install(
+        &self,
+        venv_dir: &Path,
+        force: bool,
+        progress_handler: Option<Box<&dyn ProgressHandler>>,
+    ) -> Result<bool, UpError> {
+        let hash = self.inputs_hash(venv_dir);
+        if !force && up_env_hash(&self.hash_key()).as_deref() == Some(hash.as_str()) {
+            return Ok(false);
+        }
+
+        let venv_bin = venv_dir.join("bin");
+        let tool_bin = self.tool_bin(&venv_bin, progress_handler.clone())?;
+
+        progress_handler.clone().map(|progress_handler| {
+            progress_handler.progress(format!("installing {} dependencies", self.tool.name()))
+        });
+
+        let mut commands = Vec::new();
+        match self.tool {
+            PythonPackagesTool::Pip => {
+                // Without requirements files, install the project itself,
+                // as long as there is a project to install
+                let requirements = self.requirements_files();
+                let has_project = ["pyproject.toml", "setup.py"]
+                    .iter()
+                    .any(|file| abs_path(file).exists());
+                if requirements.is_empty() && has_project {
+                    commands.push(vec![
+                        "install".to_string(),
+                        "-e".to_string(),
+                        ".".to_string(),
+                    ]);
+                }
+                for requirement in requirements {
+                    commands.push(vec![
+                        "install".to_string(),
+                        "-r".to_string(),
+                        requirement.to_string_lossy().to_string(),
+                    ]);
+                }
+            }
+            PythonPackagesTool::Pipenv => {
+                if abs_path("Pipfile.lock").exists() {
+                    commands.push(vec!["sync".to_string()]);
+                } else {
+                    commands.push(vec!["install".to_string()]);
+                }
+            }
+            PythonPackagesTool::Poetry => {
+                commands.push(vec!["install".to_string(), "--no-interaction".to_string()]);
+            }
+            PythonPackagesTool::Uv => {
+                if abs_path("uv.lock").exists() {
+                    commands.push(vec!["sync".to_string(), "--frozen".to_string()]);
+                } else {
+                    for requirement in self.requirements_files() {
+                        commands.push(vec![
+                            "pip".to_string(),
+                            "install".to_string(),
+                            "-r".to_string(),
+                            requirement.to_string_lossy().to_string(),
+                        ]);
+                    }
+                }
+            }
+        }
+
+        for args in commands {
+            let mut install = TokioCommand::new(&tool_bin);
+            install.args(args);
+            self.set_venv_env(&mut install, venv_dir);
+            install.stdout(std::process::Stdio::piped());
+            install.stderr(std::process::Stdio::piped());
+
+            run_progress(&mut install, progress_handler.clone(), RunConfig::default())?;
+        }
+
+        if let Err(err) = set_up_env_hash(&self.hash_key(), Some(hash)) {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.progress(format!("failed to update cache: {}", err))
+            });
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the path to the binary of the tool to use to install
+    /// the dependencies; if the tool is not available on the system,
+    /// it will be installed in the venv.
+    fn tool_bin(
+        &self,
+        venv_bin: &Path,
+        progress_handler: Option<Box<&dyn ProgressHandler>>,
+    ) -> Result<PathBuf, UpError> {
+        let venv_tool = venv_bin.join(self.tool.name());
+        if venv_tool.exists() || self.tool == PythonPackagesTool::Pip {
+            return Ok(venv_tool);
+        }
+
+        if command_exists(self.tool.name()) {
+            return Ok(PathBuf::from(self.tool.name()));
+        }
+
+        progress_handler.clone().map(|progress_handler| {
+            progress_handler.progress(format!("installing {} in venv", self.tool.name()))
+        });
+
+        let mut pip_install = TokioCommand::new(venv_bin.join("pip"));
+        pip_install.arg("install");
+        pip_install.arg(self.tool.name());
+        pip_install.stdout(std::process::Stdio::piped());
+        pip_install.stderr(std::process::Stdio::piped());
+
+        run_progress(&mut pip_install, progress_handler, RunConfig::default())?;
+
+        Ok(venv_tool)
+    }
+
+    fn set_venv_env(&self, command: &mut TokioCommand, venv_dir: &Path) {
+        let venv_bin = venv_dir.join("bin");
+        let path_env = std::env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![venv_bin];
+        paths.extend(std::env::split_paths(&path_env));
+        if let Ok(path_env) = std::env::join_paths(paths) {
+            command.env("PATH", path_env);
+        }
+
+        command.env("VIRTUAL_ENV", venv_dir);
+        command.env_remove("PYTHONHOME");
+
+        // Make sure the tools install the dependencies in our venv
+        // instead of creating their own
+        command.env("POETRY_VIRTUALENVS_CREATE", "false");
+        command.env("UV_PROJECT_ENVIRONMENT", venv_dir);
+        command.env("PIPENV_IGNORE_VIRTUALENVS", "0");
+        command.env("PIPENV_VERBOSITY", "-1");
+    }
+}
@@ -0,0 +1,316 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::internal::commands::utils::abs_path;
+use crate::internal::config::up::utils::hash_files;
+use crate::internal::config::up::UpConfigCondition;
+use crate::internal::config::up::UpConfigTool;
+use crate::internal::config::up::UpError;
+use crate::internal::config::ConfigValue;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpConfigStep {
+    pub index: usize,
+    pub tool: UpConfigTool,
+    pub needs: Vec<String>,
+    pub condition: Option<UpConfigCondition>,
+    pub inputs: Vec<String>,
+    pub config: String,
+}
+
+impl UpConfigStep {
+    /// Parses the step at the given position of the `up` configuration;
+    /// the position starts at 1, as shown to the user.
+    pub fn from_config_value(
+        index: usize,
+        up_name: &str,
+        config_value: Option<&ConfigValue>,
+    ) -> Option<Self> {
+        let tool = UpConfigTool::from_config_value(up_name, config_value)?;
+
+        let needs = string_or_list(config_value, "needs");
+        let inputs = string_or_list(config_value, "inputs");
+
+        // The configuration of the step is part of its inputs; we use
+        // the sorted yaml representation so that it is stable between
+        // runs regardless of the order in which the keys were loaded
+        let config = format!(
+            "{}:{}",
+            up_name,
+            config_value
+                .map(|config_value| config_value.as_yaml())
+                .unwrap_or_default()
+        );
+
+        Some(UpConfigStep {
+            index,
+            tool,
+            needs,
+            condition: UpConfigCondition::from_config_value(config_value),
+            inputs,
+            config,
+        })
+    }
+
+    pub fn has_name(&self, name: &str) -> bool {
+        self.tool.names().iter().any(|step_name| step_name == name)
+    }
+
+    /// Returns whether the step matches the given selector, which can
+    /// be any of the names of the step, or its position.
+    pub fn matches(&self, selector: &str) -> bool {
+        match selector.parse::<usize>() {
+            Ok(index) => index == self.index,
+            Err(_) => self.has_name(selector),
+        }
+    }
+
+    /// Returns the name under which the step is shown in the output.
+    pub fn name(&self) -> String {
+        let names = self.tool.names();
+        match &self.tool {
+            UpConfigTool::Asdf(_) | UpConfigTool::Custom(_) => names.last(),
+            _ => names.first(),
+        }
+        .unwrap()
+        .clone()
+    }
+
+    /// Returns the reason why the step should be skipped, if the
+    /// condition defined in its `if` parameter is not met.
+    pub fn skip_reason(&self) -> Option<String> {
+        self.condition
+            .as_ref()
+            .and_then(|condition| condition.unmet_reason())
+    }
+
+    /// Returns the key under which the hash of the inputs of the step is
+    /// stored in the up environment; the position of the step is part of
+    /// it so that two steps of the same type do not share their hash.
+    pub fn hash_key(&self) -> String {
+        format!("up-step:{}:{}", self.index, self.name())
+    }
+
+    /// Returns the hash of the inputs of the step, which are its own
+    /// configuration, the files it depends on, and the versions it
+    /// resolved; returns `None` if the inputs of the step cannot be
+    /// known, in which case the step always needs to run. This needs
+    /// to be called from the directory in which the step runs.
+    pub fn inputs_hash(&self) -> Option<String> {
+        // Custom steps can do anything, so we can only know their
+        // inputs if they were declared in the configuration
+        if matches!(self.tool, UpConfigTool::Custom(_)) && self.inputs.is_empty() {
+            return None;
+        }
+
+        let mut files = self.inputs.iter().map(abs_path).collect::<Vec<PathBuf>>();
+        files.extend(self.tool.input_files());
+
+        Some(hash_files(
+            &format!("{}\0{}", self.config, self.tool.inputs_salt()),
+            &files,
+        ))
+    }
+}
+
+/// The steps selected with the `--only` and `--skip` parameters; a
+/// step can be selected by its type (e.g. `bundler`), by the `name` of
+/// a custom step, or by its position in the `up` configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UpStepSelection {
+    pub only: Vec<String>,
+    pub skip: Vec<String>,
+}
+
+impl UpStepSelection {
+    pub fn new(only: Vec<String>, skip: Vec<String>) -> Self {
+        Self { only, skip }
+    }
+
+    /// Returns whether all the steps are selected.
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.skip.is_empty()
+    }
+
+    pub fn includes(&self, step: &UpConfigStep) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|selector| step.matches(selector)))
+            && !self.skip.iter().any(|selector| step.matches(selector))
+    }
+
+    /// Returns the selectors that do not match any of the given steps.
+    pub fn unmatched(&self, steps: &[UpConfigStep]) -> Vec<String> {
+        self.only
+            .iter()
+            .chain(self.skip.iter())
+            .filter(|selector| !steps.iter().any(|step| step.matches(selector)))
+            .cloned()
+            .collect()
+    }
+}
+
+fn string_or_list(config_value: Option<&ConfigValue>, key: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    if let Some(value) = config_value.and_then(|config_value| config_value.get(key)) {
+        if let Some(array) = value.as_array() {
+            for item in array {
+                if let Some(item) = item.as_str() {
+                    values.push(item.to_string());
+                }
+            }
+        } else if let Some(item) = value.as_str() {
+            values.push(item.to_string());
+        }
+    }
+    values
+}
+
+/// Identifies, for each step, the indexes of the steps it depends on;
+/// this includes the steps explicitly listed in `needs`, the implicit
+/// dependencies of the tool, the previous package manager steps for
+/// language tools, the custom steps acting as barriers, and the
+/// previous steps sharing the same concurrency group.
+fn dependencies(steps: &[UpConfigStep]) -> Vec<Vec<usize>> {
+    let mut dependencies = Vec::new();
+
+    for (idx, step) in steps.iter().enumerate() {
+        let mut step_deps = Vec::new();
+
+        // Steps that are needed but not available on the current system
+        // will simply not be found, which is why we do not fail here
+        for need in step.needs.iter() {
+            step_deps.extend(
+                steps
+                    .iter()
+                    .enumerate()
+                    .filter(|(other_idx, other)| *other_idx != idx && other.has_name(need))
+                    .map(|(other_idx, _)| other_idx),
+            );
+        }
+
+        // Implicit dependencies only apply to the steps defined before,
+        // as they are a convenience for the order steps are usually in
+        for need in step.tool.implicit_needs() {
+            step_deps.extend(
+                steps[..idx]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| other.has_name(&need))
+                    .map(|(other_idx, _)| other_idx),
+            );
+        }
+
+        // Language tools might be built from source or build native
+        // extensions, using the libraries installed by the package
+        // managers, so they wait for the package manager steps before them
+        if uses_system_packages(step) {
+            step_deps.extend(
+                steps[..idx]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| installs_system_packages(other))
+                    .map(|(other_idx, _)| other_idx),
+            );
+        }
+
+        // Custom steps can do anything, so unless told otherwise, we
+        // consider them as barriers: they depend on all the steps before
+        // them, and all the steps after them depend on them
+        if is_barrier(step) {
+            step_deps.extend(0..idx);
+        }
+        if let Some(barrier_idx) = steps[..idx].iter().rposition(is_barrier) {
+            step_deps.push(barrier_idx);
+        }
+
+        if let Some(group) = step.tool.concurrency_group() {
+            if let Some(other_idx) = steps[..idx]
+                .iter()
+                .rposition(|other| other.tool.concurrency_group() == Some(group.clone()))
+            {
+                step_deps.push(other_idx);
+            }
+        }
+
+        step_deps.sort();
+        step_deps.dedup();
+        dependencies.push(step_deps);
+    }
+
+    dependencies
+}
+
+/// Returns whether the step is a custom step without `needs`, which
+/// cannot run concurrently with any of the steps around it.
+fn is_barrier(step: &UpConfigStep) -> bool {
+    matches!(step.tool, UpConfigTool::Custom(_)) && step.needs.is_empty()
+}
+
+/// Returns whether the step installs packages with homebrew or a
+/// system package manager.
+fn installs_system_packages(step: &UpConfigStep) -> bool {
+    matches!(
+        step.tool,
+        UpConfigTool::Homebrew(_)
+            | UpConfigTool::Apt(_)
+            | UpConfigTool::Dnf(_)
+            | UpConfigTool::Pacman(_)
+    )
+}
+
+/// Returns whether the step installs a language tool or its packages,
+/// which might depend on the libraries installed by package managers.
+fn uses_system_packages(step: &UpConfigStep) -> bool {
+    step.tool.asdf_tool().is_some()
+        || matches!(
+            step.tool,
+            UpConfigTool::Bundler(_) | UpConfigTool::NodePackages(_)
+        )
+}
+
+/// Splits the steps in waves of steps that can run concurrently, each
+/// wave only containing steps for which all the dependencies have been
+/// handled in the previous waves; steps that need to run alone get
+/// a wave of their own.
+pub fn schedule(steps: &[UpConfigStep]) -> Result<Vec<Vec<usize>>, UpError> {
+    let dependencies = dependencies(steps);
+
+    let mut done = vec![false; steps.len()];
+    let mut waves = Vec::new();
+
+    while done.iter().any(|done| !done) {
+        let ready = (0..steps.len())
+            .filter(|idx| !done[*idx])
+            .filter(|idx| dependencies[*idx].iter().all(|dep| done[*dep]))
+            .collect::<Vec<usize>>();
+
+        if ready.is_empty() {
+            let blocked = (0..steps.len())
+                .filter(|idx| !done[*idx])
+                .map(|idx| format!("{}", idx + 1))
+                .collect::<Vec<String>>();
+            return Err(UpError::Config(format!(
+                "circular dependency between steps {}",
+                blocked.join(", ")
+            )));
+        }
+
+        // To stay as close as possible to the order of the configuration,
+        // the wave stops at the first ready step that needs to run alone,
+        // which runs right away if it is the first ready step, or in the
+        // next wave otherwise
+        let wave = match ready.iter().position(|idx| steps[*idx].tool.runs_alone()) {
+            Some(0) => vec![ready[0]],
+            Some(alone_idx) => ready[..alone_idx].to_vec(),
+            None => ready,
+        };
+
+        for idx in wave.iter() {
+            done[*idx] = true;
+        }
+        waves.push(wave);
+    }
+
+    Ok(waves)
+}
@@ -0,0 +1,775 @@
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::process::Command as TokioCommand;
+
+use crate::internal::cache::Cache;
+use crate::internal::cache::SystemPackageInstalled;
+use crate::internal::cache::SystemPackageOperation;
+use crate::internal::config::up::plan::UpPlanItem;
+use crate::internal::config::up::transaction::record_change;
+use crate::internal::config::up::transaction::UpChange;
+use crate::internal::config::up::utils::command_exists;
+use crate::internal::config::up::utils::run_progress;
+use crate::internal::config::up::utils::PrintProgressHandler;
+use crate::internal::config::up::utils::ProgressHandler;
+use crate::internal::config::up::utils::RunConfig;
+use crate::internal::config::up::utils::SpinnerProgressHandler;
+use crate::internal::config::up::UpError;
+use crate::internal::config::ConfigValue;
+use crate::internal::env::ENV;
+use crate::internal::user_interface::StringColor;
+use crate::internal::workdir;
+use crate::omni_warning;
+
+static PACKAGES_INDEX_UPDATED: OnceCell<bool> = OnceCell::new();
+static PRIVILEGES_VALIDATED: OnceCell<bool> = OnceCell::new();
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum SystemPackageManager {
+    Apt,
+    Dnf,
+    Pacman,
+}
+
+impl SystemPackageManager {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SystemPackageManager::Apt => "apt",
+            SystemPackageManager::Dnf => "dnf",
+            SystemPackageManager::Pacman => "pacman",
+        }
+    }
+
+    fn bin(&self) -> &'static str {
+        match self {
+            SystemPackageManager::Apt => "apt-get",
+            SystemPackageManager::Dnf => "dnf",
+            SystemPackageManager::Pacman => "pacman",
+        }
+    }
+
+    fn is_available(&self) -> bool {
+        command_exists(self.bin())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpConfigSystemPackages {
+    pub manager: SystemPackageManager,
+    pub install: Vec<SystemPackageInstall>,
+}
+
+impl UpConfigSystemPackages {
+    pub fn from_config_value(
+        manager: SystemPackageManager,
+        config_value: Option<&ConfigValue>,
+    ) -> Self {
+        let install = SystemPackageInstall::from_config_value(manager, config_value);
+
+        UpConfigSystemPackages { manager, install }
+    }
+
+    pub fn up(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
+        let desc = format!("install {} packages:", self.manager.name()).light_blue();
+        let main_progress_handler = PrintProgressHandler::new(desc, progress);
+
+        if self.install.is_empty() {
+            main_progress_handler
+                .success_with_message("skipping (nothing to do)".to_string().light_black());
+            return Ok(());
+        }
+
+        main_progress_handler.progress("".to_string());
+
+        let num_installs = self.install.len();
+        for (idx, install) in self.install.iter().enumerate() {
+            if let Err(err) = install.up(progress, Some((idx + 1, num_installs))) {
+                main_progress_handler.error();
+                return Err(err);
+            }
+        }
+
+        let num_handled_installs = self
+            .install
+            .iter()
+            .filter(|install| install.was_handled())
+            .count();
+
+        main_progress_handler.success_with_message(format!(
+            "installed {} package{}",
+            num_handled_installs,
+            if num_handled_installs > 1 { "s" } else { "" },
+        ));
+
+        Ok(())
+    }
+
+    pub fn down(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
+        let mut return_value = Ok(());
+
+        if let Err(err) = Cache::exclusive(|cache| {
+            let workdir = workdir(".");
+            let repo_id = workdir.id();
+            if repo_id.is_none() {
+                return false;
+            }
+            let repo_id = repo_id.unwrap();
+
+            if let Some(pkg_cache) = &mut cache.system_package_operation {
+                let desc =
+                    format!("uninstall (unused) {} packages:", self.manager.name()).light_blue();
+                let main_progress_handler = PrintProgressHandler::new(desc, progress);
+                main_progress_handler.progress("".to_string());
+
+                let mut updated = false;
+
+                let mut to_uninstall = Vec::new();
+                for (idx, install) in pkg_cache.installed.iter_mut().enumerate().rev() {
+                    if install.manager != self.manager.name() {
+                        continue;
+                    }
+                    if install.required_by.contains(&repo_id) {
+                        install.required_by.retain(|id| id != &repo_id);
+                        updated = true;
+                    }
+                    if install.required_by.is_empty() && install.installed {
+                        to_uninstall
+                            .push((idx, SystemPackageInstall::from_cache(self.manager, install)));
+                    }
+                }
+
+                let num_uninstalls = to_uninstall.len();
+                for (idx, (rmidx, install)) in to_uninstall.iter().enumerate() {
+                    if let Err(err) = install.down(progress, Some((idx + 1, num_uninstalls))) {
+                        main_progress_handler.error();
+                        return_value = Err(err);
+                        return updated;
+                    }
+                    pkg_cache.installed.remove(*rmidx);
+                    updated = true;
+                }
+
+                let current_installed = pkg_cache.installed.len();
+                pkg_cache
+                    .installed
+                    .retain(|install| !install.required_by.is_empty());
+                if current_installed != pkg_cache.installed.len() {
+                    updated = true;
+                }
+
+                if updated {
+                    let num_handled_installs = to_uninstall
+                        .iter()
+                        .filter(|(_idx, install)| install.was_handled())
+                        .count();
+
+                    main_progress_handler.success_with_message(format!(
+                        "uninstalled {} package{}",
+                        num_handled_installs,
+                        if num_handled_installs > 1 { "s" } else { "" },
+                    ));
+
+                    true
+                } else {
+                    main_progress_handler.success_with_message(format!(
+                        "no {} packages to uninstall",
+                        self.manager.name()
+                    ));
+
+                    false
+                }
+            } else {
+                false
+            }
+        }) {
+            omni_warning!(format!("failed to update cache: {}", err));
+        }
+
+        return_value
+    }
+
+    pub fn plan_up(&self) -> Vec<UpPlanItem> {
+        self.install
+            .iter()
+            .map(|install| {
+                if install.is_installed() {
+                    UpPlanItem::skip(&install.package_id(), "already installed")
+                } else {
+                    UpPlanItem::install(&install.package_id())
+                }
+            })
+            .collect()
+    }
+
+    pub fn plan_down(&self) -> Vec<UpPlanItem> {
+        let repo_id = match workdir(".").id() {
+            Some(repo_id) => repo_id,
+            None => return vec![],
+        };
+
+        let cache = match Cache::shared() {
+            Ok(cache) => cache,
+            Err(_) => return vec![],
+        };
+
+        let mut items = Vec::new();
+        if let Some(pkg_cache) = &cache.system_package_operation {
+            for install in pkg_cache.installed.iter() {
+                if install.manager == self.manager.name()
+                    && install.installed
+                    && install.required_by.iter().all(|id| id == &repo_id)
+                {
+                    let install = SystemPackageInstall::from_cache(self.manager, install);
+                    items.push(UpPlanItem::remove(&install.package_id()));
+                }
+            }
+        }
+
+        items
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.manager.is_available()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SystemPackageInstall {
+    manager: SystemPackageManager,
+    name: String,
+    version: Option<String>,
+
+    #[serde(skip)]
+    was_handled: OnceCell<bool>,
+}
+
+impl SystemPackageInstall {
+    fn from_config_value(
+        manager: SystemPackageManager,
+        config_value: Option<&ConfigValue>,
+    ) -> Vec<Self> {
+        let mut installs = Vec::new();
+
+        if let Some(config_value) = config_value {
+            if let Some(config_value) = config_value.as_table() {
+                if let Some(packages) = config_value.get("install") {
+                    installs.extend(Self::parse_packages(manager, packages));
+                }
+            } else {
+                installs.extend(Self::parse_packages(manager, config_value));
+            }
+        }
+
+        installs
+    }
+
+    fn parse_packages(manager: SystemPackageManager, packages: &ConfigValue) -> Vec<Self> {
+        let mut installs = Vec::new();
+
+        if let Some(packages) = packages.as_array() {
+            for package_config_value in packages {
+                let mut version = None;
+                let mut name = None;
+
+                if let Some(package_config) = package_config_value.as_table() {
+                    let mut rest_of_config = package_config_value.clone();
+
+                    if let Some(package) = package_config.get("package") {
+                        name = package.as_str_forced();
+                    } else if package_config.len() == 1 {
+                        let (key, value) = package_config.iter().next().unwrap();
+                        name = Some(key.to_string());
+                        rest_of_config = value.clone();
+                    }
+
+                    let parse_version = if rest_of_config.is_table() {
+                        rest_of_config.get("version")
+                    } else {
+                        Some(rest_of_config)
+                    };
+
+                    if let Some(parse_version) = parse_version {
+                        version = parse_version.as_str_forced();
+                    }
+                } else if let Some(package) = package_config_value.as_str() {
+                    name = Some(package.to_string());
+                }
+
+                if let Some(name) = name {
+                    installs.push(Self {
+                        manager,
+                        name,
+                        version,
+                        was_handled: OnceCell::new(),
+                    });
+                }
+            }
+        } else if let Some(package) = packages.as_str() {
+            installs.push(Self {
+                manager,
+                name: package.to_string(),
+                version: None,
+                was_handled: OnceCell::new(),
+            });
+        }
+
+        installs
+    }
+
+    fn from_cache(manager: SystemPackageManager, cached: &SystemPackageInstalled) -> Self {
+        Self {
+            manager,
+            name: cached.name.clone(),
+            version: cached.version.clone(),
+            was_handled: OnceCell::new(),
+        }
+    }
+
+    fn update_cache(&self, progress_handler: Option<Box<&dyn ProgressHandler>>) {
+        progress_handler
+            .clone()
+            .map(|progress_handler| progress_handler.progress("updating cache".to_string()));
+
+        let result = Cache::exclusive(|cache| {
+            let mut installed = Vec::new();
+
+            let workdir = workdir(".");
+            let repo_id = workdir.id();
+            if repo_id.is_none() {
+                return false;
+            }
+            let repo_id = repo_id.unwrap();
+
+            if let Some(pkg_cache) = &cache.system_package_operation {
+                installed.extend(pkg_cache.installed.clone());
+            }
+
+            let mut found = false;
+            for exists in installed.iter_mut() {
+                if exists.manager == self.manager.name()
+                    && exists.name == self.name
+                    && exists.version == self.version
+                {
+                    exists.installed = exists.installed || self.was_handled();
+                    if !exists.required_by.contains(&repo_id) {
+                        exists.required_by.push(repo_id.clone());
+                    }
+                    found = true;
+                    break;
+                }
+            }
+
+            if !found {
+                installed.push(SystemPackageInstalled {
+                    manager: self.manager.name().to_string(),
+                    name: self.name.clone(),
+                    version: self.version.clone(),
+                    installed: self.was_handled(),
+                    required_by: vec![repo_id.clone()],
+                });
+            }
+
+            cache.system_package_operation = Some(SystemPackageOperation {
+                installed: installed.clone(),
+                updated_at: OffsetDateTime::now_utc(),
+            });
+
+            true
+        });
+
+        if let Err(err) = result {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.progress(format!("failed to update cache: {}", err))
+            });
+        } else {
+            progress_handler
+                .clone()
+                .map(|progress_handler| progress_handler.progress("updated cache".to_string()));
+        }
+    }
+
+    fn up(
+        &self,
+        main_progress: Option<(usize, usize)>,
+        sub_progress: Option<(usize, usize)>,
+    ) -> Result<(), UpError> {
+        let desc = self.desc("install", sub_progress);
+
+        let progress_handler: Box<dyn ProgressHandler> = if ENV.interactive_shell {
+            Box::new(SpinnerProgressHandler::new(desc, main_progress))
+        } else {
+            Box::new(PrintProgressHandler::new(desc, main_progress))
+        };
+        let progress_handler: Option<Box<&dyn ProgressHandler>> =
+            Some(Box::new(progress_handler.as_ref()));
+
+        if self.is_installed() {
+            self.update_cache(progress_handler.clone());
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.success_with_message("already installed".to_string().light_black())
+            });
+            return Ok(());
+        }
+
+        if let Err(err) = self.install(progress_handler.clone()) {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.error_with_message(err.to_string());
+            });
+            return Err(err);
+        }
+        // Record a copy that was not handled yet, so that it can be
+        // handled again when uninstalling it during a rollback
+        record_change(UpChange::SystemPackageInstall(Self {
+            was_handled: OnceCell::new(),
+            ..self.clone()
+        }));
+
+        self.update_cache(progress_handler.clone());
+        progress_handler.clone().map(|progress_handler| {
+            progress_handler.success_with_message("installed".to_string().light_green());
+        });
+
+        Ok(())
+    }
+
+    fn down(
+        &self,
+        main_progress: Option<(usize, usize)>,
+        sub_progress: Option<(usize, usize)>,
+    ) -> Result<(), UpError> {
+        let desc = self.desc("uninstall", sub_progress);
+
+        let progress_handler: Box<dyn ProgressHandler> = if ENV.interactive_shell {
+            Box::new(SpinnerProgressHandler::new(desc, main_progress))
+        } else {
+            Box::new(PrintProgressHandler::new(desc, main_progress))
+        };
+        let progress_handler: Option<Box<&dyn ProgressHandler>> =
+            Some(Box::new(progress_handler.as_ref()));
+
+        if self.installed_version().is_none() {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.success_with_message("not installed".to_string().light_black())
+            });
+            return Ok(());
+        }
+
+        if let Err(err) = self.uninstall(progress_handler.clone()) {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.error_with_message(err.to_string());
+            });
+            return Err(err);
+        }
+
+        progress_handler.clone().map(|progress_handler| {
+            progress_handler.success_with_message("uninstalled".to_string().light_green());
+        });
+
+        Ok(())
+    }
+
+    /// Uninstalls the package installed during the current run, and
+    /// removes the current repository from the repositories requiring it.
+    pub fn rollback(&self) -> Result<(), UpError> {
+        self.down(None, None)?;
+
+        if let Err(err) = Cache::exclusive(|cache| {
+            let repo_id = match workdir(".").id() {
+                Some(repo_id) => repo_id,
+                None => return false,
+            };
+
+            if let Some(pkg_cache) = &mut cache.system_package_operation {
+                let is_self = |install: &SystemPackageInstalled| {
+                    install.manager == self.manager.name()
+                        && install.name == self.name
+                        && install.version == self.version
+                };
+                for install in pkg_cache.installed.iter_mut() {
+                    if is_self(install) {
+                        install.required_by.retain(|id| id != &repo_id);
+                    }
+                }
+                pkg_cache
+                    .installed
+                    .retain(|install| !is_self(install) || !install.required_by.is_empty());
+                return true;
+            }
+
+            false
+        }) {
+            return Err(UpError::Exec(format!("failed to update cache: {}", err)));
+        }
+
+        Ok(())
+    }
+
+    fn desc(&self, action: &str, sub_progress: Option<(usize, usize)>) -> String {
+        let progress_str = if let Some((current, total)) = sub_progress {
+            let padding = format!("{}", total).len();
+            format!(
+                "[{:padding$}/{:padding$}] ",
+                current,
+                total,
+                padding = padding,
+            )
+        } else {
+            "".to_string()
+        };
+
+        let version_hint = if let Some(version) = &self.version {
+            format!(" ({})", version)
+        } else {
+            "".to_string()
+        };
+
+        format!(
+            "  {}{} {}{}:",
+            progress_str, action, self.name, version_hint
+        )
+        .light_yellow()
+    }
+
+    fn package_id(&self) -> String {
+        match &self.version {
+            Some(version) => match self.manager {
+                SystemPackageManager::Apt | SystemPackageManager::Pacman => {
+                    format!("{}={}", self.name, version)
+                }
+                SystemPackageManager::Dnf => format!("{}-{}", self.name, version),
+            },
+            None => self.name.clone(),
+        }
+    }
+
+    /// Returns the version of the package currently installed on the
+    /// system, or `None` if the package is not installed.
+    fn installed_version(&self) -> Option<String> {
+        let mut query = match self.manager {
+            SystemPackageManager::Apt => {
+                let mut query = std::process::Command::new("dpkg-query");
+                query.arg("--show");
+                query.arg("--showformat=${db:Status-Status} ${Version}");
+                query
+            }
+            SystemPackageManager::Dnf => {
+                let mut query = std::process::Command::new("rpm");
+                query.arg("--query");
+                query.arg("--queryformat=installed %{VERSION}-%{RELEASE}");
+                query
+            }
+            SystemPackageManager::Pacman => {
+                let mut query = std::process::Command::new("pacman");
+                query.arg("--query");
+                query
+            }
+        };
+        query.arg(&self.name);
+        query.stdout(std::process::Stdio::piped());
+        query.stderr(std::process::Stdio::null());
+
+        let output = query.output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let output = String::from_utf8(output.stdout).ok()?;
+        let mut parts = output.split_whitespace();
+        let status = parts.next()?;
+        let version = parts.next()?;
+
+        match self.manager {
+            SystemPackageManager::Pacman if status == self.name => Some(version.to_string()),
+            SystemPackageManager::Apt | SystemPackageManager::Dnf if status == "installed" => {
+                Some(version.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn is_installed(&self) -> bool {
+        match (self.installed_version(), &self.version) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(installed), Some(expected)) => {
+                // The installed version can contain a release or epoch part
+                // that is not necessarily specified in the configuration
+                installed == *expected
+                    || installed.starts_with(&format!("{}-", expected))
+                    || installed.ends_with(&format!(":{}", expected))
+            }
+        }
+    }
+
+    fn privileged_command(
+        &self,
+        progress_handler: Option<Box<&dyn ProgressHandler>>,
+    ) -> Result<TokioCommand, UpError> {
+        if is_root() {
+            return Ok(TokioCommand::new(self.manager.bin()));
+        }
+
+        if !command_exists("sudo") {
+            return Err(UpError::Exec(format!(
+                "root privileges are required to run {}",
+                self.manager.bin()
+            )));
+        }
+
+        // Make sure we have valid sudo credentials before running the command
+        // with its output piped, so that the password prompt can be answered
+        let validated = PRIVILEGES_VALIDATED.get_or_init(|| {
+            progress_handler.clone().map(|progress_handler| {
+                progress_handler.progress("requesting root privileges".to_string());
+                progress_handler.hide();
+            });
+
+            let validated = std::process::Command::new("sudo")
+                .arg("--validate")
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false);
+
+            progress_handler
+                .clone()
+                .map(|progress_handler| progress_handler.show());
+
+            validated
+        });
+        if !validated {
+            return Err(UpError::Exec(
+                "failed to obtain root privileges".to_string(),
+            ));
+        }
+
+        let mut command = TokioCommand::new("sudo");
+        command.arg("--non-interactive");
+        command.arg(self.manager.bin());
+        Ok(command)
+    }
+
+    fn update_index(&self, progress_handler: Option<Box<&dyn ProgressHandler>>) -> bool {
+        // Only apt requires a separate step to refresh the packages index,
+        // dnf and pacman are handled through flags of the install command
+        if self.manager != SystemPackageManager::Apt {
+            return true;
+        }
+
+        *PACKAGES_INDEX_UPDATED.get_or_init(|| {
+            progress_handler
+                .clone()
+                .map(|progress_handler| progress_handler.progress("updating index".to_string()));
+
+            let mut apt_update = match self.privileged_command(progress_handler.clone()) {
+                Ok(command) => command,
+                Err(_) => return false,
+            };
+            apt_update.arg("update");
+            apt_update.env("DEBIAN_FRONTEND", "noninteractive");
+            apt_update.stdout(std::process::Stdio::piped());
+            apt_update.stderr(std::process::Stdio::piped());
+
+            run_progress(
+                &mut apt_update,
+                progress_handler.clone(),
+                RunConfig::default(),
+            )
+            .is_ok()
+        })
+    }
+
+    fn install(&self, progress_handler: Option<Box<&dyn ProgressHandler>>) -> Result<(), UpError> {
+        if !self.update_index(progress_handler.clone()) {
+            return Err(UpError::Exec(format!(
+                "failed to update {} index",
+                self.manager.name()
+            )));
+        }
+
+        let mut install = self.privileged_command(progress_handler.clone())?;
+        match self.manager {
+            SystemPackageManager::Apt => {
+                install.arg("install");
+                install.arg("--yes");
+                install.env("DEBIAN_FRONTEND", "noninteractive");
+            }
+            SystemPackageManager::Dnf => {
+                install.arg("install");
+                install.arg("--assumeyes");
+            }
+            SystemPackageManager::Pacman => {
+                install.arg("--sync");
+                install.arg("--refresh");
+                install.arg("--needed");
+                install.arg("--noconfirm");
+            }
+        }
+        install.arg(self.package_id());
+        install.stdout(std::process::Stdio::piped());
+        install.stderr(std::process::Stdio::piped());
+
+        progress_handler
+            .clone()
+            .map(|progress_handler| progress_handler.progress("installing".to_string()));
+
+        let result = run_progress(&mut install, progress_handler.clone(), RunConfig::default());
+        if result.is_ok() && self.was_handled.set(true).is_err() {
+            unreachable!();
+        }
+        result
+    }
+
+    fn uninstall(
+        &self,
+        progress_handler: Option<Box<&dyn ProgressHandler>>,
+    ) -> Result<(), UpError> {
+        let mut uninstall = self.privileged_command(progress_handler.clone())?;
+        match self.manager {
+            SystemPackageManager::Apt => {
+                uninstall.arg("remove");
+                uninstall.arg("--yes");
+                uninstall.env("DEBIAN_FRONTEND", "noninteractive");
+            }
+            SystemPackageManager::Dnf => {
+                uninstall.arg("remove");
+                uninstall.arg("--assumeyes");
+            }
+            SystemPackageManager::Pacman => {
+                uninstall.arg("--remove");
+                uninstall.arg("--noconfirm");
+            }
+        }
+        uninstall.arg(&self.name);
+        uninstall.stdout(std::process::Stdio::piped());
+        uninstall.stderr(std::process::Stdio::piped());
+
+        progress_handler
+            .clone()
+            .map(|progress_handler| progress_handler.progress("uninstalling".to_string()));
+
+        let result = run_progress(
+            &mut uninstall,
+            progress_handler.clone(),
+            RunConfig::default(),
+        );
+        if result.is_ok() && self.was_handled.set(true).is_err() {
+            unreachable!();
+        }
+        result
+    }
+
+    fn was_handled(&self) -> bool {
+        *self.was_handled.get_or_init(|| false)
+    }
+}
+
+fn is_root() -> bool {
+    if let Ok(output) = std::process::Command::new("id").arg("-u").output() {
+        if let Ok(output) = String::from_utf8(output.stdout) {
+            return output.trim() == "0";
+        }
+    }
+    false
+}
@@ -1,29 +1,40 @@
+use std::path::PathBuf;
+
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::internal::cache::UpEnvironment;
+use crate::internal::config::up::jvm::java_from_config_value;
+use crate::internal::config::up::jvm::kotlin_from_config_value;
+use crate::internal::config::up::SystemPackageManager;
 use crate::internal::config::up::UpConfigAsdfBase;
 use crate::internal::config::up::UpConfigBundler;
 use crate::internal::config::up::UpConfigCustom;
 use crate::internal::config::up::UpConfigGolang;
 use crate::internal::config::up::UpConfigHomebrew;
+use crate::internal::config::up::UpConfigNodePackages;
 use crate::internal::config::up::UpConfigNodejs;
 use crate::internal::config::up::UpConfigPython;
+use crate::internal::config::up::UpConfigSystemPackages;
 use crate::internal::config::up::UpError;
+use crate::internal::config::up::UpPlanItem;
 use crate::internal::config::ConfigValue;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum UpConfigTool {
-    // TODO: Apt(UpConfigApt),
+    Apt(UpConfigSystemPackages),
+    Asdf(UpConfigAsdfBase),
     Bash(UpConfigAsdfBase),
     Bundler(UpConfigBundler),
     Custom(UpConfigCustom),
-    // TODO: Dnf(UpConfigDnf),
+    Dnf(UpConfigSystemPackages),
     Go(UpConfigGolang),
     Homebrew(UpConfigHomebrew),
-    // TODO: Java(UpConfigAsdfBase), // JAVA_HOME
-    // TODO: Kotlin(UpConfigAsdfBase), // KOTLIN_HOME
+    Java(UpConfigAsdfBase),
+    Kotlin(UpConfigAsdfBase),
     Nodejs(UpConfigNodejs),
-    // TODO: Pacman(UpConfigPacman),
+    NodePackages(UpConfigNodePackages),
+    Pacman(UpConfigSystemPackages),
     Python(UpConfigPython),
     Ruby(UpConfigAsdfBase),
     Rust(UpConfigAsdfBase),
@@ -32,6 +43,12 @@ pub enum UpConfigTool {
 impl UpConfigTool {
     pub fn from_config_value(up_name: &str, config_value: Option<&ConfigValue>) -> Option<Self> {
         match up_name {
+            "apt" => Some(UpConfigTool::Apt(
+                UpConfigSystemPackages::from_config_value(SystemPackageManager::Apt, config_value),
+            )),
+            "asdf" => {
+                UpConfigAsdfBase::from_config_value_generic(config_value).map(UpConfigTool::Asdf)
+            }
             "bash" => Some(UpConfigTool::Bash(
                 UpConfigAsdfBase::from_config_value_with_url(
                     "bash",
@@ -45,15 +62,29 @@ impl UpConfigTool {
             "custom" => Some(UpConfigTool::Custom(UpConfigCustom::from_config_value(
                 config_value,
             ))),
+            "dnf" => Some(UpConfigTool::Dnf(
+                UpConfigSystemPackages::from_config_value(SystemPackageManager::Dnf, config_value),
+            )),
             "go" | "golang" => Some(UpConfigTool::Go(UpConfigGolang::from_config_value(
                 config_value,
             ))),
             "homebrew" | "brew" => Some(UpConfigTool::Homebrew(
                 UpConfigHomebrew::from_config_value(config_value),
             )),
+            "java" => Some(UpConfigTool::Java(java_from_config_value(config_value))),
+            "kotlin" => Some(UpConfigTool::Kotlin(kotlin_from_config_value(config_value))),
             "nodejs" | "node" => Some(UpConfigTool::Nodejs(UpConfigNodejs::from_config_value(
                 config_value,
             ))),
+            "npm" | "pnpm" | "yarn" => Some(UpConfigTool::NodePackages(
+                UpConfigNodePackages::from_config_value(up_name, config_value),
+            )),
+            "pacman" => Some(UpConfigTool::Pacman(
+                UpConfigSystemPackages::from_config_value(
+                    SystemPackageManager::Pacman,
+                    config_value,
+                ),
+            )),
             "python" => Some(UpConfigTool::Python(UpConfigPython::from_config_value(
                 config_value,
             ))),
@@ -71,12 +102,19 @@ impl UpConfigTool {
 
     pub fn up(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
         match self {
+            UpConfigTool::Apt(config) => config.up(progress),
+            UpConfigTool::Asdf(config) => config.up(progress),
             UpConfigTool::Bash(config) => config.up(progress),
             UpConfigTool::Bundler(config) => config.up(progress),
             UpConfigTool::Custom(config) => config.up(progress),
+            UpConfigTool::Dnf(config) => config.up(progress),
             UpConfigTool::Go(config) => config.up(progress),
             UpConfigTool::Homebrew(config) => config.up(progress),
+            UpConfigTool::Java(config) => config.up(progress),
+            UpConfigTool::Kotlin(config) => config.up(progress),
             UpConfigTool::Nodejs(config) => config.up(progress),
+            UpConfigTool::NodePackages(config) => config.up(progress),
+            UpConfigTool::Pacman(config) => config.up(progress),
             UpConfigTool::Python(config) => config.up(progress),
             UpConfigTool::Ruby(config) => config.up(progress),
             UpConfigTool::Rust(config) => config.up(progress),
@@ -85,29 +123,92 @@ impl UpConfigTool {
 
     pub fn down(&self, progress: Option<(usize, usize)>) -> Result<(), UpError> {
         match self {
+            UpConfigTool::Apt(config) => config.down(progress),
+            UpConfigTool::Asdf(config) => config.down(progress),
             UpConfigTool::Bash(config) => config.down(progress),
             UpConfigTool::Bundler(config) => config.down(progress),
             UpConfigTool::Custom(config) => config.down(progress),
+            UpConfigTool::Dnf(config) => config.down(progress),
             UpConfigTool::Go(config) => config.down(progress),
             UpConfigTool::Homebrew(config) => config.down(progress),
+            UpConfigTool::Java(config) => config.down(progress),
+            UpConfigTool::Kotlin(config) => config.down(progress),
             UpConfigTool::Nodejs(config) => config.down(progress),
+            UpConfigTool::NodePackages(config) => config.down(progress),
+            UpConfigTool::Pacman(config) => config.down(progress),
             UpConfigTool::Python(config) => config.down(progress),
             UpConfigTool::Ruby(config) => config.down(progress),
             UpConfigTool::Rust(config) => config.down(progress),
         }
     }
 
+    /// Returns the changes that `up` would make for this step; custom
+    /// steps only evaluate their `met?` command if `trusted` is set.
+    pub fn plan_up(&self, trusted: bool) -> Vec<UpPlanItem> {
+        match self {
+            UpConfigTool::Apt(config) => config.plan_up(),
+            UpConfigTool::Asdf(config) => config.plan_up(),
+            UpConfigTool::Bash(config) => config.plan_up(),
+            UpConfigTool::Bundler(config) => config.plan_up(),
+            UpConfigTool::Custom(config) => config.plan_up(trusted),
+            UpConfigTool::Dnf(config) => config.plan_up(),
+            UpConfigTool::Go(config) => config.plan_up(),
+            UpConfigTool::Homebrew(config) => config.plan_up(),
+            UpConfigTool::Java(config) => config.plan_up(),
+            UpConfigTool::Kotlin(config) => config.plan_up(),
+            UpConfigTool::Nodejs(config) => config.plan_up(),
+            UpConfigTool::NodePackages(config) => config.plan_up(),
+            UpConfigTool::Pacman(config) => config.plan_up(),
+            UpConfigTool::Python(config) => config.plan_up(),
+            UpConfigTool::Ruby(config) => config.plan_up(),
+            UpConfigTool::Rust(config) => config.plan_up(),
+        }
+    }
+
+    /// Returns whether the changes that `up` would make for this step
+    /// tell whether the step is met; this is not the case of custom
+    /// steps for which the `met?` command cannot be run.
+    pub fn can_check(&self, trusted: bool) -> bool {
+        match self {
+            UpConfigTool::Custom(config) => config.can_check(trusted),
+            _ => true,
+        }
+    }
+
+    /// Returns the changes that `down` would make for this step; the
+    /// asdf tools are not part of it as they are handled by the cleanup.
+    pub fn plan_down(&self, trusted: bool) -> Vec<UpPlanItem> {
+        match self {
+            UpConfigTool::Apt(config) => config.plan_down(),
+            UpConfigTool::Bundler(config) => config.plan_down(),
+            UpConfigTool::Custom(config) => config.plan_down(trusted),
+            UpConfigTool::Dnf(config) => config.plan_down(),
+            UpConfigTool::Go(config) => config.plan_down(),
+            UpConfigTool::Homebrew(config) => config.plan_down(),
+            UpConfigTool::NodePackages(config) => config.plan_down(),
+            UpConfigTool::Pacman(config) => config.plan_down(),
+            UpConfigTool::Python(config) => config.plan_down(),
+            _ => vec![],
+        }
+    }
+
     pub fn is_available(&self) -> bool {
         match self {
+            UpConfigTool::Apt(config) => config.is_available(),
+            UpConfigTool::Dnf(config) => config.is_available(),
             UpConfigTool::Homebrew(config) => config.is_available(),
+            UpConfigTool::Pacman(config) => config.is_available(),
             _ => true,
         }
     }
 
     pub fn asdf_tool(&self) -> Option<&UpConfigAsdfBase> {
         match self {
+            UpConfigTool::Asdf(config) => Some(config),
             UpConfigTool::Bash(config) => Some(config),
             UpConfigTool::Go(config) => config.asdf_base().ok(),
+            UpConfigTool::Java(config) => Some(config),
+            UpConfigTool::Kotlin(config) => Some(config),
             UpConfigTool::Nodejs(config) => Some(&config.asdf_base),
             UpConfigTool::Python(config) => config.asdf_base().ok(),
             UpConfigTool::Ruby(config) => Some(config),
@@ -116,10 +217,143 @@ impl UpConfigTool {
         }
     }
 
+    /// Returns the files that the step depends on, and that require the
+    /// step to run again if they change.
+    pub fn input_files(&self) -> Vec<PathBuf> {
+        match self {
+            UpConfigTool::Bundler(config) => config.input_files(),
+            UpConfigTool::Go(config) => config.input_files(),
+            UpConfigTool::Homebrew(config) => config.input_files(),
+            UpConfigTool::NodePackages(config) => config.input_files(),
+            UpConfigTool::Python(config) => config.input_files(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns the versions requested and locked for the tool, if any,
+    /// as part of the inputs of the step.
+    pub fn inputs_salt(&self) -> String {
+        self.asdf_tool()
+            .map(|asdf_tool| asdf_tool.inputs_salt())
+            .unwrap_or_default()
+    }
+
+    /// Returns the names of the environment variables that the step
+    /// sets in the up environment of the repository.
+    pub fn env_vars(&self) -> Vec<String> {
+        match self {
+            UpConfigTool::Bundler(_) => vec!["BUNDLE_GEMFILE".to_string()],
+            UpConfigTool::Custom(config) => config.outputs.clone(),
+            UpConfigTool::Go(config) => config.env_vars(),
+            UpConfigTool::Python(config) => config.env_vars(),
+            _ => vec![],
+        }
+    }
+
+    /// Restores the state of a step that does not need to run again
+    /// from the up environment of its previous run; returns `false` if
+    /// what the step set up is not there anymore, in which case the
+    /// step needs to run again.
+    pub fn restore(&self, up_env: &UpEnvironment) -> bool {
+        if let Some(asdf_tool) = self.asdf_tool() {
+            let versions = up_env
+                .versions
+                .iter()
+                .filter(|version| version.tool == asdf_tool.tool)
+                .cloned()
+                .collect::<Vec<_>>();
+            if !asdf_tool.restore_versions(&versions) {
+                return false;
+            }
+        }
+
+        match self {
+            UpConfigTool::Bundler(config) => config.is_set_up(),
+            UpConfigTool::Go(config) => config.is_set_up(),
+            UpConfigTool::Homebrew(config) => config.restore(),
+            UpConfigTool::NodePackages(config) => config.is_set_up(),
+            UpConfigTool::Python(config) => config.is_set_up(),
+            _ => true,
+        }
+    }
+
     pub fn dir(&self) -> Option<String> {
         match self {
             UpConfigTool::Custom(config) => config.dir(),
             _ => None,
         }
     }
+
+    /// Returns the names that can be used to refer to this step in the
+    /// `needs` parameter of another step.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = match self {
+            UpConfigTool::Apt(_) => vec!["apt"],
+            UpConfigTool::Asdf(_) => vec!["asdf"],
+            UpConfigTool::Bash(_) => vec!["bash"],
+            UpConfigTool::Bundler(_) => vec!["bundler", "bundle"],
+            UpConfigTool::Custom(_) => vec!["custom"],
+            UpConfigTool::Dnf(_) => vec!["dnf"],
+            UpConfigTool::Go(_) => vec!["go", "golang"],
+            UpConfigTool::Homebrew(_) => vec!["homebrew", "brew"],
+            UpConfigTool::Java(_) => vec!["java"],
+            UpConfigTool::Kotlin(_) => vec!["kotlin"],
+            UpConfigTool::Nodejs(_) => vec!["nodejs", "node"],
+            UpConfigTool::NodePackages(config) => vec![config.default_manager.name()],
+            UpConfigTool::Pacman(_) => vec!["pacman"],
+            UpConfigTool::Python(_) => vec!["python"],
+            UpConfigTool::Ruby(_) => vec!["ruby"],
+            UpConfigTool::Rust(_) => vec!["rust"],
+        }
+        .into_iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<String>>();
+
+        match self {
+            UpConfigTool::Asdf(config) => names.push(config.tool.clone()),
+            UpConfigTool::Custom(config) => {
+                if let Some(name) = &config.name {
+                    names.push(name.clone());
+                }
+            }
+            _ => {}
+        }
+
+        names
+    }
+
+    /// Returns the names of the steps this step depends on even if
+    /// not explicitly specified, e.g. bundler requires ruby.
+    pub fn implicit_needs(&self) -> Vec<String> {
+        match self {
+            UpConfigTool::Bundler(_) => vec!["ruby".to_string()],
+            UpConfigTool::NodePackages(_) => vec!["nodejs".to_string()],
+            _ => vec![],
+        }
+    }
+
+    /// Returns the resource shared by the steps that cannot run at the
+    /// same time as each other, e.g. package managers holding a lock.
+    pub fn concurrency_group(&self) -> Option<String> {
+        match self {
+            UpConfigTool::Apt(_) | UpConfigTool::Dnf(_) | UpConfigTool::Pacman(_) => {
+                Some("system-packages".to_string())
+            }
+            UpConfigTool::Homebrew(_) => Some("homebrew".to_string()),
+            _ => self
+                .asdf_tool()
+                .map(|asdf_tool| format!("asdf:{}", asdf_tool.tool)),
+        }
+    }
+
+    /// Returns whether the step needs to run on its own, without any
+    /// other step running concurrently; this is the case for steps
+    /// that change directory, or that might require user input.
+    pub fn runs_alone(&self) -> bool {
+        match self {
+            UpConfigTool::Apt(_) | UpConfigTool::Dnf(_) | UpConfigTool::Pacman(_) => true,
+            UpConfigTool::Custom(_) => true,
+            _ => self.dir().is_some(),
+        }
+    }
 }
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use time::OffsetDateTime;
+
+use crate::internal::cache::UpEnvironment;
+use crate::internal::cache::UpEnvironments;
+use crate::internal::config::up::homebrew::HomebrewInstall;
+use crate::internal::config::up::homebrew::HomebrewTap;
+use crate::internal::config::up::system_packages::SystemPackageInstall;
+use crate::internal::config::up::UpConfigAsdfBase;
+use crate::internal::config::up::UpConfigCustom;
+use crate::internal::config::up::UpConfigGolang;
+use crate::internal::config::up::UpConfigNodePackages;
+use crate::internal::config::up::UpConfigPython;
+use crate::internal::config::up::UpError;
+use crate::internal::workdir;
+use crate::internal::Cache;
+
+lazy_static! {
+    static ref UP_TRANSACTION: Mutex<Option<UpTransaction>> = Mutex::new(None);
+}
+
+/// A change made to the system by a step, that can be undone if
+/// a later step of the same run fails.
+#[derive(Debug, Clone)]
+pub enum UpChange {
+    AsdfVersion { tool: String, version: String },
+    HomebrewTap(HomebrewTap),
+    HomebrewInstall(HomebrewInstall),
+    SystemPackageInstall(SystemPackageInstall),
+    PythonVenv(UpConfigPython),
+    NodeModules(UpConfigNodePackages),
+    GoBin(UpConfigGolang),
+    Custom(UpConfigCustom),
+}
+
+impl UpChange {
+    fn rollback(&self) -> Result<(), UpError> {
+        match self {
+            UpChange::AsdfVersion { tool, version } => {
+                UpConfigAsdfBase::rollback_version(tool, version)
+            }
+            UpChange::HomebrewTap(tap) => tap.rollback(),
+            UpChange::HomebrewInstall(install) => install.rollback(),
+            UpChange::SystemPackageInstall(install) => install.rollback(),
+            UpChange::PythonVenv(config) => config.down(None),
+            UpChange::NodeModules(config) => config.down(None),
+            UpChange::GoBin(config) => config.down(None),
+            UpChange::Custom(config) => config.down(None),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct UpTransaction {
+    repo_id: String,
+    up_environment: Option<UpEnvironment>,
+    changes: Vec<UpChange>,
+}
+
+/// Starts recording the changes made by the steps, keeping a copy of
+/// the up environment of the current repository so that it can be
+/// restored if the transaction is rolled back.
+pub fn begin_transaction() -> Result<(), UpError> {
+    let repo_id = match workdir(".").id() {
+        Some(repo_id) => repo_id,
+        None => return Err(UpError::Exec("failed to get repository id".to_string())),
+    };
+
+    let cache = match Cache::shared() {
+        Ok(cache) => cache,
+        Err(err) => return Err(UpError::Exec(format!("failed to read cache: {}", err))),
+    };
+
+    let up_environment = cache
+        .up_environments
+        .and_then(|up_cache| up_cache.env.get(&repo_id).cloned());
+
+    *UP_TRANSACTION.lock().unwrap() = Some(UpTransaction {
+        repo_id,
+        up_environment,
+        changes: Vec::new(),
+    });
+
+    Ok(())
+}
+
+/// Records a change made to the system, if a transaction is ongoing.
+pub fn record_change(change: UpChange) {
+    if let Some(transaction) = UP_TRANSACTION.lock().unwrap().as_mut() {
+        transaction.changes.push(change);
+    }
+}
+
+/// Ends the transaction, keeping all the changes that were made.
+pub fn commit_transaction() {
+    *UP_TRANSACTION.lock().unwrap() = None;
+}
+
+/// Ends the transaction, undoing the changes that were made in the
+/// reverse order in which they were made, and restoring the up
+/// environment of the repository as it was when the transaction began.
+pub fn rollback_transaction() -> Result<(), UpError> {
+    let transaction = match UP_TRANSACTION.lock().unwrap().take() {
+        Some(transaction) => transaction,
+        None => return Ok(()),
+    };
+
+    // Try to undo all the changes even if one of them fails, so that
+    // we leave as little behind as possible
+    let mut errors = Vec::new();
+    for change in transaction.changes.iter().rev() {
+        if let Err(err) = change.rollback() {
+            errors.push(format!("{}", err));
+        }
+    }
+
+    if let Err(err) = Cache::exclusive(|cache| {
+        let mut up_env = HashMap::new();
+        if let Some(up_cache) = &cache.up_environments {
+            up_env = up_cache.env.clone();
+        }
+
+        match &transaction.up_environment {
+            Some(up_environment) => {
+                up_env.insert(transaction.repo_id.clone(), up_environment.clone());
+            }
+            None => {
+                if up_env.remove(&transaction.repo_id).is_none() {
+                    return false;
+                }
+            }
+        }
+
+        cache.up_environments = Some(UpEnvironments {
+            env: up_env.clone(),
+            updated_at: OffsetDateTime::now_utc(),
+        });
+
+        true
+    }) {
+        errors.push(format!("failed to update cache: {}", err));
+    }
+
+    if !errors.is_empty() {
+        return Err(UpError::Exec(errors.join(", ")));
+    }
+
+    Ok(())
+}
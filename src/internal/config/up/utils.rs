@@ -1,8 +1,15 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
 use indicatif::MultiProgress;
 use indicatif::ProgressBar;
 use indicatif::ProgressDrawTarget;
 use indicatif::ProgressStyle;
+use lazy_static::lazy_static;
 use regex::Regex;
+use time::OffsetDateTime;
 use tokio;
 use tokio::io::AsyncBufReadExt;
 use tokio::io::AsyncReadExt;
@@ -11,8 +18,27 @@ use tokio::process::Command as TokioCommand;
 use tokio::runtime::Runtime;
 use tokio::time::Duration;
 
+use crate::internal::cache::UpEnvironment;
+use crate::internal::cache::UpEnvironments;
 use crate::internal::config::up::UpError;
 use crate::internal::user_interface::StringColor;
+use crate::internal::workdir;
+use crate::internal::Cache;
+
+lazy_static! {
+    static ref UP_MULTIPROGRESS: Mutex<Option<MultiProgress>> = Mutex::new(None);
+}
+
+/// Sets the multi-progress that the progress handlers created afterwards
+/// will attach to; this allows steps running concurrently to each get
+/// their own line, and can be reset by passing `None`.
+pub fn set_multiprogress(multiprogress: Option<MultiProgress>) {
+    *UP_MULTIPROGRESS.lock().unwrap() = multiprogress;
+}
+
+fn multiprogress() -> Option<MultiProgress> {
+    UP_MULTIPROGRESS.lock().unwrap().clone()
+}
 
 pub struct RunConfig {
     pub timeout: Option<Duration>,
@@ -39,6 +65,119 @@ impl RunConfig {
     }
 }
 
+/// Checks if the given command is available in one of the directories
+/// of the current `PATH`.
+pub fn command_exists(command: &str) -> bool {
+    if let Some(path) = std::env::var_os("PATH") {
+        for dir in std::env::split_paths(&path) {
+            if dir.join(command).is_file() {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Returns the hash stored for the given key in the up environment
+/// of the current repository, if any.
+pub fn up_env_hash(key: &str) -> Option<String> {
+    let repo_id = workdir(".").id()?;
+    let cache = Cache::shared().ok()?;
+    let up_env = cache.up_environments?;
+    let repo_up_env = up_env.env.get(&repo_id)?;
+    repo_up_env.hashes.get(key).cloned()
+}
+
+/// Stores the hash for the given key in the up environment of the
+/// current repository, or removes it if `hash` is `None`.
+pub fn set_up_env_hash(key: &str, hash: Option<String>) -> io::Result<Cache> {
+    Cache::exclusive(|cache| {
+        let repo_id = if let Some(repo_id) = workdir(".").id() {
+            repo_id
+        } else {
+            return false;
+        };
+
+        let mut up_env = HashMap::new();
+        if let Some(up_cache) = &cache.up_environments {
+            up_env = up_cache.env.clone();
+        }
+
+        if !up_env.contains_key(&repo_id) {
+            if hash.is_none() {
+                return false;
+            }
+            up_env.insert(repo_id.clone(), UpEnvironment::new());
+        }
+        let repo_up_env = up_env.get_mut(&repo_id).unwrap();
+
+        if let Some(hash) = &hash {
+            repo_up_env.hashes.insert(key.to_string(), hash.clone());
+        } else if repo_up_env.hashes.remove(key).is_none() {
+            return false;
+        }
+
+        cache.up_environments = Some(UpEnvironments {
+            env: up_env.clone(),
+            updated_at: OffsetDateTime::now_utc(),
+        });
+
+        true
+    })
+}
+
+/// Records the root of the current repository in its up environment,
+/// so that the environments of repositories that do not exist anymore
+/// can be identified and cleaned up.
+pub fn set_up_env_path() -> io::Result<Cache> {
+    Cache::exclusive(|cache| {
+        let workdir = workdir(".");
+        let (repo_id, root) = match (workdir.id(), workdir.root()) {
+            (Some(repo_id), Some(root)) => (repo_id, root.to_string()),
+            _ => return false,
+        };
+
+        let mut up_env = match &cache.up_environments {
+            Some(up_cache) => up_cache.env.clone(),
+            None => return false,
+        };
+
+        let repo_up_env = match up_env.get_mut(&repo_id) {
+            Some(repo_up_env) => repo_up_env,
+            None => return false,
+        };
+        if repo_up_env.path == root {
+            return false;
+        }
+        repo_up_env.path = root;
+
+        cache.up_environments = Some(UpEnvironments {
+            env: up_env.clone(),
+            updated_at: OffsetDateTime::now_utc(),
+        });
+
+        true
+    })
+}
+
+/// Computes a hash of the given files contents, to be able to identify
+/// if any of the inputs of a step has changed since the last run. Files
+/// that do not exist are considered as part of the hash too.
+pub fn hash_files(salt: &str, files: &[PathBuf]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(salt.as_bytes());
+    for file in files {
+        hasher.update(b"\0");
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+        match std::fs::read(file) {
+            Ok(contents) => hasher.update(&contents),
+            Err(_) => hasher.update(b"<missing>"),
+        };
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
 pub fn run_progress(
     process_command: &mut TokioCommand,
     progress_handler: Option<Box<&dyn ProgressHandler>>,
@@ -270,6 +409,12 @@ pub struct SpinnerProgressHandler {
 
 impl SpinnerProgressHandler {
     pub fn new(desc: String, progress: Option<(usize, usize)>) -> Self {
+        if let Some(multiprogress) = multiprogress() {
+            let mut handler = Self::new_with_multi(desc, progress, multiprogress);
+            handler.no_newline_on_error();
+            return handler;
+        }
+
         Self::new_with_params(desc, progress, None)
     }
 
@@ -417,17 +562,29 @@ impl PrintProgressHandler {
     }
 }
 
+impl PrintProgressHandler {
+    fn eprintln(&self, message: String) {
+        // When attached to a multi-progress, we need to print through it
+        // or the lines would get overwritten by the progress bars
+        if let Some(multiprogress) = multiprogress() {
+            if multiprogress.println(&message).is_ok() {
+                return;
+            }
+        }
+        eprintln!("{}", message);
+    }
+}
+
 impl ProgressHandler for PrintProgressHandler {
     fn println(&self, message: String) {
-        eprintln!("{}", message);
+        self.eprintln(message);
     }
 
     fn progress(&self, message: String) {
-        eprintln!(
-            "{}",
+        self.eprintln(
             self.template
                 .replacen("{}", "-".to_string().light_black().as_str(), 1)
-                .replacen("{}", message.as_str(), 1)
+                .replacen("{}", message.as_str(), 1),
         );
     }
 
@@ -436,11 +593,10 @@ impl ProgressHandler for PrintProgressHandler {
     }
 
     fn success_with_message(&self, message: String) {
-        eprintln!(
-            "{}",
+        self.eprintln(
             self.template
                 .replacen("{}", "✔".to_string().green().as_str(), 1)
-                .replacen("{}", message.as_str(), 1)
+                .replacen("{}", message.as_str(), 1),
         );
     }
 
@@ -449,11 +605,10 @@ impl ProgressHandler for PrintProgressHandler {
     }
 
     fn error_with_message(&self, message: String) {
-        eprintln!(
-            "{}",
+        self.eprintln(
             self.template
                 .replacen("{}", "✖".to_string().red().as_str(), 1)
-                .replacen("{}", message.red().as_str(), 1)
+                .replacen("{}", message.red().as_str(), 1),
         );
     }
 
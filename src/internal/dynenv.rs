@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 
 use blake3::Hasher;
@@ -103,13 +104,75 @@ fn print_update(status: &str) {
     eprintln!("{} {}", "omni:".to_string().force_light_cyan(), status);
 }
 
+/// Renders the dynamic environment of the given path in the requested
+/// format; the operations are computed as if no dynamic environment
+/// was currently loaded.
+pub fn export_dynamic_env(format: DynamicEnvExportFormat, path: &str) -> String {
+    let cache = Cache::new();
+
+    // Undo the currently loaded dynamic environment, if any, so that we
+    // start from the same environment the shell would be starting from
+    let mut current_env = DynamicEnv::from_env(cache.clone());
+    current_env.undo(DynamicEnvExportMode::Env);
+
+    let mut expected_env = DynamicEnv::new_with_path(Some(path.to_string()), cache);
+    match expected_env.envsetter() {
+        Some(envsetter) => envsetter.render(format),
+        None => "".to_string(),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum DynamicEnvExportMode {
     Posix,
     Fish,
+    Nu,
+    Pwsh,
     Env,
 }
 
+impl DynamicEnvExportMode {
+    pub fn from_shell(shell: &str) -> Option<Self> {
+        match shell {
+            "posix" | "bash" | "zsh" => Some(Self::Posix),
+            "fish" => Some(Self::Fish),
+            "nu" | "nushell" => Some(Self::Nu),
+            "pwsh" | "powershell" => Some(Self::Pwsh),
+            _ => None,
+        }
+    }
+
+    /// Quotes the value so that it can be used as a single argument
+    /// in a command for the shell.
+    pub fn quote(&self, value: &str) -> String {
+        match self {
+            Self::Nu => serde_json::to_string(value).unwrap(),
+            Self::Pwsh => format!("'{}'", value.replace('\'', "''")),
+            _ => escape(std::borrow::Cow::Borrowed(value)).to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynamicEnvExportFormat {
+    Dotenv,
+    Direnv,
+    Json,
+    Vscode,
+}
+
+impl DynamicEnvExportFormat {
+    pub fn from_str(format: &str) -> Option<Self> {
+        match format {
+            "dotenv" => Some(Self::Dotenv),
+            "direnv" => Some(Self::Direnv),
+            "json" => Some(Self::Json),
+            "vscode" => Some(Self::Vscode),
+            _ => None,
+        }
+    }
+}
+
 pub struct DynamicEnv {
     path: Option<String>,
     id: OnceCell<u64>,
@@ -224,17 +287,36 @@ impl DynamicEnv {
     }
 
     pub fn apply(&mut self, export_mode: DynamicEnvExportMode) {
+        let mut envsetter = match self.envsetter() {
+            Some(envsetter) => envsetter,
+            None => return,
+        };
+
+        // Set the dynamic env variable so we can easily undo things
+        let json_data = envsetter.get_env_data().to_json();
+        if self.id() == 0 {
+            envsetter.unset_value(DYNENV_VAR);
+        } else {
+            envsetter.set_value(
+                DYNENV_VAR,
+                &format!("{}{}{}", self.id_str(), DYNENV_SEPARATOR, json_data),
+            );
+        }
+
+        self.data = Some(envsetter.get_env_data());
+        self.data.clone().unwrap().export(export_mode.clone());
+    }
+
+    /// Prepare the operations required to go from the current environment
+    /// to the dynamic environment, without applying them.
+    fn envsetter(&mut self) -> Option<DynamicEnvSetter> {
         let mut envsetter = DynamicEnvSetter::new();
 
         let mut up_env = None;
         let path = self.path.clone().unwrap_or(".".to_string());
         let workdir = workdir(&path);
         if workdir.in_workdir() {
-            let repo_id = workdir.id();
-            if repo_id.is_none() {
-                return;
-            }
-            let repo_id = repo_id.unwrap();
+            let repo_id = workdir.id()?;
 
             if let Some(up_cache) = &self.cache.up_environments {
                 if up_cache.env.contains_key(&repo_id) {
@@ -249,6 +331,11 @@ impl DynamicEnv {
                 self.features.push("env".to_string());
             }
             for (key, value) in up_env.env_vars.iter() {
+                envsetter.set_feature(match key.as_str() {
+                    "VIRTUAL_ENV" => "python venv",
+                    "GOBIN" => "golang bin",
+                    _ => "env",
+                });
                 envsetter.set_value(key, value);
             }
 
@@ -261,6 +348,7 @@ impl DynamicEnv {
                 let tool_prefix = format!("{}/installs/{}/{}", *ASDF_PATH, tool, version);
 
                 self.features.push(format!("{}:{}", tool, version));
+                envsetter.set_feature(&format!("{}:{}", tool, version));
 
                 match tool.as_str() {
                     "ruby" => {
@@ -319,6 +407,32 @@ impl DynamicEnv {
                         envsetter.set_value("GOROOT", &format!("{}/go", tool_prefix));
                         envsetter.set_value("GOVERSION", &version);
                         envsetter.prepend_to_list("PATH", &format!("{}/go/bin", tool_prefix));
+                        if let Some(gobin) = up_env.env_vars.get("GOBIN") {
+                            envsetter.set_feature("golang bin");
+                            envsetter.prepend_to_list("PATH", gobin);
+                        }
+                    }
+                    "java" => {
+                        if let Some(javahome) = std::env::var_os("JAVA_HOME") {
+                            envsetter.remove_from_list(
+                                "PATH",
+                                &format!("{}/bin", javahome.to_str().unwrap()),
+                            );
+                        }
+
+                        envsetter.set_value("JAVA_HOME", &tool_prefix);
+                        envsetter.prepend_to_list("PATH", &format!("{}/bin", tool_prefix));
+                    }
+                    "kotlin" => {
+                        if let Some(kotlinhome) = std::env::var_os("KOTLIN_HOME") {
+                            envsetter.remove_from_list(
+                                "PATH",
+                                &format!("{}/bin", kotlinhome.to_str().unwrap()),
+                            );
+                        }
+
+                        envsetter.set_value("KOTLIN_HOME", &format!("{}/kotlinc", tool_prefix));
+                        envsetter.prepend_to_list("PATH", &format!("{}/kotlinc/bin", tool_prefix));
                     }
                     // "nodejs" => {
                     // envsetter.set_value("NVM_DIR", "$HOME/.nvm");
@@ -328,6 +442,7 @@ impl DynamicEnv {
                     "python" => {
                         envsetter.prepend_to_list("PATH", &format!("{}/bin", tool_prefix));
                         if let Some(venv) = up_env.env_vars.get("VIRTUAL_ENV") {
+                            envsetter.set_feature("python venv");
                             envsetter.unset_value("PYTHONHOME");
                             envsetter.prepend_to_list("PATH", &format!("{}/bin", venv));
                         }
@@ -342,37 +457,18 @@ impl DynamicEnv {
         // Set the OMNI_LOADED_FEATURES variable so that it can easily be used in
         // the shell to keep showing up loaded features in the prompt or anywhere
         // else users wish.
+        envsetter.set_feature("omni");
         if self.features.len() > 0 {
             envsetter.set_value("OMNI_LOADED_FEATURES", &self.features.join(" "));
         } else {
             envsetter.unset_value("OMNI_LOADED_FEATURES");
         }
 
-        // Set the dynamic env variable so we can easily undo things
-        let json_data = envsetter.get_env_data().to_json();
-        if self.id() == 0 {
-            envsetter.unset_value(DYNENV_VAR);
-        } else {
-            envsetter.set_value(
-                DYNENV_VAR,
-                &format!("{}{}{}", self.id_str(), DYNENV_SEPARATOR, json_data),
-            );
-        }
-
-        self.data = Some(envsetter.get_env_data());
-        self.data.clone().unwrap().export(export_mode.clone());
+        Some(envsetter)
     }
 
     pub fn undo(&mut self, export_mode: DynamicEnvExportMode) {
-        if self.data.is_none() && self.data_str.is_some() {
-            let data: Result<DynamicEnvData, _> =
-                serde_json::from_str(&self.data_str.clone().unwrap());
-            if data.is_err() {
-                return;
-            }
-            let data = data.unwrap();
-            self.data = Some(data);
-        }
+        self.load_data();
 
         if self.data.is_none() {
             return;
@@ -382,6 +478,95 @@ impl DynamicEnv {
         data.prepare_undo();
         data.export(export_mode.clone());
     }
+
+    /// Decodes the changes that the currently loaded dynamic environment
+    /// operated, along with the values the variables had before them.
+    pub fn changes(&mut self) -> Vec<DynamicEnvChange> {
+        self.load_data();
+
+        let data = match &self.data {
+            Some(data) => data.clone(),
+            None => return vec![],
+        };
+
+        let mut undo = data.clone();
+        undo.prepare_undo();
+
+        let mut changes: BTreeMap<String, DynamicEnvChange> = BTreeMap::new();
+
+        for (key, value) in data.values.iter() {
+            let change = changes
+                .entry(key.to_string())
+                .or_insert_with(|| DynamicEnvChange::new(key, value.prev.clone()));
+            change.operations.push(DynamicEnvChangeOperation {
+                operation: if value.curr.is_some() { "set" } else { "unset" }.to_string(),
+                value: value.curr.clone(),
+                feature: value.feature.clone(),
+            });
+        }
+
+        for (key, operations) in data.lists.iter() {
+            if operations.is_empty() {
+                continue;
+            }
+
+            let prev = undo.env_get_var(key).filter(|prev| !prev.is_empty());
+            let change = changes
+                .entry(key.to_string())
+                .or_insert_with(|| DynamicEnvChange::new(key, prev));
+            for operation in operations.iter() {
+                let name = match operation.operation {
+                    DynamicEnvListOperation::Add if operation.index == 0 => "prepend",
+                    DynamicEnvListOperation::Add => "append",
+                    DynamicEnvListOperation::Del => "remove",
+                };
+                change.operations.push(DynamicEnvChangeOperation {
+                    operation: name.to_string(),
+                    value: Some(operation.value.clone()),
+                    feature: operation.feature.clone(),
+                });
+            }
+        }
+
+        changes.into_values().collect()
+    }
+
+    fn load_data(&mut self) {
+        if self.data.is_none() && self.data_str.is_some() {
+            let data: Result<DynamicEnvData, _> =
+                serde_json::from_str(&self.data_str.clone().unwrap());
+            if let Ok(data) = data {
+                self.data = Some(data);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DynamicEnvChange {
+    pub name: String,
+    pub previous: Option<String>,
+    pub current: Option<String>,
+    pub operations: Vec<DynamicEnvChangeOperation>,
+}
+
+impl DynamicEnvChange {
+    fn new(name: &str, previous: Option<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            previous: previous,
+            current: std::env::var(name).ok(),
+            operations: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DynamicEnvChangeOperation {
+    pub operation: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    pub feature: Option<String>,
 }
 
 enum DynamicEnvOperation {
@@ -393,31 +578,55 @@ enum DynamicEnvOperation {
     RemoveFromListByFn(String, Box<dyn Fn() -> Vec<String>>),
 }
 
+impl DynamicEnvOperation {
+    fn key(&self) -> &str {
+        match self {
+            DynamicEnvOperation::SetValue(key, _)
+            | DynamicEnvOperation::UnsetValue(key)
+            | DynamicEnvOperation::PrependToList(key, _)
+            | DynamicEnvOperation::AppendToList(key, _)
+            | DynamicEnvOperation::RemoveFromList(key, _)
+            | DynamicEnvOperation::RemoveFromListByFn(key, _) => key,
+        }
+    }
+}
+
 struct DynamicEnvSetter {
-    operations: Vec<DynamicEnvOperation>,
+    operations: Vec<(Option<String>, DynamicEnvOperation)>,
+    feature: Option<String>,
 }
 
 impl DynamicEnvSetter {
     fn new() -> Self {
         DynamicEnvSetter {
             operations: Vec::new(),
+            feature: None,
         }
     }
 
+    /// Sets the feature responsible for the operations that follow,
+    /// so that it can be shown when inspecting the environment.
+    fn set_feature(&mut self, feature: &str) {
+        self.feature = Some(feature.to_string());
+    }
+
+    fn push(&mut self, operation: DynamicEnvOperation) {
+        self.operations.push((self.feature.clone(), operation));
+    }
+
     fn set_value(&mut self, key: &str, value: &str) {
-        self.operations.push(DynamicEnvOperation::SetValue(
+        self.push(DynamicEnvOperation::SetValue(
             key.to_string(),
             value.to_string(),
         ));
     }
 
     fn unset_value(&mut self, key: &str) {
-        self.operations
-            .push(DynamicEnvOperation::UnsetValue(key.to_string()));
+        self.push(DynamicEnvOperation::UnsetValue(key.to_string()));
     }
 
     fn prepend_to_list(&mut self, key: &str, value: &str) {
-        self.operations.push(DynamicEnvOperation::PrependToList(
+        self.push(DynamicEnvOperation::PrependToList(
             key.to_string(),
             value.to_string(),
         ));
@@ -425,14 +634,14 @@ impl DynamicEnvSetter {
 
     #[allow(dead_code)]
     fn append_to_list(&mut self, key: &str, value: &str) {
-        self.operations.push(DynamicEnvOperation::AppendToList(
+        self.push(DynamicEnvOperation::AppendToList(
             key.to_string(),
             value.to_string(),
         ));
     }
 
     fn remove_from_list(&mut self, key: &str, value: &str) {
-        self.operations.push(DynamicEnvOperation::RemoveFromList(
+        self.push(DynamicEnvOperation::RemoveFromList(
             key.to_string(),
             value.to_string(),
         ));
@@ -442,17 +651,239 @@ impl DynamicEnvSetter {
     where
         F: Fn() -> Vec<String> + 'static,
     {
-        self.operations
-            .push(DynamicEnvOperation::RemoveFromListByFn(
+        self.push(DynamicEnvOperation::RemoveFromListByFn(
+            key.to_string(),
+            Box::new(f),
+        ));
+    }
+
+    /// Returns the operations with the removals by function resolved
+    /// to the values to remove, skipping the operations on variables
+    /// that do not end up being changed.
+    fn resolved_operations(&self, data: &DynamicEnvData) -> Vec<DynamicEnvOperation> {
+        let mut operations = Vec::new();
+
+        for (_, operation) in self.operations.iter() {
+            match operation {
+                DynamicEnvOperation::SetValue(key, value) => {
+                    operations.push(DynamicEnvOperation::SetValue(key.clone(), value.clone()));
+                }
+                DynamicEnvOperation::UnsetValue(key) => {
+                    operations.push(DynamicEnvOperation::UnsetValue(key.clone()));
+                }
+                DynamicEnvOperation::PrependToList(key, value) => {
+                    operations.push(DynamicEnvOperation::PrependToList(
+                        key.clone(),
+                        value.clone(),
+                    ));
+                }
+                DynamicEnvOperation::AppendToList(key, value) => {
+                    operations.push(DynamicEnvOperation::AppendToList(
+                        key.clone(),
+                        value.clone(),
+                    ));
+                }
+                DynamicEnvOperation::RemoveFromList(key, value) => {
+                    operations.push(DynamicEnvOperation::RemoveFromList(
+                        key.clone(),
+                        value.clone(),
+                    ));
+                }
+                DynamicEnvOperation::RemoveFromListByFn(key, f) => {
+                    for value in f().iter() {
+                        operations.push(DynamicEnvOperation::RemoveFromList(
+                            key.clone(),
+                            value.clone(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        operations
+            .into_iter()
+            .filter(|operation| data.env.contains_key(operation.key()))
+            .collect()
+    }
+
+    fn render(&self, format: DynamicEnvExportFormat) -> String {
+        let data = self.get_env_data();
+        let operations = self.resolved_operations(&data);
+
+        match format {
+            DynamicEnvExportFormat::Dotenv => Self::render_dotenv(&data),
+            DynamicEnvExportFormat::Direnv => Self::render_direnv(&operations),
+            DynamicEnvExportFormat::Json => Self::render_json(&data, &operations),
+            DynamicEnvExportFormat::Vscode => Self::render_vscode(&data, &operations),
+        }
+    }
+
+    fn render_dotenv(data: &DynamicEnvData) -> String {
+        // The dotenv format has no notion of lists nor of unsetting
+        // variables, we thus provide the final values only
+        data.env
+            .iter()
+            .sorted_by(|a, b| a.0.cmp(b.0))
+            .filter_map(|(key, value)| {
+                value
+                    .as_ref()
+                    .map(|value| format!("{}={}\n", key, serde_json::to_string(value).unwrap()))
+            })
+            .collect()
+    }
+
+    fn render_direnv(operations: &[DynamicEnvOperation]) -> String {
+        let mut output = String::new();
+
+        for operation in operations.iter() {
+            let line = match operation {
+                DynamicEnvOperation::SetValue(key, value) => {
+                    format!(
+                        "export {}={}",
+                        key,
+                        escape(std::borrow::Cow::Borrowed(value))
+                    )
+                }
+                DynamicEnvOperation::UnsetValue(key) => format!("unset {}", key),
+                DynamicEnvOperation::PrependToList(key, value) => {
+                    let value = escape(std::borrow::Cow::Borrowed(value));
+                    if key == "PATH" {
+                        format!("PATH_add {}", value)
+                    } else {
+                        format!("path_add {} {}", key, value)
+                    }
+                }
+                DynamicEnvOperation::AppendToList(key, value) => format!(
+                    "export {}=\"${{{}:+${}:}}\"{}",
+                    key,
+                    key,
+                    key,
+                    escape(std::borrow::Cow::Borrowed(value))
+                ),
+                DynamicEnvOperation::RemoveFromList(key, value) => {
+                    let value = escape(std::borrow::Cow::Borrowed(value));
+                    if key == "PATH" {
+                        format!("PATH_rm {}", value)
+                    } else {
+                        format!("path_rm {} {}", key, value)
+                    }
+                }
+                DynamicEnvOperation::RemoveFromListByFn(_, _) => {
+                    unreachable!("operations should be resolved")
+                }
+            };
+
+            output.push_str(&line);
+            output.push('\n');
+        }
+
+        output
+    }
+
+    fn render_json(data: &DynamicEnvData, operations: &[DynamicEnvOperation]) -> String {
+        let mut env = serde_json::Map::new();
+        for (key, value) in data.env.iter() {
+            env.insert(
                 key.to_string(),
-                Box::new(f),
-            ));
+                match value {
+                    Some(value) => serde_json::Value::String(value.to_string()),
+                    None => serde_json::Value::Null,
+                },
+            );
+        }
+
+        let operations = operations
+            .iter()
+            .map(|operation| {
+                let (name, value) = match operation {
+                    DynamicEnvOperation::SetValue(_, value) => ("set", Some(value)),
+                    DynamicEnvOperation::UnsetValue(_) => ("unset", None),
+                    DynamicEnvOperation::PrependToList(_, value) => ("prepend", Some(value)),
+                    DynamicEnvOperation::AppendToList(_, value) => ("append", Some(value)),
+                    DynamicEnvOperation::RemoveFromList(_, value) => ("remove", Some(value)),
+                    DynamicEnvOperation::RemoveFromListByFn(_, _) => {
+                        unreachable!("operations should be resolved")
+                    }
+                };
+
+                let mut object = serde_json::Map::new();
+                object.insert(
+                    "operation".to_string(),
+                    serde_json::Value::String(name.to_string()),
+                );
+                object.insert(
+                    "name".to_string(),
+                    serde_json::Value::String(operation.key().to_string()),
+                );
+                if let Some(value) = value {
+                    object.insert(
+                        "value".to_string(),
+                        serde_json::Value::String(value.to_string()),
+                    );
+                }
+                serde_json::Value::Object(object)
+            })
+            .collect();
+
+        let mut object = serde_json::Map::new();
+        object.insert("env".to_string(), serde_json::Value::Object(env));
+        object.insert(
+            "operations".to_string(),
+            serde_json::Value::Array(operations),
+        );
+
+        format!(
+            "{}\n",
+            serde_json::to_string_pretty(&serde_json::Value::Object(object)).unwrap()
+        )
+    }
+
+    fn render_vscode(data: &DynamicEnvData, operations: &[DynamicEnvOperation]) -> String {
+        // VS Code resolves `${env:VAR}` to the value of the variable in its
+        // own environment, which allows to express prepending and appending
+        // to lists; removing from a list or setting a value can however only
+        // be expressed by providing the final value
+        let mut env = serde_json::Map::new();
+        for (key, value) in data.env.iter() {
+            let mut prepend = Vec::new();
+            let mut append = Vec::new();
+            let mut only_add = true;
+            for operation in operations.iter().filter(|op| op.key() == key) {
+                match operation {
+                    DynamicEnvOperation::PrependToList(_, value) => {
+                        prepend.insert(0, value.clone())
+                    }
+                    DynamicEnvOperation::AppendToList(_, value) => append.push(value.clone()),
+                    _ => only_add = false,
+                }
+            }
+
+            let value = match value {
+                Some(_) if only_add => {
+                    let mut values = prepend;
+                    if std::env::var(key).map(|v| !v.is_empty()).unwrap_or(false) {
+                        values.push(format!("${{env:{}}}", key));
+                    }
+                    values.extend(append);
+                    serde_json::Value::String(values.join(":"))
+                }
+                Some(value) => serde_json::Value::String(value.to_string()),
+                None => serde_json::Value::Null,
+            };
+            env.insert(key.to_string(), value);
+        }
+
+        format!(
+            "{}\n",
+            serde_json::to_string_pretty(&serde_json::Value::Object(env)).unwrap()
+        )
     }
 
     fn get_env_data(&self) -> DynamicEnvData {
         let mut data = DynamicEnvData::new();
 
-        for operation in self.operations.iter() {
+        for (feature, operation) in self.operations.iter() {
+            data.feature = feature.clone();
             match operation {
                 DynamicEnvOperation::SetValue(key, value) => {
                     data.set_value(key, value);
@@ -498,6 +929,8 @@ struct DynamicEnvData {
     lists: HashMap<String, Vec<DynamicEnvListValue>>,
     #[serde(skip)]
     env: HashMap<String, Option<String>>,
+    #[serde(skip)]
+    feature: Option<String>,
 }
 
 impl DynamicEnvData {
@@ -506,6 +939,7 @@ impl DynamicEnvData {
             values: HashMap::new(),
             lists: HashMap::new(),
             env: HashMap::new(),
+            feature: None,
         }
     }
 
@@ -545,10 +979,13 @@ impl DynamicEnvData {
                 DynamicEnvValue {
                     prev: prev,
                     curr: Some(value.to_string()),
+                    feature: self.feature.clone(),
                 },
             );
         } else {
-            self.values.get_mut(key).unwrap().curr = Some(value.to_string());
+            let env_value = self.values.get_mut(key).unwrap();
+            env_value.curr = Some(value.to_string());
+            env_value.feature = self.feature.clone();
         }
 
         self.env_set_var(key, value);
@@ -566,10 +1003,13 @@ impl DynamicEnvData {
                 DynamicEnvValue {
                     prev: prev,
                     curr: None,
+                    feature: self.feature.clone(),
                 },
             );
         } else {
-            self.values.get_mut(key).unwrap().curr = None;
+            let env_value = self.values.get_mut(key).unwrap();
+            env_value.curr = None;
+            env_value.feature = self.feature.clone();
         }
 
         self.env_unset_var(key);
@@ -584,6 +1024,7 @@ impl DynamicEnvData {
             operation: DynamicEnvListOperation::Add,
             value: value.to_string(),
             index: 0,
+            feature: self.feature.clone(),
         });
 
         let cur_val = self.env_get_var(key).unwrap_or("".to_string());
@@ -610,6 +1051,7 @@ impl DynamicEnvData {
             operation: DynamicEnvListOperation::Add,
             value: value.to_string(),
             index: index,
+            feature: self.feature.clone(),
         });
 
         if cur_val.is_empty() {
@@ -631,6 +1073,7 @@ impl DynamicEnvData {
                     operation: DynamicEnvListOperation::Del,
                     value: value.to_string(),
                     index: index,
+                    feature: self.feature.clone(),
                 });
 
                 prev.remove(index);
@@ -703,6 +1146,14 @@ impl DynamicEnvData {
                 self.export_fish();
                 self.export_env();
             }
+            DynamicEnvExportMode::Nu => {
+                self.export_nu();
+                self.export_env();
+            }
+            DynamicEnvExportMode::Pwsh => {
+                self.export_pwsh();
+                self.export_env();
+            }
             DynamicEnvExportMode::Env => {
                 self.export_env();
             }
@@ -763,6 +1214,47 @@ impl DynamicEnvData {
             }
         }
     }
+
+    fn export_nu(&self) {
+        // Nushell cannot evaluate code at runtime, so we provide the
+        // changes as a record on a single line, that the hook loads
+        // in the environment; `null` values are to be hidden
+        let mut record = serde_json::Map::new();
+        for (key, value) in self.env.iter() {
+            let value = match value {
+                Some(value) if key == "PATH" => serde_json::Value::Array(
+                    value
+                        .split(":")
+                        .map(|s| serde_json::Value::String(s.to_string()))
+                        .collect(),
+                ),
+                Some(value) => serde_json::Value::String(value.to_string()),
+                None => serde_json::Value::Null,
+            };
+            record.insert(key.to_string(), value);
+        }
+
+        if !record.is_empty() {
+            println!("{}", serde_json::Value::Object(record));
+        }
+    }
+
+    fn export_pwsh(&self) {
+        let export_mode = DynamicEnvExportMode::Pwsh;
+        for (key, value) in self.env.iter() {
+            match value {
+                Some(value) => {
+                    println!("$env:{} = {}", key, export_mode.quote(value));
+                }
+                None => {
+                    println!(
+                        "Remove-Item -Path Env:{} -ErrorAction SilentlyContinue",
+                        key
+                    );
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -771,6 +1263,8 @@ struct DynamicEnvValue {
     prev: Option<String>,
     #[serde(rename = "c", default = "set_none", skip_serializing_if = "is_none")]
     curr: Option<String>,
+    #[serde(rename = "f", default = "set_none", skip_serializing_if = "is_none")]
+    feature: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -789,6 +1283,8 @@ struct DynamicEnvListValue {
     value: String,
     #[serde(rename = "i")]
     index: usize,
+    #[serde(rename = "f", default = "set_none", skip_serializing_if = "is_none")]
+    feature: Option<String>,
 }
 
 impl DynamicEnvListValue {
@@ -32,10 +32,18 @@ pub fn init_hook(shell: &str) {
             current_exe,
             include_bytes!("../../shell_integration/omni.fish.tmpl"),
         ),
+        "nu" | "nushell" => dump_integration(
+            current_exe,
+            include_bytes!("../../shell_integration/omni.nu.tmpl"),
+        ),
+        "pwsh" | "powershell" => dump_integration(
+            current_exe,
+            include_bytes!("../../shell_integration/omni.pwsh.tmpl"),
+        ),
         _ => {
             omni_error!(
                 format!(
-                    "invalid shell '{}', omni only supports bash, zsh and fish",
+                    "invalid shell '{}', omni only supports bash, zsh, fish, nu and pwsh",
                     shell
                 ),
                 "hook init"
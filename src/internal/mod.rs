@@ -52,5 +52,7 @@ pub mod dynenv;
 
 pub mod hooks;
 
+pub mod shims;
+
 pub mod self_updater;
 pub use self_updater::self_update;
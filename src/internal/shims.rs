@@ -0,0 +1,188 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::exit;
+use std::process::Command as ProcessCommand;
+
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use shell_escape::escape;
+
+use crate::internal::cache::Cache;
+use crate::internal::config::up::ASDF_PATH;
+use crate::internal::dynenv::update_dynamic_env_for_command;
+use crate::internal::env::ENV;
+use crate::internal::user_interface::StringColor;
+
+lazy_static! {
+    pub static ref SHIMS_PATH: String = format!("{}/shims", ENV.data_home);
+}
+
+const SHIM_HEADER: &str = "# This shim was generated by omni, do not edit";
+
+/// Regenerate the shims for all the binaries provided by the tools
+/// installed for `omni up`-ed repositories, and remove the shims that
+/// are not provided by any of those tools anymore.
+pub fn update_shims() -> io::Result<()> {
+    let current_exe = std::env::current_exe()?;
+    let current_exe = current_exe.to_str().unwrap_or("omni").to_string();
+
+    let cache = Cache::new();
+    let mut binaries = BTreeSet::new();
+    if let Some(up_cache) = &cache.up_environments {
+        for up_env in up_cache.env.values() {
+            for toolversion in up_env.versions.iter() {
+                for bin_dir in
+                    tool_bin_dirs(&toolversion.tool, &toolversion.version, &up_env.env_vars)
+                {
+                    binaries.extend(list_executables(&bin_dir));
+                }
+            }
+        }
+    }
+
+    // We never want to shadow omni itself
+    binaries.remove("omni");
+
+    let shims_path = PathBuf::from(&*SHIMS_PATH);
+    if binaries.is_empty() && !shims_path.exists() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(&shims_path)?;
+
+    // Remove the shims that are not needed anymore
+    for entry in std::fs::read_dir(&shims_path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !binaries.contains(&name) && is_shim(&entry.path()) {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+
+    // Write the shims, only touching the files that need changes
+    for binary in binaries.iter() {
+        let shim_path = shims_path.join(binary);
+        let content = format!(
+            "#!/bin/sh\n{}\nexec {} hook shim {} \"$@\"\n",
+            SHIM_HEADER,
+            escape(std::borrow::Cow::Borrowed(&current_exe)),
+            escape(std::borrow::Cow::Borrowed(binary)),
+        );
+
+        if std::fs::read_to_string(&shim_path).ok() != Some(content.clone()) {
+            if shim_path.exists() && !is_shim(&shim_path) {
+                // Do not overwrite files that were not created by omni
+                continue;
+            }
+            std::fs::write(&shim_path, content)?;
+        }
+        std::fs::set_permissions(&shim_path, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(())
+}
+
+/// Execute the binary for which the shim was called, in the dynamic
+/// environment of the current directory.
+pub fn exec_shim(name: &str, args: &[String]) {
+    // Remove the shims directory from the PATH, so that resolving the
+    // binary does not lead us back to the shim
+    if let Some(path) = std::env::var_os("PATH") {
+        let shims_path = Path::new(&*SHIMS_PATH);
+        let paths = std::env::split_paths(&path)
+            .filter(|path| path != shims_path)
+            .collect::<Vec<_>>();
+        if let Ok(path) = std::env::join_paths(paths) {
+            std::env::set_var("PATH", path);
+        }
+    }
+
+    // Load the dynamic environment for the current directory, which
+    // will put the binaries of the expected tool versions first in
+    // the PATH
+    update_dynamic_env_for_command(".");
+
+    let err = ProcessCommand::new(name).args(args).exec();
+
+    if err.kind() == io::ErrorKind::NotFound {
+        eprintln!(
+            "{} {} {}",
+            "omni:".to_string().light_cyan(),
+            "command not found:".to_string().red(),
+            name
+        );
+        exit(127);
+    }
+
+    eprintln!(
+        "{} {} {}",
+        "omni:".to_string().light_cyan(),
+        format!("{}:", name).red(),
+        err
+    );
+    exit(126);
+}
+
+fn tool_bin_dirs(tool: &str, version: &str, env_vars: &HashMap<String, String>) -> Vec<String> {
+    let tool_prefix = format!("{}/installs/{}/{}", *ASDF_PATH, tool, version);
+
+    let mut bin_dirs = match tool {
+        "ruby" => {
+            let version_minor = version.split(".").take(2).join(".");
+            vec![
+                format!("{}/bin", tool_prefix),
+                format!("{}/lib/ruby/gems/{}/bin", tool_prefix, version_minor),
+            ]
+        }
+        "golang" => vec![format!("{}/go/bin", tool_prefix)],
+        "kotlin" => vec![format!("{}/kotlinc/bin", tool_prefix)],
+        _ => vec![format!("{}/bin", tool_prefix)],
+    };
+
+    match tool {
+        "golang" => {
+            if let Some(gobin) = env_vars.get("GOBIN") {
+                bin_dirs.push(gobin.to_string());
+            }
+        }
+        "python" => {
+            if let Some(venv) = env_vars.get("VIRTUAL_ENV") {
+                bin_dirs.push(format!("{}/bin", venv));
+            }
+        }
+        _ => {}
+    }
+
+    bin_dirs
+}
+
+fn list_executables(dir: &str) -> Vec<String> {
+    let mut executables = Vec::new();
+
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let is_executable = std::fs::metadata(entry.path())
+                .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false);
+            if !is_executable {
+                continue;
+            }
+
+            if let Some(name) = entry.file_name().to_str() {
+                executables.push(name.to_string());
+            }
+        }
+    }
+
+    executables
+}
+
+fn is_shim(path: &Path) -> bool {
+    std::fs::read_to_string(path)
+        .map(|content| content.lines().nth(1) == Some(SHIM_HEADER))
+        .unwrap_or(false)
+}
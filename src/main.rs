@@ -9,6 +9,7 @@ use internal::env::determine_shell;
 use internal::git::auto_path_update;
 use internal::hooks::init_hook;
 use internal::hooks::uuid_hook;
+use internal::shims::exec_shim;
 use internal::StringColor;
 
 fn complete_omni_subcommand(argv: &[String]) {
@@ -41,10 +42,9 @@ fn run_omni_subcommand(argv: &[String]) {
                     } else {
                         determine_shell()
                     };
-                    let export_mode = match shell_type.as_ref() {
-                        "posix" | "bash" | "zsh" => DynamicEnvExportMode::Posix,
-                        "fish" => DynamicEnvExportMode::Fish,
-                        _ => {
+                    let export_mode = match DynamicEnvExportMode::from_shell(&shell_type) {
+                        Some(export_mode) => export_mode,
+                        None => {
                             eprintln!(
                                 "{} {} {}",
                                 "omni:".to_string().light_cyan(),
@@ -61,6 +61,18 @@ fn run_omni_subcommand(argv: &[String]) {
                     uuid_hook();
                     exit(0);
                 }
+                "shim" => {
+                    if argv.len() < 3 {
+                        eprintln!(
+                            "{} {}",
+                            "omni:".to_string().light_cyan(),
+                            "no shim specified".to_string().red(),
+                        );
+                        exit(1);
+                    }
+                    exec_shim(&argv[2], &argv[3..]);
+                    exit(1);
+                }
                 "init" => {
                     let shell = if argv.len() > 2 {
                         argv[2].clone()